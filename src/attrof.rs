@@ -0,0 +1,85 @@
+use super::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+use fuse::{FileType, FileAttr};
+
+/// Serves the attributes of the node at `path` as JSON, e.g. `{"type": "attrof", "path": "/data/x"}`.
+/// File types can't reach the other nodes of the tree, so `JsonFS` resolves the target and renders
+/// its attributes on each access. Outside of it, the file reads as empty.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct AttrOfFSFileType {
+    pub path: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl AttrOfFSFileType {
+    pub fn new(path: String) -> AttrOfFSFileType {
+        AttrOfFSFileType {
+            path,
+            built: SystemTime::now()
+        }
+    }
+
+    /// Content of the file for a target with attributes `target`
+    pub fn render(target: &FileAttr) -> Vec<u8> {
+        let seconds = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let attributes = json!({
+            "ino": target.ino,
+            "size": target.size,
+            "blocks": target.blocks,
+            "kind": match target.kind {
+                FileType::Directory => "dir",
+                FileType::Symlink => "symlink",
+                _ => "file"
+            },
+            "perm": format!("{:04o}", target.perm),
+            "nlink": target.nlink,
+            "uid": target.uid,
+            "gid": target.gid,
+            "atime": seconds(target.atime),
+            "mtime": seconds(target.mtime),
+            "ctime": seconds(target.ctime)
+        });
+        format!("{}\n", attributes).into_bytes()
+    }
+
+    /// Attributes of the file when its content is `size` bytes long
+    pub fn attributes(&self, inode: u64, size: u64) -> FileAttr {
+        FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        }
+    }
+}
+
+impl FSFileTypeOps for AttrOfFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(self.attributes(inode, 0))
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn block_size(&self) -> u64 {
+        BLOCK_UNIT
+    }
+
+    fn read(&self, _offset: i64, _buffer: &mut [u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
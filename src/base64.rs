@@ -0,0 +1,113 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use fuse::{FileType, FileAttr};
+
+/// Binary content embedded in the descriptor as base64, e.g. `base64:iVBORw0KGgo=`, decoded when
+/// the tree is built
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct Base64FSFileType {
+    pub data: Vec<u8>,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl Base64FSFileType {
+    pub fn new(pointer: &str) -> Result<Base64FSFileType, DescriptorError> {
+        Ok(Base64FSFileType {
+            data: decode(pointer)?,
+            built: SystemTime::now()
+        })
+    }
+}
+
+/// Decodes padded base64 of the standard alphabet, ignoring whitespace
+pub fn decode(encoded: &str) -> Result<Vec<u8>, DescriptorError> {
+    let encoded: Vec<u8> = encoded.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let padding = encoded.iter().rev().take_while(|&&b| b == b'=').count();
+    if encoded.len() % 4 != 0 || padding > 2 {
+        return Err(DescriptorError::invalid("base64 data of invalid length"));
+    }
+
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut bits, mut bit_count) = (0u32, 0);
+    for &b in &encoded[..encoded.len() - padding] {
+        bits = (bits << 6) | sextet(b)?;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            decoded.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    Ok(decoded)
+}
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as padded base64 of the standard alphabet, the inverse of `decode`
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().fold(0u32, |bits, &b| (bits << 8) | u32::from(b)) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn sextet(b: u8) -> Result<u32, DescriptorError> {
+    let value = match b {
+        b'A'..=b'Z' => b - b'A',
+        b'a'..=b'z' => b - b'a' + 26,
+        b'0'..=b'9' => b - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return Err(DescriptorError::invalid(format!("invalid base64 character {:?}", b as char)))
+    };
+    Ok(u32::from(value))
+}
+
+impl FSFileTypeOps for Base64FSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.data.len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+
+    fn block_size(&self) -> u64 {
+        BLOCK_UNIT
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let start = min(offset as usize, self.data.len());
+        let end = min(start + buffer.len(), self.data.len());
+        buffer[..end - start].copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+}
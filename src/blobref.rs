@@ -0,0 +1,47 @@
+use super::*;
+use fuse::FileAttr;
+
+/// Serves a blob of a content-addressed store, where the blob with hash `abcdef...` is stored
+/// at `<store>/ab/cdef...`
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct BlobRefFSFileType {
+    pub store: String,
+    pub sha256: String,
+    blob: LocalFSFileType
+}
+
+impl BlobRefFSFileType {
+    pub fn new(store: String, sha256: String) -> Result<BlobRefFSFileType, DescriptorError> {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(DescriptorError::invalid(format!("{} is not a SHA-256 hex digest", sha256)));
+        }
+        let sha256 = sha256.to_ascii_lowercase();
+        let blob_path = Path::new(&store).join(&sha256[..2]).join(&sha256[2..]);
+        let blob = LocalFSFileType::new(blob_path.to_str().ok_or_else(|| DescriptorError::invalid("blob path is not valid UTF-8"))?.to_string());
+        Ok(BlobRefFSFileType { store, sha256, blob })
+    }
+
+    pub fn blob_path(&self) -> &str {
+        &self.blob.file_path
+    }
+}
+
+impl FSFileTypeOps for BlobRefFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.blob.get_attributes(inode)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.blob.read(offset, buffer)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.blob.size()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.blob.probe()
+    }
+}
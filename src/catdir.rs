@@ -0,0 +1,58 @@
+use super::*;
+use std::time::SystemTime;
+use fuse::{FileType, FileAttr};
+
+/// Serves the concatenation of the files under the directory at `path`, in `readdir` order, e.g.
+/// `{"type": "catdir", "path": "/logs"}`. Like `attrof`, the directory is resolved by `JsonFS`,
+/// which maps each read to the files it spans. Outside of it, the file reads as empty.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct CatDirFSFileType {
+    pub path: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl CatDirFSFileType {
+    pub fn new(path: String) -> CatDirFSFileType {
+        CatDirFSFileType {
+            path,
+            built: SystemTime::now()
+        }
+    }
+
+    /// Attributes of the file when the files of the directory sum up to `size` bytes
+    pub fn attributes(&self, inode: u64, size: u64) -> FileAttr {
+        FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        }
+    }
+}
+
+impl FSFileTypeOps for CatDirFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(self.attributes(inode, 0))
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn read(&self, _offset: i64, _buffer: &mut [u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::io::Read;
+use fuse::{FileType, FileAttr};
+use crate::export::FSFileReader;
+
+/// Suffix of the checksum siblings generated when `BuildOptions::checksum_siblings` is enabled
+pub const CHECKSUM_SUFFIX: &str = ".crc32";
+
+/// Length of the content of a checksum file: 8 hex digits and a newline
+const CHECKSUM_LEN: u64 = 9;
+
+/// File a checksum is computed from
+#[derive(Debug)]
+pub enum Crc32Target {
+    /// A file declared with the checksum, built for it alone
+    File(Box<FSFileType>),
+    /// The file with this name in the directory of the checksum, as generated by
+    /// `BuildOptions::checksum_siblings`. Like `ref`, it's resolved by `JsonFS`, which computes the
+    /// checksum again when the file changes. Outside of it, the checksum reads as zeros.
+    Sibling(String)
+}
+
+/// Serves the CRC32 of the content of another file type, as lowercase hex followed by a newline.
+/// The checksum of a `Crc32Target::File` is computed on first read and cached.
+#[derive(Debug)]
+pub struct Crc32FSFileType {
+    pub target: Crc32Target,
+    /// When the file was built, reported as its times
+    pub built: SystemTime,
+    checksum: RefCell<Option<Vec<u8>>>
+}
+
+impl Crc32FSFileType {
+    pub fn new(target: FSFileType) -> Crc32FSFileType {
+        Crc32FSFileType::with_target(Crc32Target::File(Box::new(target)))
+    }
+
+    /// Checksum of the file named `name` in the same directory
+    pub fn sibling(name: String) -> Crc32FSFileType {
+        Crc32FSFileType::with_target(Crc32Target::Sibling(name))
+    }
+
+    fn with_target(target: Crc32Target) -> Crc32FSFileType {
+        Crc32FSFileType {
+            target,
+            built: SystemTime::now(),
+            checksum: RefCell::new(None)
+        }
+    }
+}
+
+/// CRC32 of the content served by `reader`, as lowercase hex followed by a newline
+pub(crate) fn checksum_of<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut crc = !0u32;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        crc = crc32_update(crc, &buffer[..read]);
+    }
+    Ok(format!("{:08x}\n", !crc).into_bytes())
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+impl FSFileTypeOps for Crc32FSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = CHECKSUM_LEN;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(CHECKSUM_LEN)
+    }
+
+    fn block_size(&self) -> u64 {
+        BLOCK_UNIT
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let target = match &self.target {
+            Crc32Target::File(target) => target,
+            Crc32Target::Sibling(_) => return Ok(())
+        };
+        if self.checksum.borrow().is_none() {
+            *self.checksum.borrow_mut() = Some(checksum_of(FSFileReader::new(target.ops())?)?);
+        }
+        let checksum = self.checksum.borrow();
+        let checksum = checksum.as_ref().unwrap();
+        let start = min(offset as usize, checksum.len());
+        let end = min(start + buffer.len(), checksum.len());
+        buffer[..end - start].copy_from_slice(&checksum[start..end]);
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::process::Command;
+use fuse::{FileType, FileAttr};
+use libc::EIO;
+use log::warn;
+
+/// Serves the stdout of a shell command, e.g. `cmd:date --utc`. Getting the attributes runs the
+/// command again, reporting the size of its output, while the reads serve the output of the last
+/// run, so that the content of a file read after a `stat` matches its size. The command runs once
+/// on the first access when it didn't run yet.
+///
+/// Commands run only when enabled with `BuildOptions::allow_commands`.
+#[derive(Debug)]
+pub struct CommandFSFileType {
+    pub command: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime,
+    output: RefCell<Option<Vec<u8>>>
+}
+
+impl CommandFSFileType {
+    pub fn new(command: String) -> CommandFSFileType {
+        CommandFSFileType {
+            command,
+            built: SystemTime::now(),
+            output: RefCell::new(None)
+        }
+    }
+
+    /// Runs the command with `sh -c`, failing with `EIO` when it exits with an error
+    fn run(&self) -> io::Result<Vec<u8>> {
+        let output = Command::new("sh").arg("-c").arg(&self.command).output()?;
+        if !output.status.success() {
+            warn!("Command {} failed with {}: {}", self.command, output.status, String::from_utf8_lossy(&output.stderr).trim());
+            return Err(io::Error::from_raw_os_error(EIO));
+        }
+        *self.output.borrow_mut() = Some(output.stdout.clone());
+        Ok(output.stdout)
+    }
+
+    fn output(&self) -> io::Result<Vec<u8>> {
+        let output = self.output.borrow().clone();
+        match output {
+            Some(output) => Ok(output),
+            None => self.run()
+        }
+    }
+}
+
+impl FSFileTypeOps for CommandFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.run()?.len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.output()?.len() as u64)
+    }
+
+    fn cache_on_open(&self) -> bool {
+        true
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let output = self.output()?;
+        let start = min(offset as usize, output.len());
+        let end = min(start + buffer.len(), output.len());
+        buffer[..end - start].copy_from_slice(&output[start..end]);
+        Ok(())
+    }
+}
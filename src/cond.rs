@@ -0,0 +1,97 @@
+use super::*;
+use std::env;
+use std::fs;
+use fuse::FileAttr;
+use raw::RawFSFileType;
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub enum Condition {
+    Env(String, String),
+    File(String, String)
+}
+
+/// A file served by its `then` source only while `condition` holds. Otherwise it reads as empty.
+#[derive(Debug)]
+pub struct CondFSFileType {
+    pub condition: Condition,
+    pub then: Box<FSFileType>
+}
+
+impl Condition {
+    /// Parses predicates like `env:FEATURE_X==on` or `file:/etc/flag==on`
+    pub fn parse(predicate: &str) -> Result<Condition, DescriptorError> {
+        let separator = predicate.find("==").ok_or_else(|| DescriptorError::invalid(format!("condition {} has no ==", predicate)))?;
+        let (source, expected) = (&predicate[..separator], predicate[separator + 2..].to_string());
+
+        match source.find(':').map(|i| source.split_at(i)) {
+            Some(("env", name)) => Ok(Condition::Env(name[1..].to_string(), expected)),
+            Some(("file", path)) | Some(("local", path)) => Ok(Condition::File(path[1..].to_string(), expected)),
+            _ => Err(DescriptorError::invalid(format!("unknown condition source in {}", predicate)))
+        }
+    }
+
+    pub fn holds(&self) -> bool {
+        match self {
+            Condition::Env(name, expected) => env::var(name).map(|v| &v == expected).unwrap_or(false),
+            // Trailing newlines are ignored, since flag files usually end with one
+            Condition::File(path, expected) => fs::read_to_string(path).map(|v| v.trim_end() == expected).unwrap_or(false)
+        }
+    }
+}
+
+impl CondFSFileType {
+    pub fn new(predicate: &str, then: FSFileType) -> Result<CondFSFileType, DescriptorError> {
+        Ok(CondFSFileType {
+            condition: Condition::parse(predicate)?,
+            then: Box::new(then)
+        })
+    }
+}
+
+impl FSFileTypeOps for CondFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        if self.condition.holds() {
+            self.then.ops().get_attributes(inode)
+        } else {
+            RawFSFileType::new(String::new()).get_attributes(inode)
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        if self.condition.holds() {
+            self.then.ops().size()
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn block_size(&self) -> u64 {
+        if self.condition.holds() {
+            self.then.ops().block_size()
+        } else {
+            RawFSFileType::new(String::new()).block_size()
+        }
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        if self.condition.holds() {
+            self.then.ops().read(offset, buffer)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        if self.condition.holds() {
+            self.then.ops().probe()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.condition.holds() && self.then.ops().cache_on_open()
+    }
+}
@@ -0,0 +1,112 @@
+use super::*;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::hash::Hasher;
+use std::collections::hash_map::DefaultHasher;
+use fuse::FileAttr;
+
+/// A file type instance shared by several nodes, like the local files with identical content
+/// found by `dedup_local_files`
+#[derive(Debug)]
+pub struct SharedFSFileType {
+    pub inner: Arc<Mutex<FSFileType>>
+}
+
+impl FSFileTypeOps for SharedFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.inner.lock().unwrap().ops().get_attributes(inode)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.lock().unwrap().ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.lock().unwrap().ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.inner.lock().unwrap().ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.lock().unwrap().ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.lock().unwrap().ops().cache_on_open()
+    }
+}
+
+/// Makes the local files of the subtree in `entries` with byte-identical content share a single
+/// file type instance, reading from the first of them. The replaced nodes are updated in `inodes`.
+pub fn dedup_local_files(entries: &RefCell<Vec<Rc<FSNode>>>, inodes: &mut HashMap<u64, Weak<FSNode>>) -> Result<(), DescriptorError> {
+    let mut by_content = HashMap::new();
+    collect_local_files(entries, &mut by_content)?;
+
+    let mut shared = HashMap::new();
+    for paths in by_content.values().filter(|paths| paths.len() > 1) {
+        let instance = Arc::new(Mutex::new(FSFileType::Local(LocalFSFileType::new(paths[0].clone()))));
+        for path in paths {
+            shared.insert(path.clone(), instance.clone());
+        }
+    }
+
+    share_local_files(entries, &Weak::new(), &shared, inodes);
+    Ok(())
+}
+
+/// Groups the paths of the local files of the subtree in `entries` by length and hash of their
+/// content. Files hashing like the first of a group without matching its content are left out.
+fn collect_local_files(entries: &RefCell<Vec<Rc<FSNode>>>, by_content: &mut HashMap<(usize, u64), Vec<String>>) -> Result<(), DescriptorError> {
+    for node in entries.borrow().iter() {
+        match &node.entry {
+            FSEntry::Dir(children) => collect_local_files(children, by_content)?,
+            FSEntry::File(FSFileType::Local(local)) if local.windows.is_empty() => {
+                let content = read_content(&local.file_path)?;
+                let mut hasher = DefaultHasher::new();
+                hasher.write(&content);
+
+                let paths = by_content.entry((content.len(), hasher.finish())).or_insert_with(Vec::new);
+                match paths.first() {
+                    Some(first) if read_content(first)? != content =>
+                        warn!("{} hashes like {} with a different content, not sharing it", local.file_path, first),
+                    _ => paths.push(local.file_path.clone())
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_content(path: &str) -> Result<Vec<u8>, DescriptorError> {
+    fs::read(path).map_err(|e| {
+        warn!("Cannot read {} to deduplicate it: {}", path, e);
+        DescriptorError::invalid(format!("cannot read {}: {}", path, e))
+    })
+}
+
+/// Replaces the nodes of the local files found in `shared` with nodes of the shared instance
+fn share_local_files(entries: &RefCell<Vec<Rc<FSNode>>>, parent: &Weak<FSNode>, shared: &HashMap<String, Arc<Mutex<FSFileType>>>, inodes: &mut HashMap<u64, Weak<FSNode>>) {
+    for node in entries.borrow_mut().iter_mut() {
+        let instance = match &node.entry {
+            FSEntry::Dir(children) => {
+                share_local_files(children, &Rc::downgrade(node), shared, inodes);
+                None
+            },
+            FSEntry::File(FSFileType::Local(local)) => shared.get(&local.file_path).cloned(),
+            _ => None
+        };
+        if let Some(inner) = instance {
+            let name = node.name.borrow().clone();
+            *node = Rc::new(FSNode {
+                inode: node.inode,
+                name: RefCell::new(name),
+                parent: RefCell::new(parent.clone()),
+                entry: FSEntry::File(FSFileType::Shared(SharedFSFileType { inner }))
+            });
+            inodes.insert(node.inode, Rc::downgrade(node));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::*;
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use fuse::FileAttr;
+
+/// How often a delay checks whether it was interrupted
+const DELAY_SLICE: Duration = Duration::from_millis(10);
+
+/// Sleeps for `delay` before each read of `inner`, emulating slow storage. Delays are cut short
+/// once `shutdown` is set, so they don't hold up unmounting.
+#[derive(Debug)]
+pub struct DelayFSFileType {
+    pub inner: Box<FSFileType>,
+    pub delay: Duration,
+    shutdown: Arc<AtomicBool>
+}
+
+impl DelayFSFileType {
+    pub fn new(inner: FSFileType, delay: Duration, shutdown: Arc<AtomicBool>) -> DelayFSFileType {
+        DelayFSFileType {
+            inner: Box::new(inner),
+            delay,
+            shutdown
+        }
+    }
+
+    fn sleep(&self) {
+        let deadline = Instant::now() + self.delay;
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::sleep(min(DELAY_SLICE, deadline - now));
+        }
+    }
+}
+
+impl FSFileTypeOps for DelayFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.inner.ops().get_attributes(inode)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.sleep();
+        self.inner.ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.ops().cache_on_open()
+    }
+}
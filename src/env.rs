@@ -0,0 +1,63 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::os::unix::ffi::OsStringExt;
+use fuse::{FileType, FileAttr};
+
+/// Serves the value of an environment variable of the process, e.g. `env:HOME`, empty when the
+/// variable isn't set. The value is looked up again on each access.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct EnvFSFileType {
+    pub name: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl EnvFSFileType {
+    pub fn new(name: String) -> Result<EnvFSFileType, DescriptorError> {
+        if name.is_empty() || name.contains('=') {
+            return Err(DescriptorError::invalid(format!("invalid environment variable name {}", name)));
+        }
+        Ok(EnvFSFileType { name, built: SystemTime::now() })
+    }
+
+    fn value(&self) -> Vec<u8> {
+        std::env::var_os(&self.name).map(|value| value.into_vec()).unwrap_or_default()
+    }
+}
+
+impl FSFileTypeOps for EnvFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.value().len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.value().len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let value = self.value();
+        let start = min(offset as usize, value.len());
+        let end = min(start + buffer.len(), value.len());
+        buffer[..end - start].copy_from_slice(&value[start..end]);
+        Ok(())
+    }
+}
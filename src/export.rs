@@ -0,0 +1,185 @@
+use super::*;
+use std::cmp::min;
+use libc::EISDIR;
+use crate::checksum::{Crc32FSFileType, Crc32Target};
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, Read, Write};
+use std::os::unix::fs::symlink;
+use std::time::UNIX_EPOCH;
+use tar::{Builder, EntryType, Header};
+
+/// Size of the buffer used to stream each file while exporting
+pub const EXPORT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Adapts a file type to `io::Read`, reading its content sequentially from offset 0
+pub struct FSFileReader<'a> {
+    file_type: &'a dyn FSFileTypeOps,
+    size: u64,
+    offset: u64
+}
+
+impl<'a> FSFileReader<'a> {
+    pub fn new(file_type: &'a dyn FSFileTypeOps) -> io::Result<FSFileReader<'a>> {
+        Ok(FSFileReader {
+            file_type,
+            size: file_type.size()?,
+            offset: 0
+        })
+    }
+
+    /// Number of bytes the reader serves
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<'a> Read for FSFileReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = min(buf.len() as u64, self.size - self.offset) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        // File types may fill less than asked: the rest reads as zeros rather than stale bytes
+        for byte in buf[..len].iter_mut() {
+            *byte = 0;
+        }
+        self.file_type.read(self.offset as i64, &mut buf[..len])?;
+        self.offset += len as u64;
+        Ok(len)
+    }
+}
+
+/// Where the exported files are read from
+pub trait ExportSource {
+    fn get_attributes(&self, node: &FSNode) -> io::Result<FileAttr>;
+    fn read(&self, node: &FSNode, offset: i64, buffer: &mut [u8]) -> io::Result<()>;
+}
+
+/// Reads the files through their file type alone. The `ref`, `catdir`, `attrof` and checksum sibling
+/// files take their content from other nodes, resolved by `JsonFS`, so exporting them fails: use
+/// `JsonFS` as source.
+pub struct FileTypes;
+
+impl FileTypes {
+    fn ops<'a>(&self, node: &'a FSNode) -> io::Result<&'a dyn FSFileTypeOps> {
+        match &node.entry {
+            FSEntry::File(FSFileType::Ref(_)) | FSEntry::File(FSFileType::CatDir(_)) | FSEntry::File(FSFileType::AttrOf(_)) |
+            FSEntry::File(FSFileType::Crc32(Crc32FSFileType { target: Crc32Target::Sibling(_), .. })) =>
+                Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} can only be exported from a JsonFS", node.full_path()))),
+            FSEntry::File(file_type) => Ok(file_type.ops()),
+            FSEntry::Dir(_) => Err(io::Error::from_raw_os_error(EISDIR))
+        }
+    }
+}
+
+impl ExportSource for FileTypes {
+    fn get_attributes(&self, node: &FSNode) -> io::Result<FileAttr> {
+        self.ops(node)?.get_attributes(node.inode)
+    }
+
+    fn read(&self, node: &FSNode, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.ops(node)?.read(offset, buffer)
+    }
+}
+
+/// Adapts a node of `source` to `FSFileTypeOps`, to read it with `FSFileReader`
+pub(crate) struct SourceNode<'a> {
+    pub(crate) source: &'a dyn ExportSource,
+    pub(crate) node: &'a FSNode
+}
+
+impl<'a> FSFileTypeOps for SourceNode<'a> {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(FileAttr { ino: inode, ..self.source.get_attributes(self.node)? })
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.source.read(self.node, offset, buffer)
+    }
+}
+
+/// Streams the content of `file_type` into `writer`, never buffering more than `EXPORT_BUFFER_SIZE` bytes
+pub fn export_file<W: Write>(file_type: &dyn FSFileTypeOps, writer: &mut W) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(EXPORT_BUFFER_SIZE, FSFileReader::new(file_type)?);
+    io::copy(&mut reader, writer)
+}
+
+/// Writes the tree rooted in `node` to `destination` on the local file system, reading the files
+/// through their file type, see `FileTypes`
+pub fn export_dir(node: &FSNode, destination: &Path) -> io::Result<()> {
+    export_dir_with(&FileTypes, node, destination)
+}
+
+/// Writes the tree rooted in `node` to `destination` on the local file system, reading the files
+/// from `source`
+pub fn export_dir_with(source: &dyn ExportSource, node: &FSNode, destination: &Path) -> io::Result<()> {
+    match &node.entry {
+        FSEntry::Dir(entries) => {
+            create_dir_all(destination)?;
+            for entry in entries.borrow().iter() {
+                export_dir_with(source, entry, &destination.join(&*entry.name.borrow()))?;
+            }
+            Ok(())
+        },
+        FSEntry::File(FSFileType::Symlink(link)) => symlink(&link.target, destination),
+        FSEntry::File(_) => {
+            let mut file = File::create(destination)?;
+            export_file(&SourceNode { source, node }, &mut file)?;
+            Ok(())
+        }
+    }
+}
+
+/// Streams the tree rooted in `node` as a tar archive into `writer`. Files are appended one at a
+/// time, never buffering more than `EXPORT_BUFFER_SIZE` bytes of each, and read through their file
+/// type, see `FileTypes`.
+pub fn export_tar<W: Write>(node: &FSNode, writer: W) -> io::Result<W> {
+    export_tar_with(&FileTypes, node, writer)
+}
+
+/// Streams the tree rooted in `node` as a tar archive into `writer`, like `export_tar`, reading the
+/// files from `source`
+pub fn export_tar_with<W: Write>(source: &dyn ExportSource, node: &FSNode, writer: W) -> io::Result<W> {
+    let mut builder = Builder::new(writer);
+    append_tar_entry(&mut builder, source, node, Path::new(""))?;
+    builder.into_inner()
+}
+
+fn append_tar_entry<W: Write>(builder: &mut Builder<W>, source: &dyn ExportSource, node: &FSNode, path: &Path) -> io::Result<()> {
+    match &node.entry {
+        FSEntry::Dir(entries) => {
+            if path != Path::new("") {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Directory);
+                header.set_size(0);
+                header.set_mode(0o755);
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            for entry in entries.borrow().iter() {
+                append_tar_entry(builder, source, entry, &path.join(&*entry.name.borrow()))?;
+            }
+            Ok(())
+        },
+        FSEntry::File(FSFileType::Symlink(link)) => {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Symlink);
+            header.set_size(0);
+            header.set_mode(0o777);
+            header.set_link_name(&link.target)?;
+            builder.append_data(&mut header, path, io::empty())
+        },
+        FSEntry::File(_) => {
+            let file = SourceNode { source, node };
+            let attr = file.get_attributes(node.inode)?;
+            let reader = FSFileReader::new(&file)?;
+
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Regular);
+            header.set_size(reader.size());
+            header.set_mode(u32::from(attr.perm));
+            header.set_mtime(attr.mtime.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+            builder.append_data(&mut header, path, BufReader::with_capacity(EXPORT_BUFFER_SIZE, reader))
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use super::*;
+use std::cell::Cell;
+use fuse::FileAttr;
+use libc::{EACCES, EAGAIN, EBADF, EINTR, EIO, ENOENT, ENOSPC, EPERM, ETIMEDOUT};
+
+/// Fails a fraction of the reads of `inner` with `errno`, to test how clients handle errors.
+/// Faults are spread deterministically: with a rate of 0.5 every second read fails.
+#[derive(Debug)]
+pub struct FaultFSFileType {
+    pub inner: Box<FSFileType>,
+    pub errno: i32,
+    pub rate: f64,
+    accumulated: Cell<f64>
+}
+
+impl FaultFSFileType {
+    pub fn new(inner: FSFileType, errno: i32, rate: f64) -> Result<FaultFSFileType, DescriptorError> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(DescriptorError::invalid(format!("fault rate {} is not between 0 and 1", rate)));
+        }
+        Ok(FaultFSFileType {
+            inner: Box::new(inner),
+            errno,
+            rate,
+            accumulated: Cell::new(0.0)
+        })
+    }
+
+    /// Parses the `fault` object of a file descriptor, e.g. `{"read": "EIO", "rate": 0.5}`
+    pub fn parse(inner: FSFileType, fault: &serde_json::Value) -> Result<FaultFSFileType, DescriptorError> {
+        let errno = match fault.get("read") {
+            Some(serde_json::Value::String(name)) => parse_errno(name)?,
+            Some(serde_json::Value::Number(n)) => n.as_i64().ok_or_else(|| DescriptorError::invalid("fault errno is not an integer"))? as i32,
+            _ => return Err(DescriptorError::invalid("fault without a read errno"))
+        };
+        let rate = match fault.get("rate") {
+            Some(rate) => rate.as_f64().ok_or_else(|| DescriptorError::invalid("fault rate is not a number"))?,
+            None => 1.0
+        };
+        FaultFSFileType::new(inner, errno, rate)
+    }
+
+    fn should_fail(&self) -> bool {
+        let accumulated = self.accumulated.get() + self.rate;
+        if accumulated >= 1.0 {
+            self.accumulated.set(accumulated - 1.0);
+            true
+        } else {
+            self.accumulated.set(accumulated);
+            false
+        }
+    }
+}
+
+fn parse_errno(name: &str) -> Result<i32, DescriptorError> {
+    match name {
+        "EACCES" => Ok(EACCES),
+        "EAGAIN" => Ok(EAGAIN),
+        "EBADF" => Ok(EBADF),
+        "EINTR" => Ok(EINTR),
+        "EIO" => Ok(EIO),
+        "ENOENT" => Ok(ENOENT),
+        "ENOSPC" => Ok(ENOSPC),
+        "EPERM" => Ok(EPERM),
+        "ETIMEDOUT" => Ok(ETIMEDOUT),
+        _ => Err(DescriptorError::invalid(format!("unknown errno {}", name)))
+    }
+}
+
+impl FSFileTypeOps for FaultFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.inner.ops().get_attributes(inode)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        if self.should_fail() {
+            return Err(io::Error::from_raw_os_error(self.errno));
+        }
+        self.inner.ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.ops().cache_on_open()
+    }
+}
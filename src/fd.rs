@@ -0,0 +1,79 @@
+use super::*;
+
+use nix::sys::stat::{FileStat, fstat};
+use nix::sys::uio::pread;
+use fuse::{FileType, FileAttr};
+use std::time::{SystemTime, Duration};
+use std::os::unix::io::RawFd;
+use libc::EIO;
+
+/// Serves the content of a file descriptor inherited by the process, e.g. `fd:3`.
+/// Reads use `pread`, so they don't move the offset shared with other readers of the descriptor.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct FdFSFileType {
+    pub fd: RawFd
+}
+
+impl FdFSFileType {
+    pub fn new(pointer: String) -> Result<FdFSFileType, DescriptorError> {
+        match pointer.parse::<RawFd>() {
+            Ok(fd) if fd >= 0 => Ok(FdFSFileType { fd }),
+            _ => Err(DescriptorError::invalid(format!("{} is not a file descriptor", pointer)))
+        }
+    }
+}
+
+fn to_io_error(error: nix::Error) -> io::Error {
+    io::Error::from_raw_os_error(error.as_errno().map(|errno| errno as i32).unwrap_or(EIO))
+}
+
+macro_rules! stat_time_to_SystemTime {
+    ($msec:expr, $nsec:expr) => { SystemTime::UNIX_EPOCH + Duration::new($msec as u64, $nsec as u32) };
+}
+
+impl FSFileTypeOps for FdFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let stat: FileStat = fstat(self.fd).map_err(to_io_error)?;
+        Ok(FileAttr {
+            ino: inode,
+            size: stat.st_size as u64,
+            blocks: stat.st_blocks as u64,
+            atime: stat_time_to_SystemTime!(stat.st_atime, stat.st_atime_nsec),
+            mtime: stat_time_to_SystemTime!(stat.st_mtime, stat.st_mtime_nsec),
+            ctime: stat_time_to_SystemTime!(stat.st_ctime, stat.st_ctime_nsec),
+            crtime: stat_time_to_SystemTime!(stat.st_ctime, stat.st_ctime_nsec),
+            kind: FileType::RegularFile,
+            perm: (stat.st_mode & 0o7777) as u16,
+            nlink: stat.st_nlink as u32,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        // pread may return fewer bytes than available, so read until the buffer is filled or the
+        // end of the file
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match pread(self.fd, &mut buffer[filled..], offset + filled as i64).map_err(to_io_error) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        fstat(self.fd).map(|stat| stat.st_size as u64).map_err(to_io_error)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        fstat(self.fd).map(|_| ()).map_err(to_io_error)
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::cmp::min;
+use fuse::FileAttr;
+use libc::EIO;
+use crate::export::FSFileReader;
+
+/// Serves the content of `inner` piped through a shell command, e.g. `gzip -dc`. The command runs
+/// on first access and its output is cached, so reads at any offset are served from memory.
+#[derive(Debug)]
+pub struct FilterFSFileType {
+    pub inner: Box<FSFileType>,
+    pub command: String,
+    output: RefCell<Option<Vec<u8>>>
+}
+
+impl FilterFSFileType {
+    pub fn new(inner: FSFileType, command: String) -> FilterFSFileType {
+        FilterFSFileType {
+            inner: Box::new(inner),
+            command,
+            output: RefCell::new(None)
+        }
+    }
+
+    fn run(&self) -> io::Result<Vec<u8>> {
+        let mut input = vec![];
+        FSFileReader::new(self.inner.ops())?.read_to_end(&mut input)?;
+        run_filter(&self.command, input)
+    }
+
+    fn with_output<T>(&self, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if self.output.borrow().is_none() {
+            *self.output.borrow_mut() = Some(self.run()?);
+        }
+        Ok(f(self.output.borrow().as_ref().unwrap()))
+    }
+}
+
+/// Pipes `input` through the shell `command`, returning its output. A command exiting with a
+/// failure status fails with `EIO`.
+pub fn run_filter(command: &str, input: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Fed from another thread, as the command may not read its input before filling stdout
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    // A command not consuming all of its input makes the feeder fail with a broken pipe
+    let _ = feeder.join();
+
+    if !output.status.success() {
+        warn!("Filter {} failed: {}", command, output.status);
+        return Err(io::Error::from_raw_os_error(EIO));
+    }
+    Ok(output.stdout)
+}
+
+impl FSFileTypeOps for FilterFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            size,
+            blocks: blocks(size, self.block_size()),
+            ..self.inner.ops().get_attributes(inode)?
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_output(|output| output.len() as u64)
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        true
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.with_output(|output| {
+            let start = min(offset as usize, output.len());
+            let end = min(start + buffer.len(), output.len());
+            buffer[..end - start].copy_from_slice(&output[start..end]);
+        })
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+}
@@ -1,33 +1,991 @@
-use fuse::{FileType, FileAttr, Filesystem, Request, ReplyData, ReplyEntry, ReplyAttr, ReplyDirectory};
+use fuse::{FileType, FileAttr, Filesystem, Request, ReplyData, ReplyEntry, ReplyAttr, ReplyDirectory, ReplyStatfs, ReplyXattr, ReplyWrite, ReplyEmpty, ReplyOpen, ReplyCreate};
 use super::*;
-use std::time::{Duration, SystemTime};
-use libc::ENOENT;
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use libc::{ENOENT, EIO, EISDIR, ENOTDIR, ESTALE, ENODATA, ERANGE, EINVAL, ELOOP, EROFS, EACCES, EEXIST, EPERM, ENAMETOOLONG, ENOTEMPTY, EFBIG, W_OK, X_OK};
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::cmp::min;
 use std::rc::{Rc, Weak};
 use std::borrow::Borrow;
-use json_fuse_fs::Flatten;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf, Component};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::sync::atomic::Ordering;
+use std::io::Read;
+use crate::log_buffer::{LogRingBuffer, LogFSFileType};
+use crate::export::{ExportSource, FSFileReader, SourceNode};
+use crate::checksum::{checksum_of, Crc32FSFileType, Crc32Target};
+use crate::sha256;
+use crate::xattr::{MIME_TYPE_XATTR, mime_type_with_charset, parse_xattrs};
+use crate::attrof::AttrOfFSFileType;
+use crate::catdir::CatDirFSFileType;
+use crate::reference::RefFSFileType;
+use log::{debug, info, warn};
+use glob::Pattern;
+use serde_json::json;
 
-const TTL: Duration = Duration::from_secs(1);
+/// How long the kernel may cache the attributes of the nodes when not configured
+pub const DEFAULT_TTL: Duration = Duration::from_secs(1);
+
+/// Name of the generated file, in the root directory, exposing the descriptor the tree was built from
+pub const DESCRIPTOR_FILE_NAME: &str = ".descriptor";
+
+/// Name of the generated file, in the root directory, exposing the SHA-256 of the descriptor the
+/// tree was built from
+pub const VERSION_FILE_NAME: &str = ".version";
+
+/// Name of the generated file, in the root directory, exposing the most recent log lines
+pub const LOG_FILE_NAME: &str = ".log";
+
+/// Name of the generated directory, in the root directory, describing the nodes by inode
+pub const INODES_DIR_NAME: &str = ".inodes";
+
+/// Mode of the directories when not configured
+pub const DEFAULT_DIR_MODE: u16 = 0o755;
+
+/// Maximum file name length reported by `statfs` when not configured
+pub const DEFAULT_NAMELEN: u32 = 255;
+
+/// Charset appended to the computed MIME type of the text files
+pub const DEFAULT_CHARSET: &str = "utf-8";
+
+/// Block size reported by `statfs`
+const STATFS_BLOCK_SIZE: u32 = 512;
+
+/// File system statistics, as reported by `statfs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statfs {
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub bsize: u32,
+    pub namelen: u32,
+    pub frsize: u32
+}
+
+/// Options passed to `fuse::mount`. With `nonempty`, mounting over a non-empty directory is
+/// allowed, hiding its content until unmount.
+pub fn mount_options(nonempty: bool) -> Vec<&'static OsStr> {
+    let mut options = vec![OsStr::new("-o"), OsStr::new("ro")];
+    options.extend(writable_mount_options(nonempty));
+    options
+}
+
+/// Mount options of a file system built with `JsonFS::new_writable`: the ones of `mount_options` without `ro`
+pub fn writable_mount_options(nonempty: bool) -> Vec<&'static OsStr> {
+    let mut options = vec!["-o", "fsname=jsonfs"];
+    if nonempty {
+        options.extend(&["-o", "nonempty"]);
+    }
+    options.iter().map(|o| OsStr::new(*o)).collect()
+}
+
+/// Checks `mountpoint` exists and is a directory
+pub fn validate_mountpoint(mountpoint: &Path) -> io::Result<()> {
+    if std::fs::metadata(mountpoint)?.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(ENOTDIR))
+    }
+}
+
+/// A read served by `JsonFS`, as passed to the callbacks registered with `JsonFS::on_read`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadEvent {
+    pub inode: u64,
+    pub offset: i64,
+    /// Number of bytes requested
+    pub size: u32,
+    /// Number of bytes served, 0 when the read failed
+    pub bytes: usize,
+    pub duration: Duration,
+    /// Errno replied, when the read failed
+    pub error: Option<i32>
+}
+
+/// A file opened with `JsonFS::open_inode`, by handle
+#[derive(Debug)]
+struct OpenFile {
+    inode: u64,
+    /// Whole content of the file when opened, unless too large. Dropped when the file is written to.
+    content: Option<Vec<u8>>,
+    /// Size of the file when opened, bounding the reads not served from `content` so that they
+    /// don't look it up on each read. Dropped with `content`.
+    size: Option<u64>
+}
+
+/// Size of the reads materializing the content of an opened file
+const OPEN_READ_SIZE: u32 = 1 << 20;
+
+/// Size of the largest file whose content is materialized when opened, the handles of the larger
+/// ones reading from the file
+const MAX_OPEN_CONTENT: u64 = 64 << 20;
+
+/// Size the raw files can grow to in a writable file system, the content being kept in memory
+const MAX_WRITTEN_SIZE: u64 = MAX_OPEN_CONTENT;
+
+/// What to do with the reads larger than the maximum read size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversizedRead {
+    /// Fail them with `EINVAL`
+    Reject,
+    /// Serve the first bytes up to the maximum read size
+    Clamp
+}
+
+/// Order of the entries served by `readdir`, after `.` and `..`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReaddirSort {
+    /// The order of the descriptor
+    Source,
+    Name,
+    NameDesc,
+    /// Smallest first
+    Size,
+    /// Oldest first
+    Mtime
+}
+
+impl ReaddirSort {
+    /// Parses a policy name: `source`, `name`, `name_desc`, `size` or `mtime`
+    pub fn parse(policy: &str) -> Option<ReaddirSort> {
+        match policy {
+            "source" => Some(ReaddirSort::Source),
+            "name" => Some(ReaddirSort::Name),
+            "name_desc" => Some(ReaddirSort::NameDesc),
+            "size" => Some(ReaddirSort::Size),
+            "mtime" => Some(ReaddirSort::Mtime),
+            _ => None
+        }
+    }
+}
 
 pub struct JsonFS {
+    descriptor: serde_json::Value,
     fs_tree_root: Rc<FSNode>,
     inode: HashMap<u64, Weak<FSNode>>,
-    dir_listing: HashMap<u64, Vec<(u64, FileType, OsString)>>
+    dir_listing: HashMap<u64, Vec<(u64, FileType, OsString)>>,
+    permission_policy: Vec<(Pattern, u16)>,
+    default_dir_mode: u16,
+    dir_permission_policy: Vec<(Pattern, u16)>,
+    options: BuildOptions,
+    /// Options the file system was built with, before the directives of the descriptor
+    build_options: BuildOptions,
+    last_inode: u64,
+    namelen: u32,
+    prune_after: Option<u32>,
+    failures: HashMap<u64, u32>,
+    recursive_dir_size: bool,
+    dir_sizes: RefCell<HashMap<u64, u64>>,
+    /// Checksums of the checksum siblings by inode, with the inode, size and mtime of the file they
+    /// were computed from
+    sibling_checksums: RefCell<HashMap<u64, ((u64, u64, SystemTime), Vec<u8>)>>,
+    read_callbacks: Vec<Box<dyn Fn(ReadEvent)>>,
+    /// Sources of the lazy directories whose children weren't built yet, by inode
+    lazy_dirs: HashMap<u64, String>,
+    /// Extended attributes declared in the descriptor, by inode
+    xattrs: HashMap<u64, HashMap<String, Vec<u8>>>,
+    /// Additional names of the files declared with `also`, by inode of the directory they appear in
+    aliases: HashMap<u64, Vec<(String, Rc<FSNode>)>>,
+    /// Attribute TTL of the nodes without a `ttl_ms`
+    ttl: Duration,
+    /// Attribute TTLs declared in the descriptor with `ttl_ms`, by inode
+    ttls: HashMap<u64, Duration>,
+    /// Inode of the `.inodes` directory, when enabled
+    inodes_dir: Option<u64>,
+    /// Files of the `.inodes` directory generated so far, by inode of the node they describe
+    inode_infos: HashMap<u64, Rc<FSNode>>,
+    max_read: Option<(u32, OversizedRead)>,
+    readdir_sort: ReaddirSort,
+    /// Whether the raw files accept writes, see `JsonFS::new_writable`
+    writable: bool,
+    /// Where the descriptor is written back on unmount
+    persist_to: Option<PathBuf>,
+    /// Charset of the text files, reported in their `user.mime_type`
+    charset: Option<String>,
+    /// Owner reported for all the nodes, rather than the one of the process or the backing file
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// Files opened with `open_inode`, by handle
+    open_files: HashMap<u64, OpenFile>,
+    last_fh: u64,
+    /// Descriptors to swap in, see `JsonFS::with_reloads`
+    reloads: Option<Receiver<serde_json::Value>>
 }
 
 impl JsonFS {
-    pub fn new(fs_tree_root: Rc<FSNode>, inode: HashMap<u64, Weak<FSNode>>) -> JsonFS {
-        let dir_listing = JsonFS::generate_dir_listing(fs_tree_root.flatten());
-        info!("Inode map: {:?}", inode);
-        JsonFS { fs_tree_root, inode, dir_listing }
+    pub fn new(descriptor: serde_json::Value) -> Result<JsonFS, DescriptorError> {
+        JsonFS::new_with_options(descriptor, &BuildOptions::default())
+    }
+
+    pub fn new_with_options(descriptor: serde_json::Value, options: &BuildOptions) -> Result<JsonFS, DescriptorError> {
+        let build_options = options.clone();
+        let (tree_descriptor, options) = options.with_directives(JsonFS::with_generated_files(&descriptor))?;
+        let (fs_tree_root, inode) = FSNode::new_with_options(tree_descriptor, &options)?;
+        debug!("Parsed FS Tree: {:?}", fs_tree_root);
+
+        let dir_listing = JsonFS::generate_dir_listing(fs_tree_root.flatten_strong());
+        debug!("Inode map: {:?}", inode);
+        let last_inode = inode.keys().max().cloned().unwrap_or(0);
+        let mut fs = JsonFS {
+            descriptor,
+            fs_tree_root,
+            inode,
+            dir_listing,
+            permission_policy: vec![],
+            default_dir_mode: DEFAULT_DIR_MODE,
+            dir_permission_policy: vec![],
+            options,
+            build_options,
+            last_inode,
+            namelen: DEFAULT_NAMELEN,
+            prune_after: None,
+            failures: HashMap::new(),
+            recursive_dir_size: false,
+            dir_sizes: RefCell::new(HashMap::new()),
+            sibling_checksums: RefCell::new(HashMap::new()),
+            read_callbacks: vec![],
+            lazy_dirs: HashMap::new(),
+            xattrs: HashMap::new(),
+            aliases: HashMap::new(),
+            ttl: DEFAULT_TTL,
+            ttls: HashMap::new(),
+            inodes_dir: None,
+            inode_infos: HashMap::new(),
+            max_read: None,
+            readdir_sort: ReaddirSort::Source,
+            writable: false,
+            persist_to: None,
+            charset: Some(DEFAULT_CHARSET.to_string()),
+            uid: None,
+            gid: None,
+            open_files: HashMap::new(),
+            last_fh: 0,
+            reloads: None
+        };
+        let (root, descriptor) = (fs.fs_tree_root.clone(), fs.descriptor.clone());
+        fs.index_descriptor(&root, &descriptor);
+        JsonFS::check_refs(&fs.fs_tree_root)?;
+        Ok(fs)
+    }
+
+    /// Builds a file system whose raw files accept writes, changing their content in memory. The
+    /// other files stay read-only.
+    pub fn new_writable(descriptor: serde_json::Value, options: &BuildOptions) -> Result<JsonFS, DescriptorError> {
+        let mut fs = JsonFS::new_with_options(descriptor, options)?;
+        fs.writable = true;
+        Ok(fs)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Writes the descriptor back to `path` on unmount, keeping the content written to the raw files
+    pub fn with_persist_to(mut self, path: PathBuf) -> JsonFS {
+        self.persist_to = Some(path);
+        self
+    }
+
+    /// Overrides the mode of the files whose full path matches a glob. When more than one
+    /// pattern matches, the last one wins.
+    pub fn with_permission_policy(mut self, permission_policy: Vec<(Pattern, u16)>) -> JsonFS {
+        self.permission_policy = permission_policy;
+        self
+    }
+
+    /// Sets the mode of the directories not matched by the directory permission policy
+    pub fn with_default_dir_mode(mut self, mode: u16) -> JsonFS {
+        self.default_dir_mode = mode;
+        self
+    }
+
+    /// Overrides the mode of the directories whose full path matches a glob. When more than one
+    /// pattern matches, the last one wins.
+    pub fn with_dir_permission_policy(mut self, dir_permission_policy: Vec<(Pattern, u16)>) -> JsonFS {
+        self.dir_permission_policy = dir_permission_policy;
+        self
+    }
+
+    /// Makes directories report the total size of their descendant files. Sizes are computed on
+    /// first access and cached until the tree changes.
+    pub fn with_recursive_dir_size(mut self, recursive_dir_size: bool) -> JsonFS {
+        self.recursive_dir_size = recursive_dir_size;
+        self
+    }
+
+    /// Registers a callback invoked after each read, once its reply is computed
+    pub fn on_read(&mut self, cb: Box<dyn Fn(ReadEvent)>) {
+        self.read_callbacks.push(cb);
+    }
+
+    /// Adds a `.log` file to the root directory, serving the content of `buffer`
+    pub fn with_log(mut self, buffer: Arc<LogRingBuffer>) -> JsonFS {
+        let log = FSEntry::File(FSFileType::Log(LogFSFileType::new(buffer)));
+        self.add_generated_node(LOG_FILE_NAME, log);
+        self
     }
 
-    fn generate_dir_listing(nodes: Vec<Weak<FSNode>>) -> HashMap<u64, Vec<(u64, FileType, OsString)>> {
+    /// Swaps in the descriptors received from `reloads` with `reload`, checking for them before
+    /// serving each request
+    pub fn with_reloads(mut self, reloads: Receiver<serde_json::Value>) -> JsonFS {
+        self.reloads = Some(reloads);
+        self
+    }
+
+    /// Adds a `.inodes` directory to the root directory, where `.inodes/<n>` is a file describing
+    /// the node with inode `n`. The files are generated on lookup, so the directory lists empty.
+    pub fn with_inodes_dir(mut self) -> JsonFS {
+        let dir = self.add_generated_node(INODES_DIR_NAME, FSEntry::Dir(RefCell::new(vec![])));
+        self.inodes_dir = Some(dir.inode);
+        self
+    }
+
+    /// Adds a node generated by the file system to the root directory, shadowing the descriptor
+    /// entry with the same name
+    fn add_generated_node(&mut self, name: &str, entry: FSEntry) -> Rc<FSNode> {
+        let root = self.fs_tree_root.clone();
+        self.last_inode += 1;
+        let node = Rc::new(FSNode {
+            inode: self.last_inode,
+            name: RefCell::new(name.to_string()),
+            parent: RefCell::new(Rc::downgrade(&root)),
+            entry
+        });
+        if let FSEntry::Dir(entries) = &root.entry {
+            let mut entries = entries.borrow_mut();
+            if let Some(position) = entries.iter().position(|e| *e.name.borrow() == name) {
+                warn!("The descriptor entry {} is shadowed by the generated one", name);
+                let shadowed = entries.remove(position);
+                for node in shadowed.flatten_strong() {
+                    self.inode.remove(&node.inode);
+                    self.dir_listing.remove(&node.inode);
+                }
+            }
+            entries.push(node.clone());
+        }
+        self.inode.insert(node.inode, Rc::downgrade(&node));
+        self.update_dir_listing(vec![root, node.clone()]);
+        self.dir_sizes.borrow_mut().clear();
+        node
+    }
+
+    /// File of `.inodes` describing the node with the inode `name`, generated on first lookup
+    fn lookup_inode_info(&mut self, name: &OsStr) -> io::Result<FileAttr> {
+        let inode = name
+            .to_str()
+            .and_then(|n| n.parse::<u64>().ok())
+            .ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+
+        if !self.inode_infos.contains_key(&inode) {
+            let entry = match &node.entry {
+                FSEntry::Dir(entries) => format!("directory of {} entries", entries.borrow().len()),
+                FSEntry::File(file_type) => format!("{:?}", file_type)
+            };
+            let info = format!("inode: {}\npath: {}\nentry: {}\n", node.inode, node.full_path(), entry);
+
+            let dir = self.inodes_dir.and_then(|dir| self.inode.get(&dir)).and_then(|n| n.upgrade());
+            self.last_inode += 1;
+            let info_node = Rc::new(FSNode {
+                inode: self.last_inode,
+                name: RefCell::new(inode.to_string()),
+                parent: RefCell::new(dir.as_ref().map(Rc::downgrade).unwrap_or_default()),
+                entry: FSEntry::File(FSFileType::Raw(RawFSFileType::new(info)))
+            });
+            self.inode.insert(info_node.inode, Rc::downgrade(&info_node));
+            self.inode_infos.insert(inode, info_node);
+        }
+        let info_node = self.inode_infos[&inode].clone();
+        self.get_node_attr(&info_node)
+    }
+
+    /// Drops a file from the listings once its backing source reported it's gone (`ENOENT` or
+    /// `ESTALE`) `failures` times in a row
+    pub fn with_prune_after(mut self, failures: u32) -> JsonFS {
+        self.prune_after = Some(failures);
+        self
+    }
+
+    /// Bounds the size of a single read to `max_read_bytes`, the larger reads being handled
+    /// according to `oversized`
+    pub fn with_max_read_bytes(mut self, max_read_bytes: u32, oversized: OversizedRead) -> JsonFS {
+        self.max_read = Some((max_read_bytes, oversized));
+        self
+    }
+
+    /// How long the kernel may cache the attributes of the nodes without a `ttl_ms`, `DEFAULT_TTL`
+    /// otherwise. Long TTLs suit static trees, short ones the files whose content changes.
+    pub fn with_ttl(mut self, ttl: Duration) -> JsonFS {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Reports all the nodes as owned by the user `uid`, e.g. a service user when running as root
+    pub fn with_uid(mut self, uid: u32) -> JsonFS {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Reports all the nodes as owned by the group `gid`
+    pub fn with_gid(mut self, gid: u32) -> JsonFS {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Charset reported in the `user.mime_type` of the text files, `None` reporting none
+    pub fn with_charset(mut self, charset: Option<String>) -> JsonFS {
+        self.charset = charset;
+        self
+    }
+
+    /// Sorts the entries of the directory listings according to `readdir_sort`
+    pub fn with_readdir_sort(mut self, readdir_sort: ReaddirSort) -> JsonFS {
+        self.readdir_sort = readdir_sort;
+        let mut dir_listing = std::mem::replace(&mut self.dir_listing, HashMap::new());
+        for entries in dir_listing.values_mut() {
+            self.sort_listing(entries);
+        }
+        self.dir_listing = dir_listing;
+        self
+    }
+
+    pub fn with_namelen(mut self, namelen: u32) -> JsonFS {
+        self.namelen = namelen;
+        self
+    }
+
+    /// Statistics of the file system, as replied to `statfs`. The blocks are the sizes of all the
+    /// files summed up, none of them free since the file system is read-only.
+    pub fn statfs_info(&self) -> Statfs {
+        let size = self.dir_size(&self.fs_tree_root);
+        Statfs {
+            blocks: (size + u64::from(STATFS_BLOCK_SIZE) - 1) / u64::from(STATFS_BLOCK_SIZE),
+            bfree: 0,
+            bavail: 0,
+            files: self.inode.len() as u64,
+            ffree: 0,
+            bsize: STATFS_BLOCK_SIZE,
+            namelen: self.namelen,
+            frsize: STATFS_BLOCK_SIZE
+        }
+    }
+
+    pub fn descriptor(&self) -> &serde_json::Value {
+        &self.descriptor
+    }
+
+    pub fn fs_tree_root(&self) -> &Rc<FSNode> {
+        &self.fs_tree_root
+    }
+
+    /// Rebuilds the subtree at `path` from `descriptor`, leaving the rest of the tree untouched.
+    /// The new nodes get fresh inodes, while the inodes of the other nodes are preserved.
+    /// `path` must point to an entry declared in the descriptor, so the root and the entries
+    /// generated by `mirror:`/`glob:` expansions can't be reloaded.
+    pub fn reload_path(&mut self, path: &str, descriptor: serde_json::Value) -> Result<(), DescriptorError> {
+        let not_declared = || DescriptorError::invalid(format!("{} is not an entry declared in the descriptor", path));
+        let old_node = self.fs_tree_root.walk(path.to_string()).ok_or_else(not_declared)?;
+        let pointer = self.descriptor_pointer(path).ok_or_else(not_declared)?;
+        if pointer.is_empty() {
+            return Err(not_declared());
+        }
+
+        let mut inodes = HashMap::new();
+        let new_node = FSNode::_new(&mut self.last_inode, &mut inodes, old_node.name.borrow().clone(), self.options.with_local_prefix(descriptor.clone()), &self.options)?;
+        info!("Reloaded subtree {}", path);
+        debug!("Reloaded subtree {}: {:?}", path, new_node);
+        self.replace_node(&old_node, new_node.clone(), inodes);
+        self.index_descriptor(&new_node, &descriptor);
+        *self.descriptor.pointer_mut(&pointer).unwrap() = descriptor;
+
+        // Keep the generated descriptor and version files in sync with the new descriptor
+        let generated = JsonFS::with_generated_files(&self.descriptor);
+        for name in &[DESCRIPTOR_FILE_NAME, VERSION_FILE_NAME] {
+            if let Some(old_generated_node) = self.fs_tree_root.walk(format!("/{}", name)) {
+                let mut inodes = HashMap::new();
+                let new_generated_node = FSNode::_new(&mut self.last_inode, &mut inodes, name.to_string(), generated[*name].clone(), &self.options)?;
+                self.replace_node(&old_generated_node, new_generated_node, inodes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the tree from `descriptor`, replacing the current one only when it builds. The
+    /// nodes at the paths found in both trees with the same kind keep their inode, so that the
+    /// handles opened on them keep working, while the other nodes get fresh inodes. The generated
+    /// `.log` and `.inodes` entries are carried over.
+    pub fn reload(&mut self, descriptor: serde_json::Value) -> Result<(), DescriptorError> {
+        let (tree_descriptor, options) = self.build_options.with_directives(JsonFS::with_generated_files(&descriptor))?;
+        let mut last_inode = self.last_inode;
+        let fs_tree_root = FSNode::_new(&mut last_inode, &mut HashMap::new(), String::new(), options.with_local_prefix(tree_descriptor), &options)?;
+
+        let generated: Vec<Rc<FSNode>> = match &self.fs_tree_root.entry {
+            FSEntry::Dir(entries) => entries
+                .borrow()
+                .iter()
+                .filter(|e| match e.entry {
+                    FSEntry::File(FSFileType::Log(_)) => true,
+                    _ => Some(e.inode) == self.inodes_dir
+                })
+                .cloned()
+                .collect(),
+            FSEntry::File(_) => vec![]
+        };
+        let mut inode = HashMap::new();
+        let generated_inodes = generated.iter().map(|n| n.inode).collect();
+        let fs_tree_root = JsonFS::preserve_inodes(fs_tree_root, Some(&self.fs_tree_root), &generated_inodes, &mut inode);
+        if let FSEntry::Dir(entries) = &fs_tree_root.entry {
+            let mut entries = entries.borrow_mut();
+            for node in &generated {
+                if let Some(position) = entries.iter().position(|e| *e.name.borrow() == *node.name.borrow()) {
+                    warn!("The descriptor entry {} is shadowed by the generated one", node.name.borrow());
+                    for shadowed in entries.remove(position).flatten_strong() {
+                        inode.remove(&shadowed.inode);
+                    }
+                }
+                entries.push(node.clone());
+                inode.insert(node.inode, Rc::downgrade(node));
+            }
+        }
+        JsonFS::check_refs(&fs_tree_root)?;
+        info!("Reloaded FS Tree");
+        debug!("Reloaded FS Tree: {:?}", fs_tree_root);
+
+        for node in &generated {
+            *node.parent.borrow_mut() = Rc::downgrade(&fs_tree_root);
+        }
+        self.descriptor = descriptor;
+        self.fs_tree_root = fs_tree_root;
+        self.inode = inode;
+        self.options = options;
+        self.last_inode = last_inode;
+        self.failures.clear();
+        self.dir_sizes.borrow_mut().clear();
+        self.sibling_checksums.borrow_mut().clear();
+        self.lazy_dirs.clear();
+        self.xattrs.clear();
+        self.aliases.clear();
+        self.ttls.clear();
+        self.inode_infos.clear();
+        self.dir_listing.clear();
+        let (root, descriptor) = (self.fs_tree_root.clone(), self.descriptor.clone());
+        self.index_descriptor(&root, &descriptor);
+        self.update_dir_listing(root.flatten_strong());
+        // The handles read their file again from now on
+        for open_file in self.open_files.values_mut() {
+            open_file.content = None;
+            open_file.size = None;
+        }
+        Ok(())
+    }
+
+    /// Gives the nodes of the tree `node` just built the inodes of the nodes of `old` at the same
+    /// path and of the same kind, but the `skipped` ones, and adds them to `inodes`
+    fn preserve_inodes(node: Rc<FSNode>, old: Option<&Rc<FSNode>>, skipped: &HashSet<u64>, inodes: &mut HashMap<u64, Weak<FSNode>>) -> Rc<FSNode> {
+        let FSNode { inode, name, entry, .. } = match Rc::try_unwrap(node) {
+            Ok(node) => node,
+            // Shared with another node of the tree, it keeps its inodes
+            Err(node) => {
+                inodes.extend(node.flatten_strong().iter().map(|n| (n.inode, Rc::downgrade(n))));
+                return node;
+            }
+        };
+        let old = old.filter(|old| !skipped.contains(&old.inode) && match (&old.entry, &entry) {
+            (FSEntry::Dir(_), FSEntry::Dir(_)) | (FSEntry::File(_), FSEntry::File(_)) => true,
+            _ => false
+        });
+        let entry = match entry {
+            FSEntry::Dir(children) => FSEntry::Dir(RefCell::new(children
+                .into_inner()
+                .into_iter()
+                .map(|child| {
+                    let old_child = match old.map(|old| &old.entry) {
+                        Some(FSEntry::Dir(old_children)) => old_children.borrow().iter().find(|c| *c.name.borrow() == *child.name.borrow()).cloned(),
+                        _ => None
+                    };
+                    JsonFS::preserve_inodes(child, old_child.as_ref(), skipped, inodes)
+                })
+                .collect())),
+            file => file
+        };
+        let node = Rc::new(FSNode {
+            inode: old.map_or(inode, |old| old.inode),
+            name,
+            parent: RefCell::new(Weak::new()),
+            entry
+        });
+        if let FSEntry::Dir(children) = &node.entry {
+            for child in children.borrow().iter() {
+                *child.parent.borrow_mut() = Rc::downgrade(&node);
+            }
+        }
+        inodes.insert(node.inode, Rc::downgrade(&node));
+        node
+    }
+
+    /// Swaps in the last descriptor received from `reloads`, keeping the current tree when it
+    /// doesn't build
+    fn apply_reloads(&mut self) {
+        let descriptor = self.reloads.as_ref().and_then(|reloads| reloads.try_iter().last());
+        if let Some(descriptor) = descriptor {
+            match self.reload(descriptor) {
+                Ok(()) => info!("Reloaded the descriptor"),
+                Err(e) => warn!("Cannot reload the descriptor, keeping the current one: {}", e)
+            }
+        }
+    }
+
+    /// Name of the entry declared in a directory descriptor with `key`
+    fn entry_name(&self, key: &str) -> String {
+        if self.options.normalize_names {
+            FSEntry::normalized_name(key)
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Key declaring the entry `name` in the directory descriptor `m`. The keys differ from the
+    /// names when building with `normalize_names`.
+    fn descriptor_key<'a>(&self, m: &'a serde_json::Map<String, serde_json::Value>, name: &'a str) -> Option<&'a str> {
+        JsonFS::find_descriptor_key(m, name, self.options.normalize_names)
+    }
+
+    fn find_descriptor_key<'a>(m: &'a serde_json::Map<String, serde_json::Value>, name: &'a str, normalize_names: bool) -> Option<&'a str> {
+        if normalize_names {
+            m.keys().find(|key| FSEntry::normalized_name(key) == name).map(|key| key.as_str())
+        } else if m.contains_key(name) {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Json pointer to the descriptor of the entry at `path`, when declared in the descriptor
+    fn descriptor_pointer(&self, path: &str) -> Option<String> {
+        let mut pointer = String::new();
+        let mut descriptor = &self.descriptor;
+        for component in Path::new(path).components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy(),
+                _ => continue
+            };
+            let key = match descriptor {
+                serde_json::Value::Object(m) => self.descriptor_key(m, &name)?.to_string(),
+                serde_json::Value::Array(_) => name.to_string(),
+                _ => return None
+            };
+            pointer.push_str(&format!("/{}", key.replace("~", "~0").replace("/", "~1")));
+            descriptor = self.descriptor.pointer(&pointer)?;
+        }
+        Some(pointer)
+    }
+
+    /// Records the lazy directories, the extended attributes, the aliases and the TTLs declared in
+    /// the `descriptor` of the subtree at `node`
+    fn index_descriptor(&mut self, node: &Rc<FSNode>, descriptor: &serde_json::Value) {
+        match (&node.entry, descriptor) {
+            (FSEntry::Dir(entries), serde_json::Value::Object(m)) => match FSEntry::lazy_source(m) {
+                Some(source) => {
+                    self.lazy_dirs.insert(node.inode, source.to_string());
+                },
+                // Validated when the directory was built
+                None if FSEntry::is_foreach(m) => if let Ok(expanded) = FSEntry::foreach_descriptor(m) {
+                    self.index_descriptor(node, &serde_json::Value::Object(expanded));
+                },
+                None => for child in entries.borrow().iter() {
+                    if let Some(child_descriptor) = self.descriptor_key(m, &child.name.borrow()).and_then(|key| m.get(key)) {
+                        self.index_descriptor(child, child_descriptor);
+                    }
+                }
+            },
+            (FSEntry::Dir(entries), serde_json::Value::Array(items)) => for child in entries.borrow().iter() {
+                if let Some(child_descriptor) = child.name.borrow().parse::<usize>().ok().and_then(|i| items.get(i)) {
+                    self.index_descriptor(child, child_descriptor);
+                }
+            },
+            (FSEntry::File(_), serde_json::Value::Object(m)) => {
+                // Validated when the file was built
+                if let Some(Ok(xattrs)) = m.get("xattrs").map(parse_xattrs) {
+                    self.xattrs.insert(node.inode, xattrs);
+                }
+                if let Some(ttl) = m.get("ttl_ms").and_then(|t| t.as_u64()) {
+                    self.ttls.insert(node.inode, Duration::from_millis(ttl));
+                }
+                if let Some(serde_json::Value::Array(aliases)) = m.get("also") {
+                    for alias in aliases.iter().filter_map(|a| a.as_str()) {
+                        self.add_alias(node, alias);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Makes `target` appear under the name `alias` too, sharing its inode. An alias containing a
+    /// `/` is a path from the root, otherwise the alias is a sibling of `target`. Aliases
+    /// colliding with an entry of their directory are skipped.
+    fn add_alias(&mut self, target: &Rc<FSNode>, alias: &str) {
+        let (dir, name) = match alias.rfind('/') {
+            Some(i) => (self.fs_tree_root.walk(format!("/{}", alias[..i].trim_start_matches('/'))), &alias[i + 1..]),
+            None => (target.parent.borrow().upgrade(), alias)
+        };
+        let dir = match dir {
+            Some(dir) => dir,
+            None => {
+                warn!("Cannot alias {} as {}: no such directory", target.full_path(), alias);
+                return;
+            }
+        };
+        let taken = match &dir.entry {
+            FSEntry::Dir(entries) => name.is_empty() || entries.borrow().iter().any(|e| *e.name.borrow() == name),
+            FSEntry::File(_) => true
+        } || self.aliases.get(&dir.inode).map(|a| a.iter().any(|(n, _)| n == name)).unwrap_or(false);
+        if taken {
+            warn!("Cannot alias {} as {}: the name is taken", target.full_path(), alias);
+            return;
+        }
+
+        self.aliases.entry(dir.inode).or_insert_with(Vec::new).push((name.to_string(), target.clone()));
+        self.update_dir_listing(vec![dir]);
+    }
+
+    /// Builds the children of the directory with `inode` when it's a lazy directory accessed for
+    /// the first time. When the source can't be fetched or isn't a directory descriptor, the
+    /// directory stays empty and the next access tries again.
+    fn resolve_lazy_dir(&mut self, inode: u64) -> io::Result<()> {
+        let source = match self.lazy_dirs.remove(&inode) {
+            Some(source) => source,
+            None => return Ok(())
+        };
+        let result = self.splice_lazy_dir(inode, &source);
+        if let Err(e) = &result {
+            warn!("Cannot resolve the lazy directory {} from {}: {}", inode, source, e);
+            self.lazy_dirs.insert(inode, source);
+        }
+        result
+    }
+
+    fn splice_lazy_dir(&mut self, inode: u64, source: &str) -> io::Result<()> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let source = self.options.with_local_prefix_str(source.to_string());
+        let file_type = FSFileType::parse_file_descriptor(&source, &self.options).map_err(|_| io::Error::from_raw_os_error(EIO))?;
+        let mut content = vec![];
+        FSFileReader::new(file_type.ops())?.read_to_end(&mut content)?;
+
+        let parsed: serde_json::Result<serde_json::Value> = serde_json::from_slice(&content);
+        let descriptor = match parsed.map(|d| self.options.with_local_prefix(d)) {
+            Ok(serde_json::Value::Object(descriptor)) => descriptor,
+            _ => return Err(io::Error::from_raw_os_error(EIO))
+        };
+        let mut inodes = HashMap::new();
+        let children = match FSEntry::create_directory(&mut self.last_inode, &mut inodes, descriptor.clone(), &self.options) {
+            Ok(FSEntry::Dir(children)) => children.into_inner(),
+            _ => return Err(io::Error::from_raw_os_error(EIO))
+        };
+
+        if let FSEntry::Dir(entries) = &node.entry {
+            for child in children.iter() {
+                *child.parent.borrow_mut() = Rc::downgrade(&node);
+            }
+            entries.borrow_mut().extend(children.iter().cloned());
+        }
+        self.inode.extend(inodes);
+        for child in children.iter() {
+            self.update_dir_listing(child.flatten_strong());
+            if let Some(child_descriptor) = self.descriptor_key(&descriptor, &child.name.borrow()).and_then(|key| descriptor.get(key)) {
+                self.index_descriptor(child, child_descriptor);
+            }
+        }
+        self.update_dir_listing(vec![node]);
+        self.dir_sizes.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Replaces `old_node` with `new_node`, whose subtree has the nodes `inodes`
+    fn replace_node(&mut self, old_node: &Rc<FSNode>, new_node: Rc<FSNode>, inodes: HashMap<u64, Weak<FSNode>>) {
+        let parent = old_node.parent.borrow().upgrade().unwrap();
+        if let FSEntry::Dir(entries) = &parent.entry {
+            let mut entries = entries.borrow_mut();
+            if let Some(position) = entries.iter().position(|e| Rc::ptr_eq(e, old_node)) {
+                entries[position] = new_node.clone();
+            }
+        }
+        *new_node.parent.borrow_mut() = Rc::downgrade(&parent);
+
+        self.forget_subtree(old_node);
+        self.inode.extend(inodes);
+        self.update_dir_listing(new_node.flatten_strong());
+        self.update_dir_listing(vec![parent]);
+    }
+
+    fn remove_node(&mut self, node: &Rc<FSNode>) {
+        if let Some(parent) = node.parent.borrow().upgrade() {
+            if let FSEntry::Dir(entries) = &parent.entry {
+                entries.borrow_mut().retain(|e| !Rc::ptr_eq(e, node));
+            }
+            self.update_dir_listing(vec![parent]);
+        }
+        self.forget_subtree(node);
+    }
+
+    fn forget_subtree(&mut self, node: &Rc<FSNode>) {
+        self.dir_sizes.borrow_mut().clear();
+        let mut forgotten = HashSet::new();
+        for node in node.flatten_strong() {
+            self.inode.remove(&node.inode);
+            self.dir_listing.remove(&node.inode);
+            self.failures.remove(&node.inode);
+            self.lazy_dirs.remove(&node.inode);
+            self.xattrs.remove(&node.inode);
+            self.aliases.remove(&node.inode);
+            self.ttls.remove(&node.inode);
+            forgotten.insert(node.inode);
+        }
+
+        // Drop the aliases of the forgotten files from the other directories
+        let mut changed_dirs = vec![];
+        for (dir, aliases) in self.aliases.iter_mut() {
+            let count = aliases.len();
+            aliases.retain(|(_, target)| !forgotten.contains(&target.inode));
+            if aliases.len() != count {
+                changed_dirs.extend(self.inode.get(dir).and_then(|n| n.upgrade()));
+            }
+        }
+        self.update_dir_listing(changed_dirs);
+    }
+
+    /// Regenerates the listings of the directories among `nodes`, aliases included
+    fn update_dir_listing(&mut self, nodes: Vec<Rc<FSNode>>) {
+        let mut dir_listing = JsonFS::generate_dir_listing(nodes);
+        for (dir, entries) in dir_listing.iter_mut() {
+            if let Some(aliases) = self.aliases.get(dir) {
+                entries.extend(aliases.iter().map(|(name, target)| (target.inode, FileType::RegularFile, OsString::from(name))));
+            }
+            self.sort_listing(entries);
+        }
+        self.dir_listing.extend(dir_listing);
+    }
+
+    /// Sorts the entries of a directory listing according to the `readdir_sort` policy, keeping
+    /// `.` and `..` first. Entries comparing equal keep their order.
+    fn sort_listing(&self, entries: &mut Vec<(u64, FileType, OsString)>) {
+        let dots = entries.iter().take_while(|(_, _, name)| name == "." || name == "..").count();
+        let entries = &mut entries[dots..];
+        let attr = |inode: u64| self.inode.get(&inode).and_then(|n| n.upgrade()).and_then(|n| self.get_node_attr(&n).ok());
+        match self.readdir_sort {
+            ReaddirSort::Source => {},
+            ReaddirSort::Name => entries.sort_by(|a, b| a.2.cmp(&b.2)),
+            ReaddirSort::NameDesc => entries.sort_by(|a, b| b.2.cmp(&a.2)),
+            ReaddirSort::Size => entries.sort_by_cached_key(|e| attr(e.0).map(|a| a.size).unwrap_or(0)),
+            ReaddirSort::Mtime => entries.sort_by_cached_key(|e| attr(e.0).map(|a| a.mtime).unwrap_or(UNIX_EPOCH))
+        }
+    }
+
+    /// The descriptor of the tree as it is now: the one the file system was built from, with the
+    /// content the raw files were written. Content that isn't valid UTF-8 is stored as base64.
+    pub fn serialize_to_json(&self) -> serde_json::Value {
+        self.serialize_node(&self.fs_tree_root, &self.descriptor)
+    }
+
+    fn serialize_node(&self, node: &FSNode, descriptor: &serde_json::Value) -> serde_json::Value {
+        use serde_json::Value::*;
+
+        match (&node.entry, descriptor) {
+            // The entries of the other directories are built from their source, not the descriptor
+            (FSEntry::Dir(entries), Object(m)) if FSEntry::lazy_source(m).is_none() && FSEntry::jsontree_source(m).is_none() && !FSEntry::is_foreach(m) => {
+                let entries = entries.borrow();
+                Object(m.iter()
+                    .map(|(name, child_descriptor)| match entries.iter().find(|e| *e.name.borrow() == self.entry_name(name)) {
+                        Some(child) => (name.clone(), self.serialize_node(child, child_descriptor)),
+                        // A directive of the root
+                        None => (name.clone(), child_descriptor.clone())
+                    })
+                    .collect())
+            },
+            (FSEntry::Dir(entries), Array(items)) => {
+                let entries = entries.borrow();
+                Array(items.iter()
+                    .enumerate()
+                    .map(|(i, child_descriptor)| match entries.iter().find(|e| *e.name.borrow() == i.to_string()) {
+                        Some(child) => self.serialize_node(child, child_descriptor),
+                        None => child_descriptor.clone()
+                    })
+                    .collect())
+            },
+            (FSEntry::File(FSFileType::Raw(raw)), String(_)) => match std::str::from_utf8(&raw.data.borrow()) {
+                Ok(data) => String(format!("raw:{}", data)),
+                Err(_) => String(format!("base64:{}", base64::encode(&raw.data.borrow())))
+            },
+            (FSEntry::File(FSFileType::Raw(raw)), Object(m)) => {
+                let mut m = m.clone();
+                let (file_type, data) = match std::str::from_utf8(&raw.data.borrow()) {
+                    Ok(data) => ("raw", data.to_string()),
+                    Err(_) => ("base64", base64::encode(&raw.data.borrow()))
+                };
+                m.insert("type".to_string(), String(file_type.to_string()));
+                m.insert("data".to_string(), String(data));
+                Object(m)
+            },
+            _ => descriptor.clone()
+        }
+    }
+
+    /// Entries of the directory with `inode` as served by `readdir`, `.` and `..` included
+    pub fn dir_entries(&self, inode: u64) -> Option<&Vec<(u64, FileType, OsString)>> {
+        self.dir_listing.get(&inode)
+    }
+
+    /// Tracks the failures of the backing source of `inode`, pruning it when configured
+    fn record_result<T>(&mut self, inode: u64, result: &io::Result<T>) {
+        let gone = match result {
+            Ok(_) => {
+                self.failures.remove(&inode);
+                return;
+            },
+            Err(e) => e.raw_os_error() == Some(ENOENT) || e.raw_os_error() == Some(ESTALE)
+        };
+        if let (true, Some(prune_after)) = (gone, self.prune_after) {
+            let failures = self.failures.entry(inode).or_insert(0);
+            *failures += 1;
+            if *failures >= prune_after {
+                if let Some(node) = self.inode.get(&inode).and_then(|n| n.upgrade()) {
+                    warn!("Pruning {} after {} failures", node.full_path(), prune_after);
+                    self.remove_node(&node);
+                }
+            }
+        }
+    }
+
+    fn with_generated_files(descriptor: &serde_json::Value) -> serde_json::Value {
+        let mut generated = descriptor.clone();
+        if let serde_json::Value::Object(root) = &mut generated {
+            if root.contains_key(DESCRIPTOR_FILE_NAME) {
+                warn!("The descriptor entry {} is shadowed by the generated one", DESCRIPTOR_FILE_NAME);
+            }
+            let pretty = serde_json::to_string_pretty(descriptor).unwrap();
+            root.insert(DESCRIPTOR_FILE_NAME.to_string(), serde_json::Value::String(format!("raw:{}", pretty)));
+
+            if root.contains_key(VERSION_FILE_NAME) {
+                warn!("The descriptor entry {} is shadowed by the generated one", VERSION_FILE_NAME);
+            }
+            root.insert(VERSION_FILE_NAME.to_string(), serde_json::Value::String(format!("raw:{}\n", JsonFS::descriptor_version(descriptor))));
+        }
+        generated
+    }
+
+    /// Version of a descriptor: the SHA-256 of its canonical JSON, which has the keys of the
+    /// objects sorted and no whitespace
+    pub fn descriptor_version(descriptor: &serde_json::Value) -> String {
+        sha256::hex_digest(serde_json::to_string(descriptor).unwrap().as_bytes())
+    }
+
+    fn generate_dir_listing(nodes: Vec<Rc<FSNode>>) -> HashMap<u64, Vec<(u64, FileType, OsString)>> {
         let mut result = HashMap::new();
 
-        for weak_node in nodes.iter() {
-            let node: Rc<FSNode> = weak_node.upgrade().unwrap();
+        for node in nodes.iter() {
             if let FSNode { inode, parent, entry: FSEntry::Dir(entries), .. } = node.borrow() {
                 let mut dir_listing: Vec<(u64, FileType, OsString)> = vec![
                     (*inode, FileType::Directory, OsString::from("."))
@@ -37,11 +995,13 @@ impl JsonFS {
                 };
                 dir_listing.extend(
                     entries
+                        .borrow()
                         .iter()
                         .map(|node| {
                             match node.borrow() {
-                                FSNode { inode, name, entry: FSEntry::Dir(_), .. } => (*inode, FileType::Directory, OsString::from(name)),
-                                FSNode { inode, name, entry: FSEntry::File(_), .. } => (*inode, FileType::RegularFile, OsString::from(name))
+                                FSNode { inode, name, entry: FSEntry::Dir(_), .. } => (*inode, FileType::Directory, OsString::from(&*name.borrow())),
+                                FSNode { inode, name, entry: FSEntry::File(FSFileType::Symlink(_)), .. } => (*inode, FileType::Symlink, OsString::from(&*name.borrow())),
+                                FSNode { inode, name, entry: FSEntry::File(_), .. } => (*inode, FileType::RegularFile, OsString::from(&*name.borrow()))
                             }
                         })
                         .collect::<Vec<(u64, FileType, OsString)>>()
@@ -50,22 +1010,37 @@ impl JsonFS {
             }
         }
 
-        info!("Generated dir listing: {:?}", result);
+        debug!("Generated dir listing: {:?}", result);
 
         result
     }
 
-    fn generate_dir_attr(&self, inode: u64) -> FileAttr {
+    /// Total size of the files under `entry`
+    fn dir_size(&self, entry: &FSNode) -> u64 {
+        if let Some(size) = self.dir_sizes.borrow().get(&entry.inode) {
+            return *size;
+        }
+        let size = match &entry.entry {
+            FSEntry::Dir(entries) => entries.borrow().iter().map(|e| self.dir_size(e)).sum(),
+            FSEntry::File(file_type) => file_type.ops().size().unwrap_or(0)
+        };
+        if let FSEntry::Dir(_) = entry.entry {
+            self.dir_sizes.borrow_mut().insert(entry.inode, size);
+        }
+        size
+    }
+
+    fn generate_dir_attr(&self, entry: &FSNode) -> FileAttr {
         FileAttr {
-            ino: inode,
-            size: 0,
+            ino: entry.inode,
+            size: if self.recursive_dir_size { self.dir_size(entry) } else { 0 },
             blocks: 0,
             atime: SystemTime::now(),
             mtime: SystemTime::now(),
             ctime: SystemTime::now(),
             crtime: SystemTime::now(),
             kind: FileType::Directory,
-            perm: 0o755,
+            perm: JsonFS::matching_permission(&self.dir_permission_policy, entry).unwrap_or(self.default_dir_mode),
             nlink: 2,
             uid: nix::unistd::getuid().into(),
             gid: nix::unistd::getgid().into(),
@@ -74,29 +1049,780 @@ impl JsonFS {
         }
     }
 
-    fn get_node_attr(&self, entry: &FSNode) -> FileAttr {
+    pub fn get_node_attr(&self, entry: &FSNode) -> io::Result<FileAttr> {
+        let mut attr = self.node_attr(entry)?;
+        if let Some(uid) = self.uid {
+            attr.uid = uid;
+        }
+        if let Some(gid) = self.gid {
+            attr.gid = gid;
+        }
+        Ok(attr)
+    }
+
+    fn node_attr(&self, entry: &FSNode) -> io::Result<FileAttr> {
         match entry {
-            FSNode { inode, entry: FSEntry::File(file), .. } => file.ops().get_attributes(*inode),
-            FSNode { inode, entry: FSEntry::Dir(_), .. } => self.generate_dir_attr(*inode)
+            FSNode { inode, entry: FSEntry::File(file), .. } => {
+                let mut attr = match file {
+                    FSFileType::AttrOf(attr_of) => attr_of.attributes(*inode, self.attrof_content(attr_of)?.len() as u64),
+                    FSFileType::Ref(_) => FileAttr { ino: *inode, ..self.node_attr(&*self.ref_target(entry)?)? },
+                    FSFileType::CatDir(cat_dir) => {
+                        let mut size = 0;
+                        for file in self.catdir_files(cat_dir)? {
+                            size += self.get_node_attr(&file)?.size;
+                        }
+                        cat_dir.attributes(*inode, size)
+                    },
+                    _ => file.ops().get_attributes(*inode)?
+                };
+                if let Some(perm) = JsonFS::matching_permission(&self.permission_policy, entry) {
+                    attr.perm = perm;
+                }
+                Ok(attr)
+            },
+            FSNode { entry: FSEntry::Dir(_), .. } => Ok(self.generate_dir_attr(entry))
+        }
+    }
+
+    /// Attributes of the node with `inode`, tracking the failures of its backing source
+    pub fn inode_attr(&mut self, inode: u64) -> io::Result<FileAttr> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let result = self.get_node_attr(&node);
+        self.record_result(inode, &result);
+        result
+    }
+
+    /// Attributes of the entry named `name` in the directory with `inode` `parent`, building the
+    /// children of the directory first when it's a lazy one
+    pub fn lookup_entry(&mut self, parent: u64, name: &OsStr) -> io::Result<FileAttr> {
+        if Some(parent) == self.inodes_dir {
+            return self.lookup_inode_info(name);
+        }
+        self.resolve_lazy_dir(parent)?;
+        let dir = self.inode.get(&parent).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let found = match &dir.entry {
+            FSEntry::Dir(entries) => entries
+                .borrow()
+                .iter()
+                .find(|e| OsStr::new(&*e.name.borrow()) == name)
+                .map(|e| e.inode),
+            FSEntry::File(_) => return Err(io::Error::from_raw_os_error(ENOTDIR))
+        };
+        let found = found.or_else(|| self.aliases
+            .get(&parent)
+            .and_then(|aliases| aliases.iter().find(|(alias, _)| OsStr::new(alias) == name))
+            .map(|(_, target)| target.inode));
+        match found {
+            Some(inode) => self.inode_attr(inode),
+            None => Err(io::Error::from_raw_os_error(ENOENT))
+        }
+    }
+
+    /// Extended attributes of the node with `inode`: the computed ones, overridden by the ones
+    /// declared in the descriptor
+    pub fn xattrs(&self, inode: u64) -> io::Result<BTreeMap<String, Vec<u8>>> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let mut xattrs = BTreeMap::new();
+        if let FSEntry::File(_) = node.entry {
+            xattrs.insert(MIME_TYPE_XATTR.to_string(), mime_type_with_charset(&node.name.borrow(), self.charset.as_ref().map(String::as_str)).into_bytes());
+        }
+        if let Some(declared) = self.xattrs.get(&inode) {
+            xattrs.extend(declared.iter().map(|(name, value)| (name.clone(), value.clone())));
         }
+        Ok(xattrs)
+    }
+
+    /// Target of the symbolic link with `inode`
+    pub fn read_link(&self, inode: u64) -> io::Result<String> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        match &node.entry {
+            FSEntry::File(FSFileType::Symlink(link)) => Ok(link.target.clone()),
+            _ => Err(io::Error::from_raw_os_error(EINVAL))
+        }
+    }
+
+    /// How long the kernel may cache the attributes of the node with `inode`: its `ttl_ms` when
+    /// declared, the TTL of the file system otherwise
+    pub fn ttl(&self, inode: u64) -> Duration {
+        self.ttls.get(&inode).cloned().unwrap_or(self.ttl)
+    }
+
+    fn reply_xattr(data: &[u8], size: u32, reply: ReplyXattr) {
+        if size == 0 {
+            reply.size(data.len() as u32);
+        } else if data.len() > size as usize {
+            reply.error(ERANGE);
+        } else {
+            reply.data(data);
+        }
+    }
+
+    /// Reads up to `size` bytes at `offset` of the file with `inode`, tracking the failures of its
+    /// backing source. Reads stop at the size the file reports, and the part of that size
+    /// the file type has no content for is filled with zeros.
+    pub fn read_inode(&mut self, inode: u64, offset: i64, size: u32) -> io::Result<Vec<u8>> {
+        self.read_handle(inode, 0, offset, size)
+    }
+
+    /// Reads the file with `inode` through the handle `fh`, from the content materialized when it
+    /// was opened or up to the size it had then. Reads as `read_inode` when `fh` isn't a handle of the file.
+    pub fn read_handle(&mut self, inode: u64, fh: u64, offset: i64, size: u32) -> io::Result<Vec<u8>> {
+        let start = Instant::now();
+        let result = match self.open_files.get(&fh) {
+            Some(OpenFile { inode: open_inode, content: Some(content), .. }) if *open_inode == inode => self.read_size(size).map(|size| {
+                let start = min(offset.max(0) as usize, content.len());
+                let end = min(start + size as usize, content.len());
+                content[start..end].to_vec()
+            }),
+            Some(OpenFile { inode: open_inode, size: file_size, .. }) if *open_inode == inode => self.read_file(inode, offset, size, *file_size),
+            _ => self.read_file(inode, offset, size, None)
+        };
+        self.record_result(inode, &result);
+
+        if !self.read_callbacks.is_empty() {
+            let event = ReadEvent {
+                inode,
+                offset,
+                size,
+                bytes: result.as_ref().map(|buffer| buffer.len()).unwrap_or(0),
+                duration: start.elapsed(),
+                error: result.as_ref().err().map(|e| e.raw_os_error().unwrap_or(EIO))
+            };
+            for callback in self.read_callbacks.iter() {
+                callback(event.clone());
+            }
+        }
+        result
+    }
+
+    /// Opens the file with `inode`, returning a handle for its reads. The reads of the file types
+    /// opting in with `cache_on_open` are served from the content read once now, so that they
+    /// don't fetch or compute their content on each read; the files over `MAX_OPEN_CONTENT` and
+    /// the other file types are read on each read.
+    pub fn open_inode(&mut self, inode: u64) -> io::Result<u64> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let cache_on_open = match &node.entry {
+            FSEntry::File(file_type) => file_type.ops().cache_on_open(),
+            FSEntry::Dir(_) => return Err(io::Error::from_raw_os_error(EISDIR))
+        };
+        let size = self.get_node_attr(&node)?.size;
+        let content = if cache_on_open && size <= MAX_OPEN_CONTENT {
+            let mut content = vec![];
+            loop {
+                let chunk = self.read_node(&node, content.len() as i64, OPEN_READ_SIZE, Some(size))?;
+                content.extend_from_slice(&chunk);
+                if chunk.len() < OPEN_READ_SIZE as usize || content.len() as u64 > MAX_OPEN_CONTENT {
+                    break;
+                }
+            }
+            // The file grew past its reported size
+            Some(content).filter(|content| content.len() as u64 <= MAX_OPEN_CONTENT)
+        } else {
+            None
+        };
+        self.last_fh += 1;
+        self.open_files.insert(self.last_fh, OpenFile { inode, content, size: Some(size) });
+        Ok(self.last_fh)
+    }
+
+    /// Frees the handle `fh` returned by `open_inode`, returning whether it was open
+    pub fn release_handle(&mut self, fh: u64) -> bool {
+        self.open_files.remove(&fh).is_some()
+    }
+
+    /// Number of the handles returned by `open_inode` not released yet
+    pub fn open_handles(&self) -> usize {
+        self.open_files.len()
+    }
+
+    /// `size` bounded by the maximum read size, when configured
+    fn read_size(&self, size: u32) -> io::Result<u32> {
+        match self.max_read {
+            Some((max_read_bytes, OversizedRead::Reject)) if size > max_read_bytes => {
+                info!("Rejecting a read of {} bytes, over the maximum of {}", size, max_read_bytes);
+                Err(io::Error::from_raw_os_error(EINVAL))
+            },
+            Some((max_read_bytes, OversizedRead::Clamp)) => Ok(min(size, max_read_bytes)),
+            _ => Ok(size)
+        }
+    }
+
+    fn read_file(&self, inode: u64, offset: i64, size: u32, file_size: Option<u64>) -> io::Result<Vec<u8>> {
+        let size = self.read_size(size)?;
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        self.read_node(&node, offset, size, file_size)
+    }
+
+    /// Reads up to `size` bytes at `offset` of `node`, stopping at `file_size` when known or at the
+    /// size the file type reports
+    fn read_node(&self, node: &FSNode, offset: i64, size: u32, file_size: Option<u64>) -> io::Result<Vec<u8>> {
+        match &node.entry {
+            FSEntry::File(FSFileType::Ref(_)) => self.read_node(&*self.ref_target(node)?, offset, size, file_size),
+            FSEntry::File(FSFileType::CatDir(cat_dir)) => {
+                // Skips the files before `offset` by their size, reading only the ones the window spans
+                let mut content = Vec::with_capacity(size as usize);
+                let mut file_start = 0;
+                for file in self.catdir_files(cat_dir)? {
+                    let wanted = size as usize - content.len();
+                    if wanted == 0 {
+                        break;
+                    }
+                    let file_size = self.get_node_attr(&file)?.size;
+                    let file_offset = (offset as u64 + content.len() as u64).saturating_sub(file_start);
+                    if file_offset < file_size {
+                        content.extend(self.read_node(&file, file_offset as i64, wanted as u32, Some(file_size))?);
+                    }
+                    file_start += file_size;
+                }
+                Ok(content)
+            },
+            FSEntry::File(FSFileType::AttrOf(attr_of)) => Ok(JsonFS::window(&self.attrof_content(attr_of)?, offset, size)),
+            FSEntry::File(FSFileType::Crc32(Crc32FSFileType { target: Crc32Target::Sibling(name), .. })) =>
+                Ok(JsonFS::window(&self.sibling_checksum(node, name)?, offset, size)),
+            FSEntry::File(file_type) => file_size.map(Ok).unwrap_or_else(|| file_type.ops().size()).and_then(|file_size| {
+                let len = min(u64::from(size), file_size.saturating_sub(offset as u64));
+                let mut buffer = vec![0; len as usize];
+                file_type.ops().read(offset, &mut buffer[..]).map(|()| buffer)
+            }),
+            FSEntry::Dir(_) => Err(io::Error::from_raw_os_error(EISDIR))
+        }
+    }
+
+    /// Writes the descriptor of the tree, as returned by `serialize_to_json`, to `persist_to`
+    /// when set. Called on unmount.
+    pub fn persist(&self) -> io::Result<()> {
+        if let Some(path) = &self.persist_to {
+            info!("Writing the descriptor back to {}", path.display());
+            let pretty = serde_json::to_string_pretty(&self.serialize_to_json()).unwrap();
+            std::fs::write(path, pretty)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` of the raw file with `inode`, returning the bytes written. Fails
+    /// with `EROFS` unless the file system is writable, `EACCES` for the other file types and
+    /// `EFBIG` when the file would grow past `MAX_WRITTEN_SIZE`.
+    pub fn write_inode(&mut self, inode: u64, offset: i64, data: &[u8]) -> io::Result<u32> {
+        self.change_raw(inode, |raw| {
+            if offset < 0 {
+                return Err(io::Error::from_raw_os_error(EINVAL));
+            }
+            JsonFS::check_written_size((offset as u64).checked_add(data.len() as u64))?;
+            Ok(raw.write(offset, data) as u32)
+        })
+    }
+
+    /// Truncates the raw file with `inode` to `size`, or grows it with zeros, returning its new
+    /// attributes. Fails like `write_inode`.
+    pub fn set_inode_size(&mut self, inode: u64, size: u64) -> io::Result<FileAttr> {
+        self.change_raw(inode, |raw| {
+            JsonFS::check_written_size(Some(size))?;
+            raw.set_size(size as usize);
+            Ok(())
+        })?;
+        self.inode_attr(inode)
+    }
+
+    /// Fails with `EFBIG` when `size`, `None` on overflow, is past `MAX_WRITTEN_SIZE`
+    fn check_written_size(size: Option<u64>) -> io::Result<()> {
+        match size {
+            Some(size) if size <= MAX_WRITTEN_SIZE => Ok(()),
+            _ => Err(io::Error::from_raw_os_error(EFBIG))
+        }
+    }
+
+    /// Applies `change` to the raw file with `inode`. Fails with `EROFS` unless the file system is
+    /// writable, and `EACCES` for the other file types.
+    fn change_raw<T, F>(&mut self, inode: u64, change: F) -> io::Result<T>
+        where F: FnOnce(&RawFSFileType) -> io::Result<T> {
+        if !self.writable {
+            return Err(io::Error::from_raw_os_error(EROFS));
+        }
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let result = match &node.entry {
+            FSEntry::File(FSFileType::Raw(raw)) => change(raw)?,
+            FSEntry::File(_) => return Err(io::Error::from_raw_os_error(EACCES)),
+            FSEntry::Dir(_) => return Err(io::Error::from_raw_os_error(EISDIR))
+        };
+        // The sizes of the directories up to the root may have changed
+        self.dir_sizes.borrow_mut().clear();
+        // The handles of the file read it again from now on
+        for open_file in self.open_files.values_mut().filter(|f| f.inode == inode) {
+            open_file.content = None;
+            open_file.size = None;
+        }
+        Ok(result)
+    }
+
+    /// Creates the empty directory `name` in the directory with `inode` `parent`, returning its
+    /// attributes. Fails with `EROFS` unless the file system is writable.
+    pub fn make_dir(&mut self, parent: u64, name: &OsStr) -> io::Result<FileAttr> {
+        let node = self.add_node(parent, name, FSEntry::Dir(RefCell::new(vec![])), json!({}))?;
+        self.get_node_attr(&node)
+    }
+
+    /// Creates the empty raw file `name` in the directory with `inode` `parent`, returning its
+    /// attributes and a handle opened on it. Fails with `EROFS` unless the file system is writable.
+    pub fn create_file(&mut self, parent: u64, name: &OsStr) -> io::Result<(FileAttr, u64)> {
+        let entry = FSEntry::File(FSFileType::Raw(RawFSFileType::new(String::new())));
+        let node = self.add_node(parent, name, entry, serde_json::Value::String("raw:".to_string()))?;
+        let attr = self.get_node_attr(&node)?;
+        Ok((attr, self.open_inode(node.inode)?))
+    }
+
+    /// Adds the node `name` with `entry` to the directory with `inode` `parent`, declaring it with
+    /// `descriptor` in the descriptor of the directory, when it has one, so that it's persisted.
+    /// Fails with `EEXIST` when the name is taken, also by an alias or ignoring case.
+    fn add_node(&mut self, parent: u64, name: &OsStr, entry: FSEntry, descriptor: serde_json::Value) -> io::Result<Rc<FSNode>> {
+        if !self.writable {
+            return Err(io::Error::from_raw_os_error(EROFS));
+        }
+        if Some(parent) == self.inodes_dir {
+            return Err(io::Error::from_raw_os_error(EPERM));
+        }
+        let name = name.to_str().ok_or_else(|| io::Error::from_raw_os_error(EINVAL))?;
+        if name.len() > self.namelen as usize {
+            return Err(io::Error::from_raw_os_error(ENAMETOOLONG));
+        }
+        self.resolve_lazy_dir(parent)?;
+        let dir = self.inode.get(&parent).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let entries = match &dir.entry {
+            FSEntry::Dir(entries) => entries,
+            FSEntry::File(_) => return Err(io::Error::from_raw_os_error(ENOTDIR))
+        };
+        let lowercase_name = name.to_lowercase();
+        let aliases = self.aliases.get(&parent).into_iter().flatten().map(|(alias, _)| alias.clone());
+        if entries.borrow().iter().map(|e| e.name.borrow().clone()).chain(aliases).any(|n| n.to_lowercase() == lowercase_name) {
+            return Err(io::Error::from_raw_os_error(EEXIST));
+        }
+
+        self.last_inode += 1;
+        let node = Rc::new(FSNode {
+            inode: self.last_inode,
+            name: RefCell::new(name.to_string()),
+            parent: RefCell::new(Rc::downgrade(&dir)),
+            entry
+        });
+        entries.borrow_mut().push(node.clone());
+        self.inode.insert(node.inode, Rc::downgrade(&node));
+        self.update_dir_listing(vec![dir.clone(), node.clone()]);
+        self.dir_sizes.borrow_mut().clear();
+
+        if let Some(m) = self.dir_descriptor_mut(&dir) {
+            m.insert(name.to_string(), descriptor);
+        }
+        Ok(node)
+    }
+
+    /// Removes the file `name` from the directory with `inode` `parent`. Fails with `EROFS` unless
+    /// the file system is writable.
+    pub fn unlink_entry(&mut self, parent: u64, name: &OsStr) -> io::Result<()> {
+        self.remove_entry(parent, name, false)
+    }
+
+    /// Removes the empty directory `name` from the directory with `inode` `parent`. Fails with
+    /// `EROFS` unless the file system is writable.
+    pub fn remove_dir(&mut self, parent: u64, name: &OsStr) -> io::Result<()> {
+        self.remove_entry(parent, name, true)
+    }
+
+    /// Removes the entry `name` of the directory with `inode` `parent`, along with its declaration
+    /// in the descriptor. The `ref` files leading to it are left in place and fail with `ENOENT`
+    /// until a file is created at their target path again.
+    fn remove_entry(&mut self, parent: u64, name: &OsStr, dir: bool) -> io::Result<()> {
+        if !self.writable {
+            return Err(io::Error::from_raw_os_error(EROFS));
+        }
+        if Some(parent) == self.inodes_dir {
+            return Err(io::Error::from_raw_os_error(EPERM));
+        }
+        self.resolve_lazy_dir(parent)?;
+        let parent_node = self.inode.get(&parent).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        let found = match &parent_node.entry {
+            FSEntry::Dir(entries) => entries.borrow().iter().find(|e| OsStr::new(&*e.name.borrow()) == name).cloned(),
+            FSEntry::File(_) => return Err(io::Error::from_raw_os_error(ENOTDIR))
+        };
+        let node = match found {
+            Some(node) => node,
+            None => {
+                // An alias goes away alone, leaving the file it names in place
+                let aliases = self.aliases.get_mut(&parent).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+                let position = aliases.iter().position(|(alias, _)| OsStr::new(alias) == name).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+                if dir {
+                    return Err(io::Error::from_raw_os_error(ENOTDIR));
+                }
+                aliases.remove(position);
+                self.update_dir_listing(vec![parent_node]);
+                return Ok(());
+            }
+        };
+        match (&node.entry, dir) {
+            (FSEntry::Dir(_), false) => return Err(io::Error::from_raw_os_error(EISDIR)),
+            (FSEntry::File(_), true) => return Err(io::Error::from_raw_os_error(ENOTDIR)),
+            _ => {}
+        }
+        if Some(node.inode) == self.inodes_dir {
+            return Err(io::Error::from_raw_os_error(EPERM));
+        }
+        if let FSEntry::Dir(entries) = &node.entry {
+            self.resolve_lazy_dir(node.inode)?;
+            if !entries.borrow().is_empty() || self.aliases.get(&node.inode).map_or(false, |a| !a.is_empty()) {
+                return Err(io::Error::from_raw_os_error(ENOTEMPTY));
+            }
+        }
+
+        self.remove_descriptor_entry(&parent_node, &node.name.borrow());
+        self.remove_node(&node);
+        for dangling in self.fs_tree_root.flatten_strong() {
+            if let FSEntry::File(FSFileType::Ref(RefFSFileType { path, .. })) = &dangling.entry {
+                if self.ref_target(&dangling).is_err() {
+                    warn!("The ref {} leads to {}, which no longer exists", dangling.full_path(), path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the entry `name` of the directory with `inode` `parent` to `new_name` in the directory
+    /// with `inode` `new_parent`, keeping its inode. An existing file or empty directory at the
+    /// destination is replaced, as long as it's of the same kind. Fails with `EROFS` unless the
+    /// file system is writable.
+    pub fn rename_entry(&mut self, parent: u64, name: &OsStr, new_parent: u64, new_name: &OsStr) -> io::Result<()> {
+        if !self.writable {
+            return Err(io::Error::from_raw_os_error(EROFS));
+        }
+        if Some(parent) == self.inodes_dir || Some(new_parent) == self.inodes_dir {
+            return Err(io::Error::from_raw_os_error(EPERM));
+        }
+        let new_name = new_name.to_str().ok_or_else(|| io::Error::from_raw_os_error(EINVAL))?;
+        if new_name.len() > self.namelen as usize {
+            return Err(io::Error::from_raw_os_error(ENAMETOOLONG));
+        }
+        self.resolve_lazy_dir(parent)?;
+        self.resolve_lazy_dir(new_parent)?;
+        let dir = |fs: &JsonFS, inode: u64| -> io::Result<Rc<FSNode>> {
+            let node = fs.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+            match node.entry {
+                FSEntry::Dir(_) => Ok(node),
+                FSEntry::File(_) => Err(io::Error::from_raw_os_error(ENOTDIR))
+            }
+        };
+        let child = |dir: &FSNode, name: &OsStr| match &dir.entry {
+            FSEntry::Dir(entries) => entries.borrow().iter().find(|e| OsStr::new(&*e.name.borrow()) == name).cloned(),
+            FSEntry::File(_) => None
+        };
+        let (old_dir, new_dir) = (dir(self, parent)?, dir(self, new_parent)?);
+        let node = child(&old_dir, name).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if Some(node.inode) == self.inodes_dir {
+            return Err(io::Error::from_raw_os_error(EPERM));
+        }
+        if parent == new_parent && name == new_name {
+            return Ok(());
+        }
+        // A directory can't be moved under itself
+        let mut ancestor = Some(new_dir.clone());
+        while let Some(dir) = ancestor {
+            if dir.inode == node.inode {
+                return Err(io::Error::from_raw_os_error(EINVAL));
+            }
+            ancestor = dir.parent.borrow().upgrade();
+        }
+
+        let replaced = child(&new_dir, OsStr::new(new_name)).filter(|e| !Rc::ptr_eq(e, &node));
+        if let Some(replaced) = &replaced {
+            match (&node.entry, &replaced.entry) {
+                (FSEntry::Dir(_), FSEntry::File(_)) => return Err(io::Error::from_raw_os_error(ENOTDIR)),
+                (FSEntry::File(_), FSEntry::Dir(_)) => return Err(io::Error::from_raw_os_error(EISDIR)),
+                (FSEntry::Dir(_), FSEntry::Dir(entries)) => {
+                    self.resolve_lazy_dir(replaced.inode)?;
+                    if !entries.borrow().is_empty() || self.aliases.get(&replaced.inode).map_or(false, |a| !a.is_empty()) {
+                        return Err(io::Error::from_raw_os_error(ENOTEMPTY));
+                    }
+                },
+                _ => {}
+            }
+            if Some(replaced.inode) == self.inodes_dir {
+                return Err(io::Error::from_raw_os_error(EPERM));
+            }
+        }
+        // The other names of the destination directory can't differ only in case either
+        let lowercase_name = new_name.to_lowercase();
+        if let FSEntry::Dir(entries) = &new_dir.entry {
+            let taken = entries
+                .borrow()
+                .iter()
+                .filter(|e| !Rc::ptr_eq(e, &node) && replaced.as_ref().map_or(true, |r| !Rc::ptr_eq(e, r)))
+                .map(|e| e.name.borrow().clone())
+                .chain(self.aliases.get(&new_parent).into_iter().flatten().map(|(alias, _)| alias.clone()))
+                .any(|n| n.to_lowercase() == lowercase_name);
+            if taken {
+                return Err(io::Error::from_raw_os_error(EEXIST));
+            }
+        }
+
+        if let Some(replaced) = replaced {
+            self.remove_descriptor_entry(&new_dir, new_name);
+            self.remove_node(&replaced);
+        }
+        let descriptor = self.remove_descriptor_entry(&old_dir, &node.name.borrow());
+        if let (Some(descriptor), Some(m)) = (descriptor, self.dir_descriptor_mut(&new_dir)) {
+            m.insert(new_name.to_string(), descriptor);
+        }
+
+        if let FSEntry::Dir(entries) = &old_dir.entry {
+            entries.borrow_mut().retain(|e| !Rc::ptr_eq(e, &node));
+        }
+        *node.name.borrow_mut() = new_name.to_string();
+        *node.parent.borrow_mut() = Rc::downgrade(&new_dir);
+        if let FSEntry::Dir(entries) = &new_dir.entry {
+            entries.borrow_mut().push(node.clone());
+        }
+        // The `..` entry of a moved directory changes too
+        self.update_dir_listing(vec![old_dir, new_dir, node]);
+        self.dir_sizes.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Descriptor of the directory `dir`, when its entries are declared in the descriptor rather
+    /// than built from a source
+    /// Removes the entry `name` from the descriptor of `dir`, returning its descriptor
+    fn remove_descriptor_entry(&mut self, dir: &FSNode, name: &str) -> Option<serde_json::Value> {
+        let normalize_names = self.options.normalize_names;
+        let m = self.dir_descriptor_mut(dir)?;
+        let key = JsonFS::find_descriptor_key(m, name, normalize_names)?.to_string();
+        m.remove(&key)
+    }
+
+    fn dir_descriptor_mut(&mut self, dir: &FSNode) -> Option<&mut serde_json::Map<String, serde_json::Value>> {
+        let pointer = self.descriptor_pointer(&dir.full_path())?;
+        match self.descriptor.pointer_mut(&pointer) {
+            Some(serde_json::Value::Object(m)) if FSEntry::lazy_source(m).is_none() && FSEntry::jsontree_source(m).is_none() && !FSEntry::is_foreach(m) => Some(m),
+            _ => None
+        }
+    }
+
+    /// Checks the `access` of the node with `inode` for `mask`, a combination of `R_OK`, `W_OK` and
+    /// `X_OK`, or `F_OK` for its existence. Reading is allowed on all the nodes, writing only on
+    /// the raw files and the directories of a writable file system, and executing on the directories and the files
+    /// with an executable mode.
+    pub fn check_access(&self, inode: u64, mask: i32) -> io::Result<()> {
+        let node = self.inode.get(&inode).and_then(|n| n.upgrade()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if mask & W_OK != 0 {
+            match &node.entry {
+                FSEntry::File(FSFileType::Raw(_)) | FSEntry::Dir(_) if self.writable => {},
+                _ => return Err(io::Error::from_raw_os_error(EACCES))
+            }
+        }
+        if mask & X_OK != 0 {
+            if let FSEntry::File(_) = node.entry {
+                if self.get_node_attr(&node)?.perm & 0o111 == 0 {
+                    return Err(io::Error::from_raw_os_error(EACCES));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes the entries of the directory with `inode` to `add`, starting from `offset`, until
+    /// `add` returns `true` because the reply is full. Each entry comes with the offset of the
+    /// next one in the whole listing, so that a listing resumed from it continues after the entry.
+    pub fn list_dir<F>(&mut self, inode: u64, offset: i64, mut add: F) -> io::Result<()>
+        where F: FnMut(u64, i64, FileType, &OsStr) -> bool {
+        self.resolve_lazy_dir(inode)?;
+        let dir_entries = self.dir_listing.get(&inode).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if offset < 0 {
+            return Ok(());
+        }
+        for (i, (entry_inode, kind, name)) in dir_entries.iter().enumerate().skip(offset as usize) {
+            if add(*entry_inode, i as i64 + 1, *kind, name) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// File a `ref` file leads to, following the references to other `ref` files. Fails with
+    /// `ENOENT` for a missing target, `EISDIR` for a directory and `ELOOP` for a cycle.
+    fn ref_target(&self, node: &FSNode) -> io::Result<Rc<FSNode>> {
+        JsonFS::follow_ref(&self.fs_tree_root, node)
+    }
+
+    fn follow_ref(root: &Rc<FSNode>, node: &FSNode) -> io::Result<Rc<FSNode>> {
+        let mut visited = HashSet::new();
+        visited.insert(node.inode);
+        let mut path = match &node.entry {
+            FSEntry::File(FSFileType::Ref(reference)) => reference.path.clone(),
+            _ => return Err(io::Error::from_raw_os_error(EINVAL))
+        };
+        loop {
+            let target = root.walk(path).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+            if !visited.insert(target.inode) {
+                return Err(io::Error::from_raw_os_error(ELOOP));
+            }
+            path = match &target.entry {
+                FSEntry::File(FSFileType::Ref(reference)) => reference.path.clone(),
+                FSEntry::File(_) => return Ok(target),
+                FSEntry::Dir(_) => return Err(io::Error::from_raw_os_error(EISDIR))
+            };
+        }
+    }
+
+    /// Checks that the `ref` files of the tree `root` lead to a file
+    fn check_refs(root: &Rc<FSNode>) -> Result<(), DescriptorError> {
+        for node in root.flatten_strong() {
+            if let FSEntry::File(FSFileType::Ref(RefFSFileType { path, .. })) = &node.entry {
+                if let Err(e) = JsonFS::follow_ref(root, &node) {
+                    let reason = match e.raw_os_error() {
+                        Some(ELOOP) => format!("the ref to {} is part of a cycle", path),
+                        Some(EISDIR) => format!("the ref target {} is a directory", path),
+                        _ => format!("the ref target {} doesn't exist", path)
+                    };
+                    warn!("{}: {}", node.full_path(), reason);
+                    return Err(DescriptorError::At { path: node.full_path(), cause: Box::new(DescriptorError::invalid(reason)) });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Files under the directory of a `catdir` file, depth first in `readdir` order. The other
+    /// `catdir` files are skipped, so that none can include itself.
+    fn catdir_files(&self, cat_dir: &CatDirFSFileType) -> io::Result<Vec<Rc<FSNode>>> {
+        let dir = self.fs_tree_root.walk(cat_dir.path.clone()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if let FSEntry::File(_) = dir.entry {
+            return Err(io::Error::from_raw_os_error(ENOTDIR));
+        }
+        let mut files = vec![];
+        self.collect_catdir_files(dir.inode, &mut files);
+        Ok(files)
+    }
+
+    fn collect_catdir_files(&self, dir: u64, files: &mut Vec<Rc<FSNode>>) {
+        let entries = match self.dir_listing.get(&dir) {
+            Some(entries) => entries,
+            None => return
+        };
+        for (inode, _, name) in entries.iter() {
+            if name == "." || name == ".." {
+                continue;
+            }
+            if let Some(node) = self.inode.get(inode).and_then(|n| n.upgrade()) {
+                match &node.entry {
+                    FSEntry::Dir(_) => self.collect_catdir_files(node.inode, files),
+                    FSEntry::File(FSFileType::CatDir(_)) => {},
+                    FSEntry::File(_) => files.push(node)
+                }
+            }
+        }
+    }
+
+    /// Up to `size` bytes of `content` at `offset`
+    fn window(content: &[u8], offset: i64, size: u32) -> Vec<u8> {
+        let start = min(offset as usize, content.len());
+        let end = min(start + size as usize, content.len());
+        content[start..end].to_vec()
+    }
+
+    /// Content of a checksum sibling `node`: the checksum of the file `name` of its directory,
+    /// computed again when the inode, the size or the mtime of the file change
+    fn sibling_checksum(&self, node: &FSNode, name: &str) -> io::Result<Vec<u8>> {
+        let target = node.parent.borrow().upgrade()
+            .and_then(|dir| match &dir.entry {
+                FSEntry::Dir(entries) => entries.borrow().iter().find(|e| *e.name.borrow() == name).cloned(),
+                FSEntry::File(_) => None
+            })
+            .ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if let FSEntry::Dir(_) = target.entry {
+            return Err(io::Error::from_raw_os_error(EISDIR));
+        }
+        let attr = self.get_node_attr(&target)?;
+        let key = (target.inode, attr.size, attr.mtime);
+        if let Some((computed_for, checksum)) = self.sibling_checksums.borrow().get(&node.inode) {
+            if *computed_for == key {
+                return Ok(checksum.clone());
+            }
+        }
+        let checksum = checksum_of(FSFileReader::new(&SourceNode { source: self, node: &target })?)?;
+        self.sibling_checksums.borrow_mut().insert(node.inode, (key, checksum.clone()));
+        Ok(checksum)
+    }
+
+    /// Content of an `attrof` file: the attributes of its target, which can't be another `attrof` file
+    fn attrof_content(&self, attr_of: &AttrOfFSFileType) -> io::Result<Vec<u8>> {
+        let target = self.fs_tree_root.walk(attr_of.path.clone()).ok_or_else(|| io::Error::from_raw_os_error(ENOENT))?;
+        if let FSEntry::File(FSFileType::AttrOf(_)) = target.entry {
+            return Err(io::Error::from_raw_os_error(ELOOP));
+        }
+        Ok(AttrOfFSFileType::render(&self.get_node_attr(&target)?))
+    }
+
+    /// Lists the node at `path` as a JSON array of `{name, type, size, mode}` entries: the children
+    /// for a directory, or the node itself for a file
+    pub fn ls(&self, path: &str) -> Option<serde_json::Value> {
+        let node = self.fs_tree_root.walk(path.to_string())?;
+        let entries = match &node.entry {
+            FSEntry::Dir(entries) => entries.borrow().iter().filter_map(|e| self.ls_entry(e)).collect(),
+            FSEntry::File(_) => self.ls_entry(&node).into_iter().collect()
+        };
+        Some(serde_json::Value::Array(entries))
+    }
+
+    fn ls_entry(&self, node: &FSNode) -> Option<serde_json::Value> {
+        let attr = match self.get_node_attr(node) {
+            Ok(attr) => attr,
+            Err(e) => {
+                warn!("Cannot list {}: {}", node.full_path(), e);
+                return None;
+            }
+        };
+        Some(json!({
+            "name": *node.name.borrow(),
+            "type": match attr.kind {
+                FileType::Directory => "dir",
+                FileType::Symlink => "symlink",
+                _ => "file"
+            },
+            "size": attr.size,
+            "mode": format!("{:04o}", attr.perm)
+        }))
+    }
+
+    fn matching_permission(policy: &[(Pattern, u16)], entry: &FSNode) -> Option<u16> {
+        if policy.is_empty() {
+            return None;
+        }
+        let path = entry.full_path();
+        policy
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern.matches(&path))
+            .map(|(_, perm)| *perm)
     }
 }
 
 // https://github.com/libfuse/libfuse/blob/e16fdc06d7473f00499b6b03fb7bd06259a22135/include/fuse.h#L290
+/// Exports the files as they're read from the mountpoint, resolving the `ref`, `catdir` and `attrof` ones
+impl ExportSource for JsonFS {
+    fn get_attributes(&self, node: &FSNode) -> io::Result<FileAttr> {
+        self.get_node_attr(node)
+    }
+
+    fn read(&self, node: &FSNode, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let content = self.read_node(node, offset, buffer.len() as u32, None)?;
+        buffer[..content.len()].copy_from_slice(&content);
+        Ok(())
+    }
+}
+
 impl Filesystem for JsonFS {
 
     fn lookup(&mut self, _req: &Request, parent: u64, lookup_name: &OsStr, reply: ReplyEntry) {
-        info!("lookup for name: {} parent: {}", lookup_name.to_str().unwrap(), parent);
-        if let FSNode { name, entry: FSEntry::Dir(entries), .. } = self.inode.get(&parent).unwrap().upgrade().unwrap().borrow() {
-            info!("lookup in dir: {:?}, {:?}", name, entries);
-            if let Some(entry) = entries
-                .iter()
-                .find(|e| e.name == lookup_name.to_str().unwrap()) {
-                reply.entry(&TTL, &self.get_node_attr(&*entry), 0);
-                return;
-            }
+        self.apply_reloads();
+        info!("lookup for name: {} parent: {}", lookup_name.to_string_lossy(), parent);
+        match self.lookup_entry(parent, lookup_name) {
+            Ok(attr) => reply.entry(&self.ttl(attr.ino), &attr, 0),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
         }
-        reply.error(ENOENT);
     }
 
     /** Get file attributes.
@@ -109,15 +1835,22 @@ impl Filesystem for JsonFS {
      * may also be NULL if the file is open.
      */
     fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        self.apply_reloads();
         info!("getattr for {}", ino);
-        if let Some(entry) = self.inode.get(&ino).unwrap().upgrade() {
-            reply.attr(
-                &TTL,
-                &self.get_node_attr(&*entry)
-            );
-            return;
+        match self.inode_attr(ino) {
+            Ok(attr) => reply.attr(&self.ttl(ino), &attr),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /** Read symbolic link */
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        self.apply_reloads();
+        info!("readlink for {}", ino);
+        match self.read_link(ino) {
+            Ok(target) => reply.data(target.as_bytes()),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
         }
-        reply.error(ENOENT);
     }
 
     /** Read data from an open file
@@ -129,23 +1862,112 @@ impl Filesystem for JsonFS {
      * value of the read system call will reflect the return value of
      * this operation.
      */
-    fn read(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, size: u32, reply: ReplyData) {
+    fn read(&mut self, _req: &Request, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        self.apply_reloads();
         info!("read for {} at offset {}", ino, offset);
-        if let FSNode { entry: FSEntry::File(file_type), .. }  = self.inode.get(&ino).unwrap().upgrade().unwrap().borrow() {
-            // Create the buf
-            let mut buffer = vec![0; size as usize];
+        match self.read_handle(ino, fh, offset, size) {
+            Ok(buffer) => reply.data(&buffer[..]),
+            Err(e) => {
+                info!("Error while reading: {:?}", e);
+                reply.error(e.raw_os_error().unwrap_or(EIO))
+            }
+        }
+    }
 
-            let read_result = file_type.ops().read(offset, &mut buffer[..]);
+    /// Open a file, materializing its content for the reads of the returned handle
+    fn open(&mut self, _req: &Request, ino: u64, _flags: u32, reply: ReplyOpen) {
+        self.apply_reloads();
+        info!("open for {}", ino);
+        match self.open_inode(ino) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
 
-            // Read
-            if let Ok(()) = read_result {
-                reply.data(&buffer[..]);
-                return;
-            } else {
-                info!("Error while reading: {:?}", read_result.unwrap_err())
-            }
+    /// Release an open file, freeing its handle
+    fn release(&mut self, _req: &Request, ino: u64, fh: u64, _flags: u32, _lock_owner: u64, _flush: bool, reply: ReplyEmpty) {
+        info!("release for {}", ino);
+        self.release_handle(fh);
+        reply.ok();
+    }
+
+    /** Write data to an open file
+     *
+     * Write should return exactly the number of bytes requested
+     * except on error.
+     */
+    fn write(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, data: &[u8], _flags: u32, reply: ReplyWrite) {
+        self.apply_reloads();
+        info!("write for {} at offset {} of {} bytes", ino, offset, data.len());
+        match self.write_inode(ino, offset, data) {
+            Ok(written) => reply.written(written),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Set file attributes. Only the size of the raw files can be changed, for truncation; the
+    /// other attributes are left as they are.
+    fn setattr(&mut self, _req: &Request, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, size: Option<u64>, _atime: Option<SystemTime>, _mtime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
+        self.apply_reloads();
+        info!("setattr for {} with size {:?}", ino, size);
+        let result = match size {
+            Some(size) => self.set_inode_size(ino, size),
+            None => self.inode_attr(ino)
+        };
+        match result {
+            Ok(attr) => reply.attr(&self.ttl(ino), &attr),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Create a directory
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
+        self.apply_reloads();
+        info!("mkdir for name: {} parent: {}", name.to_string_lossy(), parent);
+        match self.make_dir(parent, name) {
+            Ok(attr) => reply.entry(&self.ttl(attr.ino), &attr, 0),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Remove a file
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.apply_reloads();
+        info!("unlink for name: {} parent: {}", name.to_string_lossy(), parent);
+        match self.unlink_entry(parent, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Remove a directory
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.apply_reloads();
+        info!("rmdir for name: {} parent: {}", name.to_string_lossy(), parent);
+        match self.remove_dir(parent, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Rename a file or a directory
+    fn rename(&mut self, _req: &Request, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, reply: ReplyEmpty) {
+        self.apply_reloads();
+        info!("rename for name: {} parent: {} to name: {} parent: {}", name.to_string_lossy(), parent, newname.to_string_lossy(), newparent);
+        match self.rename_entry(parent, name, newparent, newname) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Create and open a file
+    fn create(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _flags: u32, reply: ReplyCreate) {
+        self.apply_reloads();
+        info!("create for name: {} parent: {}", name.to_string_lossy(), parent);
+        match self.create_file(parent, name) {
+            Ok((attr, fh)) => reply.created(&self.ttl(attr.ino), &attr, 0, fh, 0),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
         }
-        reply.error(ENOENT);
     }
 
     /** Read directory
@@ -164,19 +1986,86 @@ impl Filesystem for JsonFS {
      * '1'.
      */
     fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        self.apply_reloads();
         info!("readdir for {} and offset {}", ino, offset);
-        if let Some(dir_entries) = self.dir_listing.get(&ino) {
-            if offset < dir_entries.len().try_into().unwrap() {
-                dir_entries
-                    .iter()
-                    .skip(offset as usize)
-                    .enumerate()
-                    .find(|(i, (inode, f, s))| reply.add(*inode, *i as i64 + 1, *f, s));
-            }
-            reply.ok();
-            return;
+        match self.list_dir(ino, offset, |inode, next_offset, kind, name| reply.add(inode, next_offset, kind, name)) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /// Check file access permissions
+    fn access(&mut self, _req: &Request, ino: u64, mask: u32, reply: ReplyEmpty) {
+        self.apply_reloads();
+        info!("access for {} with mask {:o}", ino, mask);
+        match self.check_access(ino, mask as i32) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /** Get file system statistics
+     *
+     * The 'f_favail', 'f_fsid' and 'f_flag' fields are ignored
+     */
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        self.apply_reloads();
+        info!("statfs");
+        let statfs = self.statfs_info();
+        reply.statfs(
+            statfs.blocks,
+            statfs.bfree,
+            statfs.bavail,
+            statfs.files,
+            statfs.ffree,
+            statfs.bsize,
+            statfs.namelen,
+            statfs.frsize
+        );
+    }
+
+    /// Clean up filesystem. Called on filesystem exit.
+    fn destroy(&mut self, _req: &Request) {
+        info!("destroy");
+        self.options.shutdown.store(true, Ordering::SeqCst);
+        if let Err(e) = self.persist() {
+            warn!("Cannot write the descriptor back: {}", e);
+        }
+    }
+
+    /** Get an extended attribute
+     *
+     * With a zero `size`, only the size of the value is replied
+     */
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        self.apply_reloads();
+        info!("getxattr {} for {}", name.to_string_lossy(), ino);
+        match self.xattrs(ino) {
+            Ok(xattrs) => match xattrs.get(&*name.to_string_lossy()) {
+                Some(value) => JsonFS::reply_xattr(value, size, reply),
+                None => reply.error(ENODATA)
+            },
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
+        }
+    }
+
+    /** List extended attribute names
+     *
+     * The names are replied NUL-terminated. With a zero `size`, only the size of the list is replied
+     */
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        self.apply_reloads();
+        info!("listxattr for {}", ino);
+        match self.xattrs(ino) {
+            Ok(xattrs) => {
+                let names: Vec<u8> = xattrs
+                    .keys()
+                    .flat_map(|name| name.bytes().chain(iter::once(0)))
+                    .collect();
+                JsonFS::reply_xattr(&names, size, reply)
+            },
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(EIO))
         }
-        reply.error(ENOENT);
     }
 
 }
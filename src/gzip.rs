@@ -0,0 +1,87 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::io::Read;
+use flate2::read::GzDecoder;
+use fuse::{FileType, FileAttr};
+use libc::EIO;
+use log::warn;
+
+/// Serves gzip content embedded in the descriptor, decompressed, e.g. `gzip:H4sIAAAAAAAA...` with
+/// the compressed bytes as base64, or `{"type": "gzip", "path": "/x.gz"}` for a local file read
+/// when the tree is built. The content is decompressed on first access and cached.
+#[derive(Debug)]
+pub struct GzipFSFileType {
+    pub compressed: Vec<u8>,
+    /// When the file was built, reported as its times
+    pub built: SystemTime,
+    inflated: RefCell<Option<Vec<u8>>>
+}
+
+impl GzipFSFileType {
+    pub fn new(compressed: Vec<u8>) -> GzipFSFileType {
+        GzipFSFileType {
+            compressed,
+            built: SystemTime::now(),
+            inflated: RefCell::new(None)
+        }
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<GzipFSFileType, DescriptorError> {
+        Ok(GzipFSFileType::new(base64::decode(encoded)?))
+    }
+
+    pub fn from_path(path: &str) -> Result<GzipFSFileType, DescriptorError> {
+        let compressed = std::fs::read(path).map_err(|e| {
+            warn!("Cannot read {}: {}", path, e);
+            DescriptorError::invalid(format!("cannot read {}: {}", path, e))
+        })?;
+        Ok(GzipFSFileType::new(compressed))
+    }
+
+    fn with_inflated<T>(&self, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if self.inflated.borrow().is_none() {
+            let mut inflated = vec![];
+            GzDecoder::new(&self.compressed[..]).read_to_end(&mut inflated).map_err(|e| {
+                warn!("Cannot decompress gzip content: {}", e);
+                io::Error::from_raw_os_error(EIO)
+            })?;
+            *self.inflated.borrow_mut() = Some(inflated);
+        }
+        Ok(f(self.inflated.borrow().as_ref().unwrap()))
+    }
+}
+
+impl FSFileTypeOps for GzipFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_inflated(|inflated| inflated.len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.with_inflated(|inflated| {
+            let start = min(offset as usize, inflated.len());
+            let end = min(start + buffer.len(), inflated.len());
+            buffer[..end - start].copy_from_slice(&inflated[start..end]);
+        })
+    }
+}
@@ -1,40 +1,288 @@
 use super::*;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 use fuse::{FileType, FileAttr};
-use reqwest::StatusCode;
+use reqwest::{StatusCode, RequestBuilder, Response};
+use reqwest::header::{ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_RANGE, LAST_MODIFIED, RANGE};
 use std::io::Read;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, Condvar, MutexGuard};
+use std::cmp::{min, max};
 use log::info;
 
+/// Counting semaphore bounding the concurrent requests of the http files sharing it. Requests
+/// beyond the limit wait for a running one to complete.
+#[derive(Debug)]
+pub struct HttpLimiter {
+    max_requests: usize,
+    running: Mutex<usize>,
+    released: Condvar
+}
+
+/// A running request, counted by its `HttpLimiter` until dropped
+pub struct HttpPermit<'a> {
+    limiter: &'a HttpLimiter
+}
+
+impl HttpLimiter {
+    /// Limits the concurrent requests to `max_requests`, at least one
+    pub fn new(max_requests: usize) -> HttpLimiter {
+        HttpLimiter {
+            max_requests: max(1, max_requests),
+            running: Mutex::new(0),
+            released: Condvar::new()
+        }
+    }
+
+    pub fn acquire(&self) -> HttpPermit {
+        let mut running: MutexGuard<usize> = self.running.lock().unwrap();
+        while *running >= self.max_requests {
+            running = self.released.wait(running).unwrap();
+        }
+        *running += 1;
+        HttpPermit { limiter: self }
+    }
+}
+
+impl<'a> Drop for HttpPermit<'a> {
+    fn drop(&mut self) {
+        *self.limiter.running.lock().unwrap() -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+/// Response of the upstream kept by an http file with a cache TTL
+#[derive(Debug)]
+struct CachedResponse {
+    /// Whole content, when fetched by a GET rather than a HEAD
+    body: Option<Vec<u8>>,
+    content_length: u64,
+    modified: SystemTime,
+    /// Whether the upstream advertised `Accept-Ranges: bytes`
+    accept_ranges: bool,
+    fetched: Instant
+}
+
 #[derive(Debug)]
-#[derive(Eq, PartialEq)]
-#[derive(Hash)]
 pub struct HttpFSFileType {
-    pub address: String
+    pub address: String,
+    /// Value of the `Accept-Encoding` header sent with each request. `identity` makes the
+    /// upstream serve the content uncompressed, so `Content-Length` matches the served bytes.
+    pub accept_encoding: Option<String>,
+    /// Modification time reported by the last response with a valid `Last-Modified`. When the
+    /// upstream never sent one, the time of the first response.
+    last_modified: Cell<Option<SystemTime>>,
+    limiter: Option<Arc<HttpLimiter>>,
+    /// How long a response is reused before requesting the upstream again. Without it each
+    /// `getattr` sends a HEAD and each `read` a ranged GET. With it, the reads of an upstream
+    /// not advertising `Accept-Ranges: bytes` are served from the whole content.
+    cache_ttl: Option<Duration>,
+    cache: RefCell<Option<CachedResponse>>
 }
 
 impl HttpFSFileType {
     pub fn new(pointer: String) -> HttpFSFileType {
         HttpFSFileType {
-            address: pointer
+            address: pointer,
+            accept_encoding: None,
+            last_modified: Cell::new(None),
+            limiter: None,
+            cache_ttl: None,
+            cache: RefCell::new(None)
         }
     }
-}
 
-impl FSFileTypeOps for HttpFSFileType {
-    fn get_attributes(&self, inode: u64) -> FileAttr {
+    /// Reuses the responses of the upstream for `cache_ttl`. Unless the upstream accepts ranges,
+    /// the reads are served from the whole content fetched by a single GET.
+    pub fn with_cache_ttl(mut self, cache_ttl: Option<Duration>) -> HttpFSFileType {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Makes the requests wait for a permit of `limiter`, shared with other http files
+    pub fn with_limiter(mut self, limiter: Option<Arc<HttpLimiter>>) -> HttpFSFileType {
+        self.limiter = limiter;
+        self
+    }
+
+    fn permit(&self) -> Option<HttpPermit> {
+        self.limiter.as_ref().map(|limiter| limiter.acquire())
+    }
+
+    pub fn with_accept_encoding(mut self, accept_encoding: Option<String>) -> HttpFSFileType {
+        self.accept_encoding = accept_encoding;
+        self
+    }
+
+    fn with_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.accept_encoding {
+            Some(accept_encoding) => request.header(ACCEPT_ENCODING, accept_encoding.as_str()),
+            None => request
+        }
+    }
+
+    fn head(&self) -> io::Result<Response> {
+        let _permit = self.permit();
+        let client = reqwest::Client::new();
+        self.with_headers(client.head(&self.address)).send().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+    }
+
+    fn modified(&self, resp: &Response) -> SystemTime {
+        let last_modified = resp.headers()
+            .get(LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+        let mtime = match (last_modified, self.last_modified.get()) {
+            (Some(mtime), _) | (None, Some(mtime)) => mtime,
+            (None, None) => SystemTime::now()
+        };
+        self.last_modified.set(Some(mtime));
+        mtime
+    }
+
+    /// Applies `f` to the cached response, when fresh and holding the body if `with_body`.
+    /// An expired response is dropped.
+    fn cached<T, F: FnOnce(&CachedResponse) -> T>(&self, with_body: bool, f: F) -> Option<T> {
+        let ttl = self.cache_ttl?;
+        let expired = match &*self.cache.borrow() {
+            Some(cached) if cached.fetched.elapsed() < ttl => {
+                if cached.body.is_some() || !with_body {
+                    return Some(f(cached));
+                }
+                false
+            },
+            Some(_) => true,
+            None => false
+        };
+        if expired {
+            *self.cache.borrow_mut() = None;
+        }
+        None
+    }
+
+    fn store(&self, body: Option<Vec<u8>>, content_length: u64, modified: SystemTime, resp: &Response) {
+        if self.cache_ttl.is_some() {
+            let accept_ranges = resp.headers()
+                .get(ACCEPT_RANGES)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().eq_ignore_ascii_case("bytes"))
+                .unwrap_or(false);
+            *self.cache.borrow_mut() = Some(CachedResponse { body, content_length, modified, accept_ranges, fetched: Instant::now() });
+        }
+    }
+
+    /// Size and modification time of the content, from the cache or a HEAD
+    fn metadata(&self) -> io::Result<(u64, SystemTime)> {
+        if let Some(metadata) = self.cached(false, |cached| (cached.content_length, cached.modified)) {
+            return Ok(metadata);
+        }
+        let res = self.head()?;
+        let size = res.content_length().unwrap_or(0);
+        let mtime = self.modified(&res);
+        self.store(None, size, mtime, &res);
+        Ok((size, mtime))
+    }
+
+    /// Whether the upstream advertises `Accept-Ranges: bytes`, from the cache or a HEAD
+    fn accepts_ranges(&self) -> io::Result<bool> {
+        if self.cached(false, |_| ()).is_none() {
+            self.metadata()?;
+        }
+        Ok(self.cached(false, |cached| cached.accept_ranges).unwrap_or(false))
+    }
+
+    /// Serves the read from the cached content, fetching it with a GET when missing or expired
+    fn read_cached(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        if self.cached(true, |_| ()).is_none() {
+            let _permit = self.permit();
+            let client = reqwest::Client::new();
+            let mut resp = self.with_headers(client.get(&self.address))
+                .send()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+            if resp.status() != StatusCode::OK {
+                info!("Response received, but with status code {:?}", resp.status());
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "Unexpected status code"));
+            }
+            let mut body: Vec<u8> = vec![];
+            resp.read_to_end(&mut body)?;
+            info!("Cached response of length {:?}", body.len());
+            let mtime = self.modified(&resp);
+            let len = body.len() as u64;
+            self.store(Some(body), len, mtime, &resp);
+        }
+
+        if let Some(CachedResponse { body: Some(body), .. }) = &*self.cache.borrow() {
+            let start = min(offset as usize, body.len());
+            let end = min(start + buffer.len(), body.len());
+            buffer[..end - start].copy_from_slice(&body[start..end]);
+        }
+        Ok(())
+    }
+
+    /// Reads with ranged GETs. A server may reply `206 Partial Content` with a shorter range
+    /// than requested, in which case the rest of the window is requested again.
+    fn read_ranges(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
         let client = reqwest::Client::new();
-        let res = client.head(&self.address).send().unwrap();
+        let mut filled = 0;
+
+        while filled < buffer.len() {
+            let _permit = self.permit();
+            let start = offset as u64 + filled as u64;
+            let end = offset as u64 + buffer.len() as u64 - 1;
+            let mut resp = self.with_headers(client.get(&self.address))
+                .header(RANGE, format!("bytes={}-{}", start, end))
+                .send()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+
+            if resp.status() == StatusCode::PARTIAL_CONTENT {
+                if content_range_start(&resp) != Some(start) {
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, "Unexpected Content-Range in partial response"));
+                }
+                let mut chunk: Vec<u8> = vec![];
+                resp.read_to_end(&mut chunk)?;
+
+                let len = min(chunk.len(), buffer.len() - filled);
+                info!("Received {} bytes of the range {}-{}", len, start, end);
+                if len == 0 {
+                    break;
+                }
+                buffer[filled..filled + len].copy_from_slice(&chunk[..len]);
+                filled += len;
+            } else if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                // The window starts past the end of the content
+                break;
+            } else if resp.status() ==  StatusCode::OK {
+                // The server ignored the range and sent the whole content
+                let mut body: Vec<u8> = vec![];
+                resp.read_to_end(&mut body)?;
+
+                info!("Received response of length {:?}, content-length: {:?}", body.len(), resp.content_length());
+
+                let body_start = min(start as usize, body.len());
+                let body_end = min(body_start + buffer.len() - filled, body.len());
+                buffer[filled..filled + body_end - body_start].copy_from_slice(&body[body_start..body_end]);
 
-        let size: u64 = res.content_length().unwrap_or(0);
+                return Ok(());
+            } else {
+                info!("Response received, but with status code {:?}", resp.status());
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "Shit happens"));
+            }
+        }
+
+        Ok(())
+    }
+}
 
-        FileAttr {
+impl FSFileTypeOps for HttpFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let (size, mtime) = self.metadata()?;
+        Ok(FileAttr {
             ino: inode,
             size,
-            blocks: 1,
-            atime: SystemTime::now(),
-            mtime: SystemTime::now(),
-            ctime: SystemTime::now(),
-            crtime: SystemTime::now(),
+            blocks: blocks(size, self.block_size()),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
             kind: FileType::RegularFile,
             perm: 0o644,
             nlink: 1,
@@ -42,29 +290,32 @@ impl FSFileTypeOps for HttpFSFileType {
             gid: nix::unistd::getgid().into(),
             rdev: 0,
             flags: 0
-        }
+        })
     }
 
-    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
-        let mut resp = reqwest::get(&self.address).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-
-        if resp.status() ==  StatusCode::OK {
-            let off: usize = offset as usize;
-            let mut body: Vec<u8> = vec![];
-            resp.read_to_end(&mut body);
-
-            info!("Received response of length {:?}, content-length: {:?}", body.len(), resp.content_length());
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.0)
+    }
 
-            if buffer.len() > body.len() - off {
-                buffer[..body.len() - off].copy_from_slice(&body[offset as usize..])
-            } else {
-                buffer.copy_from_slice(&body[offset as usize..buffer.len()])
-            }
+    fn cache_on_open(&self) -> bool {
+        true
+    }
 
-            Ok(())
-        } else {
-            info!("Response received, but with status code {:?}", resp.status());
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "Shit happens"))
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        if self.cache_ttl.is_some() && !self.accepts_ranges()? {
+            return self.read_cached(offset, buffer);
         }
+        self.read_ranges(offset, buffer)
+    }
+}
+
+/// First byte of the range served by a `206 Partial Content` response, from its
+/// `Content-Range: bytes <first>-<last>/<length>` header
+fn content_range_start(resp: &Response) -> Option<u64> {
+    let content_range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?.trim();
+    if !content_range.starts_with("bytes ") {
+        return None;
     }
+    let range = &content_range["bytes ".len()..];
+    range[..range.find('-')?].trim().parse().ok()
 }
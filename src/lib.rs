@@ -1,25 +1,97 @@
 pub mod raw;
 pub mod local;
 pub mod http;
+pub mod cond;
+pub mod sqlite;
+pub mod timeout;
+pub mod fd;
+pub mod checksum;
+pub mod fault;
+pub mod log_buffer;
+pub mod stdin;
+pub mod lint;
+pub mod rotate;
+pub mod blobref;
+pub mod sized;
+pub mod unixsock;
+pub mod xattr;
+pub mod proc;
+pub mod seq;
+pub mod delay;
+pub mod filter;
+pub mod dedup;
+pub mod window;
+pub mod base64;
+pub mod random;
+pub mod pipeline;
+pub mod mode;
+pub mod symlink;
+pub mod attrof;
+pub mod fs;
+pub mod export;
+pub mod quota;
+pub mod catdir;
+pub mod sha256;
+pub mod command;
+pub mod env;
+pub mod gzip;
+pub mod reference;
 
 use std::error::Error;
 use std::fmt::{Display, Formatter, Debug};
 use std::{fmt, iter, io};
 use std::path::{Path, Component};
+use std::cmp::max;
 use std::ffi::OsStr;
 use raw::RawFSFileType;
 use local::LocalFSFileType;
 use fuse::FileAttr;
 use std::rc::{Rc, Weak};
 use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::collections::HashMap;
-use std::borrow::Borrow;
-use crate::http::HttpFSFileType;
+use crate::http::{HttpFSFileType, HttpLimiter};
+use crate::cond::CondFSFileType;
+use crate::sqlite::SqliteFSFileType;
+use crate::timeout::TimeoutFSFileType;
+use crate::fd::FdFSFileType;
+use crate::checksum::{Crc32FSFileType, CHECKSUM_SUFFIX};
+use crate::fault::FaultFSFileType;
+use crate::log_buffer::LogFSFileType;
+use crate::stdin::StdinFSFileType;
+use crate::rotate::RotateFSFileType;
+use crate::blobref::BlobRefFSFileType;
+use crate::sized::SizedFSFileType;
+use crate::unixsock::{UnixSockFSFileType, DEFAULT_UNIXSOCK_TIMEOUT};
+use crate::proc::ProcFSFileType;
+use crate::seq::SeqFSFileType;
+use crate::delay::DelayFSFileType;
+use crate::filter::FilterFSFileType;
+use crate::dedup::SharedFSFileType;
+use crate::window::WindowFSFileType;
+use crate::base64::Base64FSFileType;
+use crate::random::RandomFSFileType;
+use crate::pipeline::{PipelineFSFileType, Step};
+use crate::mode::ModeFSFileType;
+use crate::symlink::SymlinkFSFileType;
+use crate::attrof::AttrOfFSFileType;
+use crate::quota::QuotaFSFileType;
+use crate::catdir::CatDirFSFileType;
+use crate::command::CommandFSFileType;
+use crate::env::EnvFSFileType;
+use crate::gzip::GzipFSFileType;
+use crate::reference::RefFSFileType;
+use std::time::Duration;
+use std::fs::read_dir;
+use log::warn;
+use serde_json::json;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Debug)]
 pub struct FSNode {
     pub inode: u64,
-    pub name: String,
+    pub name: RefCell<String>,
     pub parent: RefCell<Weak<FSNode>>,
     pub entry: FSEntry
 }
@@ -27,76 +99,177 @@ pub struct FSNode {
 #[derive(Debug)]
 pub enum FSEntry {
     File(FSFileType),
-    Dir(Vec<Rc<FSNode>>)
+    Dir(RefCell<Vec<Rc<FSNode>>>)
 }
 
 #[derive(Debug)]
 pub enum FSFileType {
     Raw(RawFSFileType),
     Local(LocalFSFileType),
-    Http(HttpFSFileType)
+    Http(HttpFSFileType),
+    Cond(CondFSFileType),
+    Sqlite(SqliteFSFileType),
+    Timeout(TimeoutFSFileType),
+    Fd(FdFSFileType),
+    Crc32(Crc32FSFileType),
+    Fault(FaultFSFileType),
+    Log(LogFSFileType),
+    Stdin(StdinFSFileType),
+    Rotate(RotateFSFileType),
+    BlobRef(BlobRefFSFileType),
+    Sized(SizedFSFileType),
+    UnixSock(UnixSockFSFileType),
+    Proc(ProcFSFileType),
+    Seq(SeqFSFileType),
+    Delay(DelayFSFileType),
+    Filter(FilterFSFileType),
+    Shared(SharedFSFileType),
+    Window(WindowFSFileType),
+    Base64(Base64FSFileType),
+    Random(RandomFSFileType),
+    Pipeline(PipelineFSFileType),
+    Mode(ModeFSFileType),
+    Symlink(SymlinkFSFileType),
+    AttrOf(AttrOfFSFileType),
+    Quota(QuotaFSFileType),
+    CatDir(CatDirFSFileType),
+    Command(CommandFSFileType),
+    Env(EnvFSFileType),
+    Gzip(GzipFSFileType),
+    Ref(RefFSFileType)
 }
 
 impl FSNode {
     pub fn new(descriptor: serde_json::Value) -> Result<(Rc<FSNode>, HashMap<u64, Weak<FSNode>>), DescriptorError> {
-        let fs_tree = FSNode::_new(&mut 0, String::new(), descriptor)?;
-        let map: HashMap<u64, Weak<FSNode>> = fs_tree
-            .flatten()
-            .into_iter()
-            .map(|e| {
-                let inode = e.upgrade().unwrap().inode;
-                (inode, e)
-            })
-            .collect();
+        FSNode::new_with_options(descriptor, &BuildOptions::default())
+    }
 
+    pub fn new_with_options(descriptor: serde_json::Value, options: &BuildOptions) -> Result<(Rc<FSNode>, HashMap<u64, Weak<FSNode>>), DescriptorError> {
+        let mut map = HashMap::new();
+        let fs_tree = FSNode::new_with_inodes(descriptor, options, &mut map)?;
         Ok((fs_tree, map))
     }
 
-    fn _new(parent_inode: &mut u64, name: String, descriptor: serde_json::Value) -> Result<Rc<FSNode>, DescriptorError> {
-        use serde_json::value::Value::*;
+    /// Builds the tree like `new_with_options`, adding its nodes to `inodes` while building them
+    /// rather than walking the tree again once built, which matters for huge descriptors
+    pub fn new_with_inodes(descriptor: serde_json::Value, options: &BuildOptions, inodes: &mut HashMap<u64, Weak<FSNode>>) -> Result<Rc<FSNode>, DescriptorError> {
+        let (descriptor, options) = options.with_directives(descriptor)?;
+        let descriptor = options.with_local_prefix(descriptor);
+        FSNode::_new(&mut 0, inodes, String::new(), descriptor, &options)
+    }
+
+    /// Builds a tree from a manifest, an array of `{"path": "dir/file.txt", "size": 3, "url": "http://..."}`
+    /// entries, where `url` can be replaced by `file` pointing to a local path. The declared sizes
+    /// are reported as they are, so content is fetched only on read.
+    pub fn from_manifest(manifest: &serde_json::Value) -> Result<(Rc<FSNode>, HashMap<u64, Weak<FSNode>>), DescriptorError> {
+        FSNode::new(FSNode::manifest_descriptor(manifest)?)
+    }
+
+    fn manifest_descriptor(manifest: &serde_json::Value) -> Result<serde_json::Value, DescriptorError> {
+        let mut root = serde_json::Map::new();
+        for entry in manifest.as_array().ok_or_else(|| DescriptorError::invalid("the manifest is not an array"))? {
+            let path = entry.get("path").and_then(|p| p.as_str()).ok_or_else(|| DescriptorError::field("path"))?;
+            let mut file = serde_json::Map::new();
+            match (entry.get("url"), entry.get("file")) {
+                (Some(url), None) => {
+                    file.insert("type".to_string(), serde_json::Value::String("http".to_string()));
+                    file.insert("url".to_string(), url.clone());
+                },
+                (None, Some(local)) => {
+                    file.insert("type".to_string(), serde_json::Value::String("file".to_string()));
+                    file.insert("path".to_string(), local.clone());
+                },
+                _ => return Err(DescriptorError::invalid(format!("manifest entry {} needs either a url or a file", path)))
+            }
+            if let Some(size) = entry.get("size") {
+                file.insert("size".to_string(), size.clone());
+            }
+
+            let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+            let name = components.pop().ok_or_else(|| DescriptorError::invalid(format!("manifest path {} has no name", path)))?;
+            let mut dir = &mut root;
+            for component in components {
+                let child = dir
+                    .entry(component.to_string())
+                    .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+                dir = match child {
+                    serde_json::Value::Object(m) if !FSFileType::is_file_object(m) => m,
+                    _ => return Err(DescriptorError::invalid(format!("manifest path {} goes through a file", path)))
+                };
+            }
+            if dir.contains_key(name) {
+                return Err(DescriptorError::invalid(format!("manifest path {} is declared more than once", path)));
+            }
+            dir.insert(name.to_string(), serde_json::Value::Object(file));
+        }
+        Ok(serde_json::Value::Object(root))
+    }
 
+    fn _new(parent_inode: &mut u64, inodes: &mut HashMap<u64, Weak<FSNode>>, name: String, descriptor: serde_json::Value, options: &BuildOptions) -> Result<Rc<FSNode>, DescriptorError> {
         *parent_inode = *parent_inode + 1;
         let this_node_inode = *parent_inode;
 
-        // Create the entry of this node
-        let entry = match descriptor {
-            Object(m) => FSEntry::create_directory(parent_inode, m),
-            String(s) => FSEntry::create_file(s),
-            _ => Err(DescriptorError)
-        }?;
+        // Create the entry of this node, the errors of the subtree being located under its name
+        let entry = FSEntry::create_entry(parent_inode, inodes, descriptor, options).map_err(|e| e.within(&name))?;
 
         // Create this node
         let node = Rc::new(FSNode {
             inode: this_node_inode,
-            name,
+            name: RefCell::new(name),
             parent: RefCell::new(Weak::new()),
             entry
         });
 
         // Link the parents
         if let FSEntry::Dir(childs) = &node.entry {
-            for child in childs {
+            for child in childs.borrow().iter() {
                 *child.parent.borrow_mut() = Rc::downgrade(&node)
             }
         }
+        inodes.insert(this_node_inode, Rc::downgrade(&node));
 
         Ok(node)
     }
 
-    pub fn walk(&self, path: String) -> Option<&FSNode> {
+    /// Absolute path of this node from the root of the tree, e.g. `/nested/file.txt`
+    pub fn full_path(&self) -> String {
+        match self.parent.borrow().upgrade() {
+            Some(parent) => {
+                let parent_path = parent.full_path();
+                if parent_path.ends_with('/') {
+                    format!("{}{}", parent_path, self.name.borrow())
+                } else {
+                    format!("{}/{}", parent_path, self.name.borrow())
+                }
+            },
+            None => String::from("/")
+        }
+    }
+
+    /// Number of nodes of the subtree rooted in this node, itself included, like `flatten().len()`
+    /// without collecting them
+    pub fn count(&self) -> usize {
+        match &self.entry {
+            FSEntry::Dir(entries) => 1 + entries.borrow().iter().map(|e| e.count()).sum::<usize>(),
+            FSEntry::File(_) => 1
+        }
+    }
+
+    pub fn walk(self: &Rc<Self>, path: String) -> Option<Rc<FSNode>> {
         Path::new(&path)
             .components()
             .skip(1)
-            .fold(Some(self), |o, c| o.and_then(|e| e._walk(c)))
+            .fold(Some(self.clone()), |o, c| o.and_then(|e| e._walk(c)))
     }
 
-    fn _walk(&self, component: Component) -> Option<&FSNode> {
+    fn _walk(&self, component: Component) -> Option<Rc<FSNode>> {
         match (component, self) {
             (Component::Normal(c), FSNode { inode:_, name: _, parent: _, entry: FSEntry::Dir(entries) }) =>
                 entries
+                    .borrow()
                     .iter()
-                    .find(|e| OsStr::new(&e.name) == c)
-                    .map(|r| r.borrow()),
+                    .find(|e| OsStr::new(&*e.name.borrow()) == c)
+                    .cloned(),
             (_, _) => None
         }
     }
@@ -104,6 +277,9 @@ impl FSNode {
 
 pub trait Flatten<T> {
     fn flatten(&self) -> Vec<Weak<T>>;
+
+    /// Like `flatten`, but returns strong references, for when the tree is known to be alive
+    fn flatten_strong(&self) -> Vec<Rc<T>>;
 }
 
 impl Flatten<FSNode> for Rc<FSNode> {
@@ -112,48 +288,646 @@ impl Flatten<FSNode> for Rc<FSNode> {
         match &self.entry {
             FSEntry::Dir (entries) =>
                 iter::once(Rc::downgrade(self))
-                    .chain(entries.iter().flat_map(|e| e.flatten()))
+                    .chain(entries.borrow().iter().flat_map(|e| e.flatten()))
                     .collect(),
             FSEntry::File(_) => vec![Rc::downgrade(self)]
         }
     }
 
+    fn flatten_strong(&self) -> Vec<Rc<FSNode>> {
+        match &self.entry {
+            FSEntry::Dir (entries) =>
+                iter::once(self.clone())
+                    .chain(entries.borrow().iter().flat_map(|e| e.flatten_strong()))
+                    .collect(),
+            FSEntry::File(_) => vec![self.clone()]
+        }
+    }
+
 }
 
 impl FSEntry {
 
-    fn create_file(file_descriptor: String) -> Result<FSEntry, DescriptorError> {
-        let (descriptor_type, descriptor_pointer) = file_descriptor
-            .split_at(file_descriptor.find(':').ok_or(DescriptorError)?);
+    fn create_entry(parent_inode: &mut u64, inodes: &mut HashMap<u64, Weak<FSNode>>, descriptor: serde_json::Value, options: &BuildOptions) -> Result<FSEntry, DescriptorError> {
+        use serde_json::value::Value::*;
+
+        match descriptor {
+            // The children of a lazy directory are built on first access, see `JsonFS`
+            Object(m) if FSEntry::lazy_source(&m).is_some() => Ok(FSEntry::Dir(RefCell::new(vec![]))),
+            Object(m) if FSEntry::jsontree_source(&m).is_some() => {
+                let tree = FSEntry::jsontree_descriptor(FSEntry::jsontree_source(&m).unwrap())?;
+                FSEntry::create_directory(parent_inode, inodes, tree, options)
+            },
+            Object(m) if FSEntry::is_foreach(&m) => FSEntry::create_directory(parent_inode, inodes, FSEntry::foreach_descriptor(&m)?, options),
+            Object(m) if FSFileType::is_file_object(&m) => FSEntry::create_file_object(m, options),
+            Object(m) => FSEntry::create_directory(parent_inode, inodes, m, options),
+            Array(a) => FSEntry::create_directory(parent_inode, inodes, FSEntry::array_descriptor(a), options),
+            String(s) => FSEntry::create_file(parent_inode, inodes, s, options),
+            _ => Err(DescriptorError::InvalidNodeType { path: "".into() })
+        }
+    }
+
+    fn create_file(parent_inode: &mut u64, inodes: &mut HashMap<u64, Weak<FSNode>>, file_descriptor: String, options: &BuildOptions) -> Result<FSEntry, DescriptorError> {
+        match file_descriptor.find(':').map(|i| file_descriptor.split_at(i)) {
+            Some(("mirror", path)) => {
+                let mirror = FSEntry::mirror_descriptor(Path::new(&path[1..]), options)?;
+                let entry = FSEntry::create_directory(parent_inode, inodes, mirror, options)?;
+                if let (true, FSEntry::Dir(entries)) = (options.dedup_mirrors, &entry) {
+                    dedup::dedup_local_files(entries, inodes)?;
+                }
+                Ok(entry)
+            },
+            Some(("glob", pattern)) => {
+                let glob = FSEntry::glob_descriptor(&pattern[1..], options)?;
+                FSEntry::create_directory(parent_inode, inodes, glob, options)
+            },
+            _ => {
+                Ok(FSEntry::File(FSFileType::parse_file_string(file_descriptor, options)?))
+            }
+        }
+    }
+
+    /// Expands a local directory into the descriptor of its content
+    fn mirror_descriptor(path: &Path, options: &BuildOptions) -> Result<serde_json::Map<String, serde_json::Value>, DescriptorError> {
+        let entries = match read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) if options.allow_empty_expansion => {
+                warn!("Cannot mirror {}, leaving it empty: {}", path.display(), e);
+                return Ok(serde_json::Map::new());
+            },
+            Err(e) => {
+                warn!("Cannot mirror {}: {}", path.display(), e);
+                return Err(DescriptorError::invalid(format!("cannot mirror {}: {}", path.display(), e)));
+            }
+        };
+
+        let mut descriptor = serde_json::Map::new();
+        for entry in entries {
+            let entry_path = entry.map_err(|e| DescriptorError::invalid(format!("cannot mirror {}: {}", path.display(), e)))?.path();
+            if let Some(value) = FSEntry::expansion_entry(&entry_path, options)? {
+                descriptor.insert(FSEntry::expansion_name(&entry_path)?, value);
+            }
+        }
+        Ok(descriptor)
+    }
+
+    /// Expands the local files matching a glob into the descriptor of a flat directory
+    fn glob_descriptor(pattern: &str, options: &BuildOptions) -> Result<serde_json::Map<String, serde_json::Value>, DescriptorError> {
+        let paths = glob::glob(pattern).map_err(|e| {
+            warn!("Invalid glob {}: {}", pattern, e);
+            DescriptorError::invalid(format!("invalid glob {}: {}", pattern, e))
+        })?;
+
+        let mut descriptor = serde_json::Map::new();
+        for path in paths {
+            let path = path.map_err(|e| DescriptorError::invalid(format!("cannot expand {}: {}", pattern, e)))?;
+            if let Some(value) = FSEntry::expansion_entry(&path, options)? {
+                let name = FSEntry::expansion_name(&path)?;
+                if descriptor.contains_key(&name) {
+                    warn!("Glob {} matches more than one file named {}, keeping {}", pattern, name, path.display());
+                }
+                descriptor.insert(name, value);
+            }
+        }
+
+        if descriptor.is_empty() {
+            warn!("Glob {} doesn't match any file", pattern);
+            if !options.allow_empty_expansion {
+                return Err(DescriptorError::invalid(format!("glob {} doesn't match any file", pattern)));
+            }
+        }
+        Ok(descriptor)
+    }
+
+    /// Descriptor of a local path found while expanding a mirror or a glob, or `None` when it's skipped
+    fn expansion_entry(path: &Path, options: &BuildOptions) -> Result<Option<serde_json::Value>, DescriptorError> {
+        if path.is_dir() {
+            return match FSEntry::mirror_descriptor(path, options) {
+                Ok(mirror) => Ok(Some(serde_json::Value::Object(mirror))),
+                Err(_) if options.skip_unreadable => Ok(None),
+                Err(e) => Err(e)
+            };
+        }
+
+        let file_path = path.to_str().ok_or_else(|| DescriptorError::invalid(format!("{} is not valid UTF-8", path.display())))?.to_string();
+        match LocalFSFileType::new(file_path.clone()).probe() {
+            Ok(()) => Ok(Some(serde_json::Value::String(format!("file:{}", file_path)))),
+            Err(e) if options.skip_unreadable => {
+                warn!("Skipping unreadable file {}: {}", file_path, e);
+                Ok(None)
+            },
+            Err(e) => {
+                warn!("Cannot read {}: {}", file_path, e);
+                Err(DescriptorError::invalid(format!("cannot read {}: {}", file_path, e)))
+            }
+        }
+    }
+
+    fn expansion_name(path: &Path) -> Result<String, DescriptorError> {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .map(String::from)
+            .ok_or_else(|| DescriptorError::invalid(format!("{} has no valid UTF-8 name", path.display())))
+    }
+
+    /// Source of the descriptor of a `{"type": "lazyfs", "source": "http://..."}` directory, whose
+    /// children are fetched on first access rather than when the tree is built
+    pub fn lazy_source(descriptor: &serde_json::Map<String, serde_json::Value>) -> Option<&str> {
+        match (descriptor.get("type"), descriptor.get("source")) {
+            (Some(serde_json::Value::String(t)), Some(serde_json::Value::String(source))) if t == LAZYFS_TYPE => Some(source),
+            _ => None
+        }
+    }
+
+    /// Path of the JSON document of a `{"type": "jsontree", "source": "/data.json"}` directory,
+    /// whose content is built from the document itself
+    pub fn jsontree_source(descriptor: &serde_json::Map<String, serde_json::Value>) -> Option<&str> {
+        match (descriptor.get("type"), descriptor.get("source")) {
+            (Some(serde_json::Value::String(t)), Some(serde_json::Value::String(source))) if t == JSONTREE_TYPE => Some(source),
+            _ => None
+        }
+    }
+
+    /// Expands the JSON document at `path` into the descriptor of a directory: objects become
+    /// directories, arrays become directories indexed from 0 and scalars become raw files
+    fn jsontree_descriptor(path: &str) -> Result<serde_json::Map<String, serde_json::Value>, DescriptorError> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            warn!("Cannot read the JSON tree {}: {}", path, e);
+            DescriptorError::invalid(format!("cannot read the JSON tree {}: {}", path, e))
+        })?;
+        let document: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
+            warn!("Cannot parse the JSON tree {}: {}", path, e);
+            DescriptorError::invalid(format!("cannot parse the JSON tree {}: {}", path, e))
+        })?;
+
+        match FSEntry::jsontree_value(document) {
+            serde_json::Value::Object(tree) if !FSFileType::is_file_object(&tree) => Ok(tree),
+            _ => {
+                warn!("The JSON tree {} is a scalar, rather than an object or an array", path);
+                Err(DescriptorError::invalid(format!("the JSON tree {} is a scalar", path)))
+            }
+        }
+    }
+
+    /// Directory descriptor of an array, whose entries are named by their index: `0`, `1`, ...
+    pub fn array_descriptor(items: Vec<serde_json::Value>) -> serde_json::Map<String, serde_json::Value> {
+        items.into_iter().enumerate().map(|(i, item)| (i.to_string(), item)).collect()
+    }
+
+    fn jsontree_value(value: serde_json::Value) -> serde_json::Value {
+        use serde_json::value::Value::*;
+
+        // Scalars are raw objects, so that no object of the document looks like a file object
+        match value {
+            Object(m) => Object(m.into_iter().map(|(k, v)| (k, FSEntry::jsontree_value(v))).collect()),
+            Array(a) => Object(a.into_iter().enumerate().map(|(i, v)| (i.to_string(), FSEntry::jsontree_value(v))).collect()),
+            String(s) => json!({ "type": "raw", "data": s }),
+            other => json!({ "type": "raw", "data": other.to_string() })
+        }
+    }
+
+    /// Whether the descriptor is a `{"type": "foreach", "items": [...], "template": {...}}` directory
+    pub fn is_foreach(descriptor: &serde_json::Map<String, serde_json::Value>) -> bool {
+        match descriptor.get("type") {
+            Some(serde_json::Value::String(t)) => t == FOREACH_TYPE,
+            _ => false
+        }
+    }
+
+    /// Expands a `foreach` directory into the descriptor of its entries: the entries of `template`
+    /// for each of the `items`, with `{item}` replaced by the item in the names and the string
+    /// values. The variable is named by `as`, `item` by default, so that the template of a nested
+    /// `foreach` can refer to the items of both.
+    pub fn foreach_descriptor(descriptor: &serde_json::Map<String, serde_json::Value>) -> Result<serde_json::Map<String, serde_json::Value>, DescriptorError> {
+        use serde_json::value::Value::*;
+
+        let variable = match descriptor.get("as") {
+            Some(String(variable)) => variable.as_str(),
+            Some(_) => return Err(DescriptorError::field("as")),
+            None => "item"
+        };
+        let (items, template) = match (descriptor.get("items"), descriptor.get("template")) {
+            (Some(Array(items)), Some(Object(template))) => (items, template),
+            _ => return Err(DescriptorError::invalid("foreach needs an items array and a template object"))
+        };
+
+        let mut expanded = serde_json::Map::new();
+        for item in items {
+            let item = match item {
+                String(s) => s.clone(),
+                Number(n) => n.to_string(),
+                _ => return Err(DescriptorError::invalid(format!("foreach item {} is neither a string nor a number", item)))
+            };
+            for (name, value) in template {
+                let name = substitute(name, variable, &item);
+                if expanded.contains_key(&name) {
+                    warn!("The foreach expansion has more than one entry named {}", name);
+                    return Err(DescriptorError::invalid(format!("the foreach expansion has more than one entry named {}", name)));
+                }
+                expanded.insert(name, FSEntry::foreach_value(value, variable, &item));
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Replaces `{variable}` with `item` in the names and string values of `value`, except in the
+    /// template of a nested `foreach` declaring a variable with the same name
+    fn foreach_value(value: &serde_json::Value, variable: &str, item: &str) -> serde_json::Value {
+        use serde_json::value::Value::*;
+
+        match value {
+            String(s) => String(substitute(s, variable, item)),
+            Array(a) => Array(a.iter().map(|v| FSEntry::foreach_value(v, variable, item)).collect()),
+            Object(m) => {
+                let shadowed = FSEntry::is_foreach(m) && m.get("as").and_then(|v| v.as_str()).unwrap_or("item") == variable;
+                Object(m.iter()
+                    .map(|(k, v)| match (shadowed, k.as_str()) {
+                        (true, "template") => (k.clone(), v.clone()),
+                        _ => (substitute(k, variable, item), FSEntry::foreach_value(v, variable, item))
+                    })
+                    .collect())
+            },
+            other => other.clone()
+        }
+    }
 
-        let fs_entry_type = FSFileType::parse_file_type(descriptor_type, descriptor_pointer[1..].to_string())?;
+    fn create_file_object(file_descriptor: serde_json::Map<String, serde_json::Value>, options: &BuildOptions) -> Result<FSEntry, DescriptorError> {
+        let fs_entry_type = FSFileType::parse_file_object(file_descriptor, options)?;
 
         Ok(FSEntry::File(fs_entry_type))
     }
 
-    fn create_directory<'a>(parent_inode: &mut u64, dir_descriptor: serde_json::Map<String, serde_json::Value>) -> Result<FSEntry, DescriptorError> {
+    fn create_directory(parent_inode: &mut u64, inodes: &mut HashMap<u64, Weak<FSNode>>, dir_descriptor: serde_json::Map<String, serde_json::Value>, options: &BuildOptions) -> Result<FSEntry, DescriptorError> {
+        // An entry without a name can't be looked up, and would be taken for the root by `walk`
+        if dir_descriptor.contains_key("") {
+            warn!("Directory entries can't have an empty name");
+            return Err(DescriptorError::invalid("directory entries can't have an empty name"));
+        }
+
+        let dir_descriptor = if options.normalize_names {
+            FSEntry::with_normalized_names(dir_descriptor)?
+        } else {
+            dir_descriptor
+        };
+
+        let dir_descriptor = if options.checksum_siblings {
+            FSEntry::with_checksum_siblings(dir_descriptor)
+        } else {
+            dir_descriptor
+        };
+
+        FSEntry::check_case_collisions(&dir_descriptor)?;
+
         let entries_result: Result<Vec<Rc<FSNode>>, DescriptorError> =
             dir_descriptor
                 .into_iter()
-                .map(|(k, v)| FSNode::_new(parent_inode, k, v))
+                .map(|(k, v)| FSNode::_new(parent_inode, inodes, k, v, options))
                 .collect();
 
-        Ok(FSEntry::Dir(entries_result?))
+        Ok(FSEntry::Dir(RefCell::new(entries_result?)))
+    }
+
+    /// `name` lowercased and NFC-normalized, as the entries are named when building with `normalize_names`
+    pub(crate) fn normalized_name(name: &str) -> String {
+        name.nfc().collect::<String>().to_lowercase()
+    }
+
+    /// Lowercases and NFC-normalizes the names of the directory entries, failing when two of them
+    /// end up with the same name
+    fn with_normalized_names(dir_descriptor: serde_json::Map<String, serde_json::Value>) -> Result<serde_json::Map<String, serde_json::Value>, DescriptorError> {
+        let mut normalized = serde_json::Map::new();
+        for (name, value) in dir_descriptor {
+            let normalized_name = FSEntry::normalized_name(&name);
+            if normalized.contains_key(&normalized_name) {
+                warn!("The name {} collides with another entry once normalized to {}", name, normalized_name);
+                return Err(DescriptorError::invalid(format!("the name {} collides with another entry once normalized to {}", name, normalized_name)));
+            }
+            normalized.insert(normalized_name, value);
+        }
+        Ok(normalized)
+    }
+
+    /// Fails when two entries of the directory have names differing only in case, which can't be
+    /// told apart on case-insensitive hosts
+    fn check_case_collisions(dir_descriptor: &serde_json::Map<String, serde_json::Value>) -> Result<(), DescriptorError> {
+        let mut names: HashMap<String, &String> = HashMap::new();
+        for name in dir_descriptor.keys() {
+            if let Some(other) = names.insert(name.to_lowercase(), name) {
+                warn!("The name {} collides with {} ignoring case", name, other);
+                return Err(DescriptorError::invalid(format!("the name {} collides with {} ignoring case", name, other)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a `<name>.crc32` sibling to each file of the directory. Checksum files don't get a
+    /// sibling of their own, and entries already declared with that name are left untouched.
+    fn with_checksum_siblings(dir_descriptor: serde_json::Map<String, serde_json::Value>) -> serde_json::Map<String, serde_json::Value> {
+        let siblings: Vec<(String, serde_json::Value)> = dir_descriptor
+            .iter()
+            .filter(|(name, value)| !name.ends_with(CHECKSUM_SUFFIX) && FSEntry::is_plain_file(value))
+            .map(|(name, _)| (format!("{}{}", name, CHECKSUM_SUFFIX), json!({ "type": "crc32", "sibling": name })))
+            .filter(|(name, _)| !dir_descriptor.contains_key(name))
+            .collect();
+
+        let mut dir_descriptor = dir_descriptor;
+        dir_descriptor.extend(siblings);
+        dir_descriptor
+    }
+
+    fn is_plain_file(descriptor: &serde_json::Value) -> bool {
+        match descriptor {
+            serde_json::Value::String(s) => !s.starts_with("mirror:") && !s.starts_with("glob:"),
+            serde_json::Value::Object(m) => FSFileType::is_file_object(m),
+            _ => false
+        }
     }
 }
 
+/// Size of the unit `FileAttr.blocks` is counted in
+pub const BLOCK_UNIT: u64 = 512;
+
+/// Preferred block size of the file types not configuring their own
+pub const DEFAULT_BLOCK_SIZE: u64 = 4096;
+
+/// Number of `BLOCK_UNIT`s taken by `size` bytes stored in blocks of `block_size` bytes.
+/// `block_size` is rounded up to a multiple of `BLOCK_UNIT`.
+pub fn blocks(size: u64, block_size: u64) -> u64 {
+    let units_per_block = max(1, (block_size + BLOCK_UNIT - 1) / BLOCK_UNIT);
+    let block_size = units_per_block * BLOCK_UNIT;
+    (size + block_size - 1) / block_size * units_per_block
+}
+
 pub trait FSFileTypeOps {
-    fn get_attributes(&self, inode: u64) -> FileAttr;
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr>;
     fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()>;
+
+    /// Preferred block size, the `blocks` of the attributes are computed from
+    fn block_size(&self) -> u64 {
+        DEFAULT_BLOCK_SIZE
+    }
+
+    /// Size of the content, for the callers not needing the other attributes
+    fn size(&self) -> io::Result<u64> {
+        self.get_attributes(0).map(|attr| attr.size)
+    }
+
+    /// Checks the backing source can be read
+    fn probe(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the reads of an open handle are served from the content read once on open, for
+    /// the sources fetching or computing their whole content on each read. The wrappers don't opt
+    /// in, so that they act on each read.
+    fn cache_on_open(&self) -> bool {
+        false
+    }
 }
 
 impl FSFileType {
-    fn parse_file_type(type_descriptor: &str, pointer: String) -> Result<FSFileType, DescriptorError> {
+    fn parse_file_descriptor(file_descriptor: &str, options: &BuildOptions) -> Result<FSFileType, DescriptorError> {
+        let (descriptor_type, descriptor_pointer) = file_descriptor
+            .split_at(file_descriptor.find(':').ok_or_else(|| DescriptorError::MissingColon(file_descriptor.to_string()))?);
+
+        FSFileType::parse_file_type(descriptor_type, descriptor_pointer[1..].to_string(), options)
+    }
+
+    /// A JSON object is a file descriptor, rather than a directory, when its `type` is one of the
+    /// `OBJECT_FILE_TYPES`. A directory can have an entry named `type` too, a bare string with
+    /// `$default_type`, so the other values leave the object a directory.
+    fn is_file_object(descriptor: &serde_json::Map<String, serde_json::Value>) -> bool {
+        match descriptor.get("type") {
+            Some(serde_json::Value::String(t)) => OBJECT_FILE_TYPES.contains(&t.as_str()),
+            _ => false
+        }
+    }
+
+    fn parse_file_object(descriptor: serde_json::Map<String, serde_json::Value>, options: &BuildOptions) -> Result<FSFileType, DescriptorError> {
+        let string_field = |key: &str| descriptor
+            .get(key)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| DescriptorError::field(key));
+
+        // Keep in sync with `OBJECT_FILE_TYPES`
+        let file_type = match string_field("type")? {
+            "raw" => FSFileType::Raw(RawFSFileType::new(string_field("data")?.to_string())),
+            "base64" => FSFileType::Base64(Base64FSFileType::new(string_field("data")?)?),
+            "gzip" => match (descriptor.get("data").and_then(|d| d.as_str()), descriptor.get("path").and_then(|p| p.as_str())) {
+                (Some(data), None) => FSFileType::Gzip(GzipFSFileType::from_base64(data)?),
+                (None, Some(path)) => FSFileType::Gzip(GzipFSFileType::from_path(path)?),
+                _ => return Err(DescriptorError::invalid("gzip needs either a data or a path"))
+            },
+            "file" | "local" => {
+                let windows = match descriptor.get("windows") {
+                    Some(serde_json::Value::Array(windows)) => windows
+                        .iter()
+                        .map(|w| w.as_str().ok_or_else(|| DescriptorError::field("windows")).and_then(LocalFSFileType::parse_window))
+                        .collect::<Result<Vec<_>, DescriptorError>>()?,
+                    Some(_) => return Err(DescriptorError::field("windows")),
+                    None => vec![]
+                };
+                let local = LocalFSFileType::new(string_field("path")?.to_string()).with_windows(windows);
+                local.check_windows()?;
+                FSFileType::Local(local)
+            },
+            "http" | "https" => FSFileType::Http(
+                HttpFSFileType::new(string_field("url")?.to_string())
+                    .with_accept_encoding(string_field("accept_encoding").ok().map(String::from))
+                    .with_limiter(options.http_limiter.clone())
+                    .with_cache_ttl(match descriptor.get("cache_ttl_ms") {
+                        Some(ttl) => Some(Duration::from_millis(ttl.as_u64().ok_or_else(|| DescriptorError::field("cache_ttl_ms"))?)),
+                        None => None
+                    })
+            ),
+            "rotate" => FSFileType::Rotate(RotateFSFileType::new(
+                string_field("pattern")?.to_string(),
+                match string_field("order") {
+                    Ok("newest_first") => true,
+                    Ok("oldest_first") | Err(_) => false,
+                    Ok(_) => return Err(DescriptorError::field("order"))
+                }
+            )?),
+            "blobref" => FSFileType::BlobRef(BlobRefFSFileType::new(
+                string_field("store")?.to_string(),
+                string_field("sha256")?.to_string()
+            )?),
+            "unixsock" => FSFileType::UnixSock(UnixSockFSFileType::new(
+                string_field("path")?.to_string(),
+                string_field("request").ok().map(String::from),
+                match descriptor.get("timeout_ms") {
+                    Some(timeout) => Duration::from_millis(timeout.as_u64().ok_or_else(|| DescriptorError::field("timeout_ms"))?),
+                    None => DEFAULT_UNIXSOCK_TIMEOUT
+                }
+            )),
+            "seq" => {
+                let number_field = |key: &str| descriptor.get(key).and_then(|v| v.as_u64()).ok_or_else(|| DescriptorError::field(key));
+                FSFileType::Seq(SeqFSFileType::new(
+                    number_field("from")?,
+                    number_field("to")?,
+                    string_field("sep").unwrap_or("\n").to_string()
+                )?)
+            },
+            "random" => FSFileType::Random(RandomFSFileType::new(
+                descriptor.get("size").and_then(|v| v.as_u64()).ok_or_else(|| DescriptorError::field("size"))?,
+                match descriptor.get("seed") {
+                    Some(seed) => seed.as_u64().ok_or_else(|| DescriptorError::field("seed"))?,
+                    None => 0
+                }
+            )),
+            "attrof" => match string_field("path")? {
+                path if path.starts_with('/') => FSFileType::AttrOf(AttrOfFSFileType::new(path.to_string())),
+                _ => return Err(DescriptorError::field("path"))
+            },
+            "catdir" => match string_field("path")? {
+                path if path.starts_with('/') => FSFileType::CatDir(CatDirFSFileType::new(path.to_string())),
+                _ => return Err(DescriptorError::field("path"))
+            },
+            "proc" => FSFileType::Proc(ProcFSFileType::new(string_field("field")?)?),
+            "cond" => FSFileType::Cond(CondFSFileType::new(
+                string_field("when")?,
+                FSFileType::parse_file_descriptor(string_field("then")?, options)?
+            )?),
+            "crc32" => FSFileType::Crc32(match descriptor.get("sibling") {
+                Some(serde_json::Value::String(name)) => Crc32FSFileType::sibling(name.clone()),
+                Some(_) => return Err(DescriptorError::field("sibling")),
+                None => Crc32FSFileType::new(
+                    FSFileType::parse_file_value(descriptor.get("of").cloned().ok_or_else(|| DescriptorError::field("of"))?, options)?
+                )
+            }),
+            "pipeline" => FSFileType::Pipeline(PipelineFSFileType::new(
+                FSFileType::parse_file_value(descriptor.get("source").cloned().ok_or_else(|| DescriptorError::field("source"))?, options)?,
+                descriptor
+                    .get("steps")
+                    .and_then(|steps| steps.as_array())
+                    .ok_or_else(|| DescriptorError::field("steps"))?
+                    .iter()
+                    .map(|step| step.as_str().ok_or_else(|| DescriptorError::field("steps")).and_then(Step::parse))
+                    .collect::<Result<Vec<Step>, DescriptorError>>()?
+            )),
+            other => return Err(DescriptorError::UnknownFileType(other.to_string()))
+        };
+
+        if let Some(xattrs) = descriptor.get("xattrs") {
+            xattr::parse_xattrs(xattrs)?;
+        }
+
+        // Attribute TTL of the file, see `JsonFS`
+        if let Some(ttl) = descriptor.get("ttl_ms") {
+            ttl.as_u64().ok_or_else(|| DescriptorError::field("ttl_ms"))?;
+        }
+
+        // Additional names of the file, see `JsonFS`
+        match descriptor.get("also") {
+            Some(serde_json::Value::Array(aliases)) if aliases.iter().all(|a| a.is_string()) => {},
+            Some(_) => return Err(DescriptorError::field("also")),
+            None => {}
+        }
+
+        let file_type = match descriptor.get("filter") {
+            Some(command) => FSFileType::Filter(FilterFSFileType::new(file_type, command.as_str().ok_or_else(|| DescriptorError::field("filter"))?.to_string())),
+            None => file_type
+        };
+
+        let file_type = match (descriptor.get("size"), &file_type) {
+            // The size of a random file is its own
+            (None, _) | (Some(_), FSFileType::Random(_)) => file_type,
+            (Some(size), _) => FSFileType::Sized(SizedFSFileType::new(file_type, size.as_u64().ok_or_else(|| DescriptorError::field("size"))?))
+        };
+
+        let file_type = match descriptor.get("mode") {
+            Some(mode) => FSFileType::Mode(ModeFSFileType::new(file_type, ModeFSFileType::parse_mode(mode.as_str().ok_or_else(|| DescriptorError::field("mode"))?)?)),
+            None => file_type
+        };
+
+        let file_type = match descriptor.get("read_delay_ms") {
+            Some(delay) => {
+                let delay = Duration::from_millis(delay.as_u64().ok_or_else(|| DescriptorError::field("read_delay_ms"))?);
+                FSFileType::Delay(DelayFSFileType::new(file_type, delay, options.shutdown.clone()))
+            },
+            None => file_type
+        };
+
+        let file_type = match descriptor.get("read_timeout_ms") {
+            Some(timeout) => {
+                let timeout = Duration::from_millis(timeout.as_u64().ok_or_else(|| DescriptorError::field("read_timeout_ms"))?);
+                FSFileType::Timeout(TimeoutFSFileType::new(file_type, timeout))
+            },
+            None => file_type
+        };
+
+        let file_type = match descriptor.get("available") {
+            Some(available) => FSFileType::Window(WindowFSFileType::parse(file_type, available)?),
+            None => file_type
+        };
+
+        let file_type = match descriptor.get("fault") {
+            Some(fault) => FSFileType::Fault(FaultFSFileType::parse(file_type, fault)?),
+            None => file_type
+        };
+
+        let file_type = match descriptor.get("quota") {
+            Some(quota) => FSFileType::Quota(QuotaFSFileType::new(file_type, quota.as_u64().ok_or_else(|| DescriptorError::field("quota"))?)),
+            None => file_type
+        };
+
+        Ok(file_type)
+    }
+
+    /// Parses a file descriptor in either the string or the object form
+    fn parse_file_value(descriptor: serde_json::Value, options: &BuildOptions) -> Result<FSFileType, DescriptorError> {
+        match descriptor {
+            serde_json::Value::String(s) => FSFileType::parse_file_string(s, options),
+            serde_json::Value::Object(m) if FSFileType::is_file_object(&m) => FSFileType::parse_file_object(m, options),
+            other => Err(DescriptorError::invalid(format!("{} is not a file descriptor", other)))
+        }
+    }
+
+    /// Parses a file declared as a string, with the `$default_type` when it has no known prefix
+    fn parse_file_string(file_descriptor: String, options: &BuildOptions) -> Result<FSFileType, DescriptorError> {
+        match &options.default_type {
+            Some(default_type) if !FSFileType::has_known_prefix(&file_descriptor) =>
+                FSFileType::parse_file_type(default_type, file_descriptor, options),
+            _ => FSFileType::parse_file_descriptor(&file_descriptor, options)
+        }
+    }
+
+    fn has_known_prefix(file_descriptor: &str) -> bool {
+        file_descriptor
+            .find(':')
+            .map(|i| FILE_TYPES.contains(&&file_descriptor[..i]))
+            .unwrap_or(false)
+    }
+
+    fn parse_file_type(type_descriptor: &str, pointer: String, options: &BuildOptions) -> Result<FSFileType, DescriptorError> {
         match type_descriptor {
             "raw" => Ok(FSFileType::Raw(raw::RawFSFileType::new(pointer))),
+            "base64" => Ok(FSFileType::Base64(Base64FSFileType::new(&pointer)?)),
+            "symlink" => Ok(FSFileType::Symlink(SymlinkFSFileType::new(pointer)?)),
             "file" | "local" => Ok(FSFileType::Local(LocalFSFileType::new(pointer))),
-            "http" | "https" => Ok(FSFileType::Http(HttpFSFileType::new(format!("{}:{}", type_descriptor, pointer)))),
-            _ => Err(DescriptorError)
+            // Either `http://host/x` or `http:http://host/x`, with the whole url as pointer
+            "http" | "https" => {
+                let address = if pointer.contains("://") {
+                    pointer
+                } else {
+                    format!("{}:{}", type_descriptor, pointer)
+                };
+                Ok(FSFileType::Http(HttpFSFileType::new(address).with_limiter(options.http_limiter.clone())))
+            },
+            "sqlite" => Ok(FSFileType::Sqlite(SqliteFSFileType::new(pointer)?)),
+            "fd" => Ok(FSFileType::Fd(FdFSFileType::new(pointer)?)),
+            "stdin" => Ok(FSFileType::Stdin(StdinFSFileType::new()?)),
+            "rotate" => Ok(FSFileType::Rotate(RotateFSFileType::new(pointer, false)?)),
+            "unixsock" => Ok(FSFileType::UnixSock(UnixSockFSFileType::new(pointer, None, DEFAULT_UNIXSOCK_TIMEOUT))),
+            "env" => Ok(FSFileType::Env(EnvFSFileType::new(pointer)?)),
+            "gzip" => Ok(FSFileType::Gzip(GzipFSFileType::from_base64(&pointer)?)),
+            "ref" => Ok(FSFileType::Ref(RefFSFileType::new(pointer)?)),
+            "cmd" if options.allow_commands => Ok(FSFileType::Command(CommandFSFileType::new(pointer))),
+            "cmd" => {
+                warn!("The command {} can't run, commands aren't allowed", pointer);
+                Err(DescriptorError::invalid("cmd files are disabled, see BuildOptions::allow_commands"))
+            },
+            _ => Err(DescriptorError::UnknownFileType(type_descriptor.to_string()))
         }
     }
 
@@ -161,22 +935,270 @@ impl FSFileType {
         match self {
             FSFileType::Raw(s) => s,
             FSFileType::Local(s) => s,
-            FSFileType::Http(s) => s
+            FSFileType::Http(s) => s,
+            FSFileType::Cond(s) => s,
+            FSFileType::Sqlite(s) => s,
+            FSFileType::Timeout(s) => s,
+            FSFileType::Fd(s) => s,
+            FSFileType::Crc32(s) => s,
+            FSFileType::Fault(s) => s,
+            FSFileType::Log(s) => s,
+            FSFileType::Stdin(s) => s,
+            FSFileType::Rotate(s) => s,
+            FSFileType::BlobRef(s) => s,
+            FSFileType::Sized(s) => s,
+            FSFileType::UnixSock(s) => s,
+            FSFileType::Proc(s) => s,
+            FSFileType::Seq(s) => s,
+            FSFileType::Delay(s) => s,
+            FSFileType::Filter(s) => s,
+            FSFileType::Shared(s) => s,
+            FSFileType::Window(s) => s,
+            FSFileType::Base64(s) => s,
+            FSFileType::Random(s) => s,
+            FSFileType::Pipeline(s) => s,
+            FSFileType::Mode(s) => s,
+            FSFileType::Symlink(s) => s,
+            FSFileType::AttrOf(s) => s,
+            FSFileType::Quota(s) => s,
+            FSFileType::CatDir(s) => s,
+            FSFileType::Command(s) => s,
+            FSFileType::Env(s) => s,
+            FSFileType::Gzip(s) => s,
+            FSFileType::Ref(s) => s
+        }
+    }
+}
+
+/// Types recognized by `FSFileType::parse_file_type`
+const FILE_TYPES: &[&str] = &["raw", "base64", "symlink", "file", "local", "http", "https", "sqlite", "fd", "stdin", "rotate", "unixsock", "cmd", "env", "gzip", "ref"];
+
+/// Types recognized by `FSFileType::parse_file_object`
+const OBJECT_FILE_TYPES: &[&str] = &["raw", "base64", "gzip", "file", "local", "http", "https", "rotate", "blobref", "unixsock", "seq", "random", "attrof", "catdir", "proc", "cond", "crc32", "pipeline"];
+
+/// Type of the directories whose children are built from a descriptor fetched on first access
+pub const LAZYFS_TYPE: &str = "lazyfs";
+
+/// Type of the directories built from the content of a local JSON document
+pub const JSONTREE_TYPE: &str = "jsontree";
+
+/// Type of the directories whose entries are instantiated from a template for each item of a list
+pub const FOREACH_TYPE: &str = "foreach";
+
+/// Root descriptor key setting the type of the string values without a known `type:` prefix
+pub const DEFAULT_TYPE_DIRECTIVE: &str = "$default_type";
+
+/// Options controlling how a descriptor is turned into a tree
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    /// Omit the files of a mirror or glob expansion that can't be read, rather than failing the build
+    pub skip_unreadable: bool,
+    /// Add a `<name>.crc32` file next to each file, serving the CRC32 of its content
+    pub checksum_siblings: bool,
+    /// Type of the string values without a known `type:` prefix, the whole value being the pointer
+    pub default_type: Option<String>,
+    /// Lowercase and NFC-normalize the entry names, failing on the resulting collisions
+    pub normalize_names: bool,
+    /// Build an empty directory for a mirror of a missing directory or a glob matching no file,
+    /// rather than failing the build
+    pub allow_empty_expansion: bool,
+    /// Directory prepended to the absolute paths of the `file:`, `local:`, `mirror:` and `glob:`
+    /// entries, as if the descriptor was used in a chroot
+    pub local_prefix: Option<String>,
+    /// Make the files of a mirror with identical content share a single file type instance
+    pub dedup_mirrors: bool,
+    /// Bounds the concurrent requests of all the http files of the tree
+    pub http_limiter: Option<Arc<HttpLimiter>>,
+    /// Set when the file system shuts down, cutting the read delays short
+    pub shutdown: Arc<AtomicBool>,
+    /// Build the `cmd:` files, which run arbitrary shell commands, rather than failing the build
+    pub allow_commands: bool
+}
+
+impl BuildOptions {
+    /// Strips the directives from the root of `descriptor`, returning the options they set
+    pub fn with_directives(&self, descriptor: serde_json::Value) -> Result<(serde_json::Value, BuildOptions), DescriptorError> {
+        let mut options = self.clone();
+        let mut descriptor = descriptor;
+        if let serde_json::Value::Object(root) = &mut descriptor {
+            match root.remove(DEFAULT_TYPE_DIRECTIVE) {
+                Some(serde_json::Value::String(t)) if FILE_TYPES.contains(&t.as_str()) => options.default_type = Some(t),
+                Some(_) => return Err(DescriptorError::invalid(format!("{} is not a known file type", DEFAULT_TYPE_DIRECTIVE))),
+                None => {}
+            }
+        }
+        Ok((descriptor, options))
+    }
+
+    /// Rewrites the absolute local paths of `descriptor` under `local_prefix`, when set
+    pub fn with_local_prefix(&self, descriptor: serde_json::Value) -> serde_json::Value {
+        match &self.local_prefix {
+            Some(prefix) => self.prefix_value(prefix, descriptor),
+            None => descriptor
+        }
+    }
+
+    /// Rewrites the absolute path of a string file descriptor under `local_prefix`, when set
+    pub fn with_local_prefix_str(&self, descriptor: String) -> String {
+        match &self.local_prefix {
+            Some(prefix) => self.prefix_string(prefix, descriptor),
+            None => descriptor
+        }
+    }
+
+    fn prefix_value(&self, prefix: &str, descriptor: serde_json::Value) -> serde_json::Value {
+        use serde_json::value::Value::*;
+
+        match descriptor {
+            String(s) => String(self.prefix_string(prefix, s)),
+            Object(m) if FSEntry::lazy_source(&m).is_some() => Object(m),
+            Object(mut m) if FSEntry::jsontree_source(&m).is_some() => {
+                let source = prefix_path(prefix, FSEntry::jsontree_source(&m).unwrap());
+                m.insert("source".to_string(), String(source));
+                Object(m)
+            },
+            Object(mut m) if FSFileType::is_file_object(&m) => {
+                let is_local = match m.get("type").and_then(|t| t.as_str()) {
+                    Some(t) => LOCAL_TYPES.contains(&t),
+                    None => false
+                };
+                if let (true, Some(String(path))) = (is_local, m.get("path")) {
+                    let path = prefix_path(prefix, path);
+                    m.insert("path".to_string(), String(path));
+                }
+                // The files a `cond`, a `crc32` or a `pipeline` file is computed from
+                for key in &["then", "of", "source"] {
+                    if let Some(inner) = m.remove(*key) {
+                        m.insert(key.to_string(), self.prefix_value(prefix, inner));
+                    }
+                }
+                Object(m)
+            },
+            Object(m) => Object(m.into_iter().map(|(k, v)| (k, self.prefix_value(prefix, v))).collect()),
+            other => other
+        }
+    }
+
+    fn prefix_string(&self, prefix: &str, descriptor: String) -> String {
+        if let Some(i) = descriptor.find(':') {
+            let (descriptor_type, path) = (&descriptor[..i], &descriptor[i + 1..]);
+            if LOCAL_TYPES.contains(&descriptor_type) || descriptor_type == "mirror" || descriptor_type == "glob" {
+                return format!("{}:{}", descriptor_type, prefix_path(prefix, path));
+            }
+        }
+        match &self.default_type {
+            Some(default_type) if LOCAL_TYPES.contains(&default_type.as_str()) && !FSFileType::has_known_prefix(&descriptor) =>
+                prefix_path(prefix, &descriptor),
+            _ => descriptor
         }
     }
 }
 
-pub struct DescriptorError;
+/// Path of the descriptor standing for the stdin of the process, e.g. `generate-tree | json_fuse_fs - /mnt`
+pub const STDIN_DESCRIPTOR: &str = "-";
+
+/// Parses the JSON descriptor read from `reader`
+pub fn read_descriptor<R: io::Read>(reader: R) -> io::Result<serde_json::Value> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Loads the JSON descriptor from the file at `path`, or from stdin when `path` is
+/// `STDIN_DESCRIPTOR`. Stdin is then consumed, so the `stdin:` files fail to build.
+pub fn load_descriptor(path: &str) -> io::Result<serde_json::Value> {
+    if path == STDIN_DESCRIPTOR {
+        read_descriptor(io::Cursor::new(stdin::take_stdin()?))
+    } else {
+        read_descriptor(io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+/// `template` with `{variable}` replaced by `item`
+fn substitute(template: &str, variable: &str, item: &str) -> String {
+    template.replace(&format!("{{{}}}", variable), item)
+}
+
+/// Types served from a local path
+const LOCAL_TYPES: &[&str] = &["file", "local"];
+
+/// `path` under `prefix` when it's absolute, as is otherwise
+fn prefix_path(prefix: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        format!("{}{}", prefix.trim_end_matches('/'), path)
+    } else {
+        path.to_string()
+    }
+}
+
+#[derive(PartialEq)]
+pub enum DescriptorError {
+    /// A file descriptor whose type, in the `type:` prefix or the `type` field, is unknown
+    UnknownFileType(String),
+    /// A string file descriptor without the `:` separating the type from the pointer
+    MissingColon(String),
+    /// A value at `path` which is neither a string, an object nor an array
+    InvalidNodeType { path: String },
+    /// A field of an object descriptor that is missing or has the wrong type
+    InvalidField(String),
+    /// Any other invalid value, with the reason
+    Invalid(String),
+    /// `cause` found while building the entry at `path`
+    At { path: String, cause: Box<DescriptorError> }
+}
+
+impl DescriptorError {
+    pub fn invalid<S: Into<String>>(reason: S) -> DescriptorError {
+        DescriptorError::Invalid(reason.into())
+    }
+
+    pub fn field(key: &str) -> DescriptorError {
+        DescriptorError::InvalidField(key.to_string())
+    }
+
+    /// Path of the entry of the descriptor the error was found in, `/` for the root
+    pub fn path(&self) -> &str {
+        match self {
+            DescriptorError::At { path, .. } | DescriptorError::InvalidNodeType { path } if !path.is_empty() => path,
+            _ => "/"
+        }
+    }
+
+    /// The error without the path it was found at
+    pub fn cause(&self) -> &DescriptorError {
+        match self {
+            DescriptorError::At { cause, .. } => cause,
+            other => other
+        }
+    }
+
+    /// Locates the error under the entry `name`, as it bubbles up from a child to its parent
+    fn within(self, name: &str) -> DescriptorError {
+        if name.is_empty() {
+            return self;
+        }
+        match self {
+            DescriptorError::At { path, cause } => DescriptorError::At { path: format!("/{}{}", name, path), cause },
+            DescriptorError::InvalidNodeType { path } => DescriptorError::InvalidNodeType { path: format!("/{}{}", name, path) },
+            cause => DescriptorError::At { path: format!("/{}", name), cause: Box::new(cause) }
+        }
+    }
+}
 
 impl Debug for DescriptorError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "JSON descriptor error")
+        write!(f, "{}", self)
     }
 }
 
 impl Display for DescriptorError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "JSON descriptor error")
+        match self {
+            DescriptorError::UnknownFileType(t) => write!(f, "unknown file type {}", t),
+            DescriptorError::MissingColon(descriptor) => write!(f, "{} has no type: prefix", descriptor),
+            DescriptorError::InvalidNodeType { .. } => write!(f, "{}: neither a file nor a directory descriptor", self.path()),
+            DescriptorError::InvalidField(key) => write!(f, "missing or invalid field {}", key),
+            DescriptorError::Invalid(reason) => write!(f, "{}", reason),
+            DescriptorError::At { path, cause } => write!(f, "{}: {}", path, cause)
+        }
     }
 }
 
@@ -0,0 +1,67 @@
+use super::*;
+use std::fmt;
+
+/// An inconsistency found in a descriptor, which doesn't prevent it from being mounted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    /// Full path of the file in the mounted tree
+    pub path: String,
+    pub message: String
+}
+
+impl Display for LintWarning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Performs cheap checks on the files whose content is in the descriptor, e.g. a `.json` raw file
+/// that doesn't parse as JSON. Other file types are not read.
+pub fn lint_descriptor(descriptor: &serde_json::Value) -> Vec<LintWarning> {
+    let default_type = match descriptor.get(DEFAULT_TYPE_DIRECTIVE) {
+        Some(serde_json::Value::String(t)) => Some(t.as_str()),
+        _ => None
+    };
+    let mut warnings = vec![];
+    if let serde_json::Value::Object(root) = descriptor {
+        lint_directory("", root, default_type, &mut warnings);
+    }
+    warnings
+}
+
+fn lint_directory(path: &str, directory: &serde_json::Map<String, serde_json::Value>, default_type: Option<&str>, warnings: &mut Vec<LintWarning>) {
+    for (name, value) in directory.iter() {
+        if path.is_empty() && name == DEFAULT_TYPE_DIRECTIVE {
+            continue;
+        }
+        let entry_path = format!("{}/{}", path, name);
+        match value {
+            serde_json::Value::Object(m) if FSFileType::is_file_object(m) => {
+                if let (Some("raw"), Some(data)) = (m.get("type").and_then(|t| t.as_str()), m.get("data").and_then(|d| d.as_str())) {
+                    lint_content(&entry_path, data, warnings);
+                }
+            },
+            // The content of a lazy directory isn't known before mounting
+            serde_json::Value::Object(m) if FSEntry::lazy_source(m).is_some() => {},
+            serde_json::Value::Object(m) => lint_directory(&entry_path, m, default_type, warnings),
+            serde_json::Value::Array(a) => lint_directory(&entry_path, &FSEntry::array_descriptor(a.clone()), default_type, warnings),
+            serde_json::Value::String(s) if s.starts_with("raw:") => lint_content(&entry_path, &s[4..], warnings),
+            serde_json::Value::String(s) if default_type == Some("raw") && !FSFileType::has_known_prefix(s) =>
+                lint_content(&entry_path, s, warnings),
+            _ => {}
+        }
+    }
+}
+
+fn lint_content(path: &str, content: &str, warnings: &mut Vec<LintWarning>) {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    if let Some("json") = extension.as_ref().map(String::as_str) {
+        let parsed: serde_json::Result<serde_json::Value> = serde_json::from_str(content);
+        if let Err(e) = parsed {
+            warnings.push(LintWarning {
+                path: path.to_string(),
+                message: format!("the extension implies JSON, but the content doesn't parse: {}", e)
+            });
+        }
+    }
+}
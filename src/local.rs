@@ -8,20 +8,91 @@ use std::fs::{Metadata, File};
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::io::{Seek, SeekFrom, Read};
+use std::ops::Range;
+use std::cmp::min;
+use libc::ENOENT;
 
 #[derive(Debug)]
 #[derive(Eq, PartialEq)]
 #[derive(Hash)]
 pub struct LocalFSFileType {
-    pub file_path: String
+    pub file_path: String,
+    /// Ranges of the backing file stitched together into the served content. When empty,
+    /// the whole file is served.
+    pub windows: Vec<Range<u64>>
 }
 
 impl LocalFSFileType {
     pub fn new(pointer: String) -> LocalFSFileType {
         LocalFSFileType {
-            file_path: pointer
+            file_path: pointer,
+            windows: vec![]
         }
     }
+
+    pub fn with_windows(mut self, windows: Vec<Range<u64>>) -> LocalFSFileType {
+        self.windows = windows;
+        self
+    }
+
+    /// Parses a window like `100..110`
+    pub fn parse_window(window: &str) -> Result<Range<u64>, DescriptorError> {
+        let invalid = || DescriptorError::invalid(format!("invalid window {}", window));
+        let separator = window.find("..").ok_or_else(invalid)?;
+        let start = window[..separator].trim().parse::<u64>().map_err(|_| invalid())?;
+        let end = window[separator + 2..].trim().parse::<u64>().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(start..end)
+    }
+
+    /// Fails when a window ends past the end of the backing file, unless it's missing
+    pub fn check_windows(&self) -> Result<(), DescriptorError> {
+        if let Ok(metadata) = fs::metadata(&self.file_path) {
+            if let Some(window) = self.windows.iter().find(|w| w.end > metadata.len()) {
+                return Err(DescriptorError::invalid(format!(
+                    "the window {}..{} ends past the end of {} ({} bytes)", window.start, window.end, self.file_path, metadata.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The windows clamped to a backing file of `file_size` bytes, which may have shrunk since
+    /// the windows were checked
+    fn clamped_windows<'a>(&'a self, file_size: u64) -> impl Iterator<Item = Range<u64>> + 'a {
+        self.windows.iter().map(move |w| min(w.start, file_size)..min(w.end, file_size))
+    }
+
+    fn windowed_size(&self, file_size: u64) -> u64 {
+        if self.windows.is_empty() {
+            file_size
+        } else {
+            self.clamped_windows(file_size).map(|w| w.end - w.start).sum()
+        }
+    }
+
+    fn read_windows(&self, file: &mut File, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let mut window_start = 0;
+        let mut written = 0;
+        for window in self.clamped_windows(file.metadata()?.len()) {
+            if written == buffer.len() {
+                break;
+            }
+            let window_len = window.end - window.start;
+            let position = offset + written as u64;
+            if position < window_start + window_len {
+                let skip = position - window_start;
+                let len = min(window_len - skip, (buffer.len() - written) as u64) as usize;
+                file.seek(SeekFrom::Start(window.start + skip))?;
+                file.read_exact(&mut buffer[written..written + len])?;
+                written += len;
+            }
+            window_start += window_len;
+        }
+        Ok(())
+    }
 }
 
 macro_rules! stat_time_to_SystemTime {
@@ -29,13 +100,17 @@ macro_rules! stat_time_to_SystemTime {
 }
 
 impl FSFileTypeOps for LocalFSFileType {
-    fn get_attributes(&self, inode: u64) -> FileAttr {
-        let stat: FileStat = stat(OsStr::new(&self.file_path)).unwrap();
-        let meta: Metadata = fs::metadata(&self.file_path).unwrap();
-        FileAttr {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let meta: Metadata = fs::metadata(&self.file_path)?;
+        let stat: FileStat = stat(OsStr::new(&self.file_path)).map_err(|e| {
+            io::Error::from_raw_os_error(e.as_errno().map(|errno| errno as i32).unwrap_or(ENOENT))
+        })?;
+        let size = self.windowed_size(stat.st_size as u64);
+        Ok(FileAttr {
             ino: inode,
-            size: stat.st_size as u64,
-            blocks: stat.st_blocks as u64,
+            size,
+            // The allocation of the backing file doesn't tell how much of it the windows cover
+            blocks: if self.windows.is_empty() { stat.st_blocks as u64 } else { blocks(size, self.block_size()) },
             atime: stat_time_to_SystemTime!(stat.st_atime, stat.st_atime_nsec),
             mtime: stat_time_to_SystemTime!(stat.st_mtime, stat.st_mtime_nsec),
             ctime: stat_time_to_SystemTime!(stat.st_ctime, stat.st_ctime_nsec),
@@ -47,13 +122,35 @@ impl FSFileTypeOps for LocalFSFileType {
             gid: stat.st_gid,
             rdev: stat.st_rdev as u32,
             flags: 0
-        }
+        })
     }
     fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
         let mut file = File::open(&self.file_path)?;
 
+        if !self.windows.is_empty() {
+            return self.read_windows(&mut file, offset as u64, buffer);
+        }
+
+        // A single read may return fewer bytes than available, so read until the buffer is
+        // filled or the end of the file
         file.seek(SeekFrom::Start(offset as u64))?;
-        file.read(buffer)?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match file.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
         Ok(())
     }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.windowed_size(fs::metadata(&self.file_path)?.len()))
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        File::open(&self.file_path).map(|_| ())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+use super::*;
+use std::collections::VecDeque;
+use std::cmp::{min, max};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use fuse::{FileType, FileAttr};
+use log::{Log, Metadata, Record, Level, SetLoggerError};
+
+/// Least severe level kept in the ring buffer, whatever `RUST_LOG` says
+const BUFFER_LEVEL: Level = Level::Info;
+
+/// Keeps the most recent `capacity` log lines in memory
+#[derive(Debug)]
+pub struct LogRingBuffer {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize
+}
+
+impl LogRingBuffer {
+    pub fn new(capacity: usize) -> LogRingBuffer {
+        LogRingBuffer {
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity
+        }
+    }
+
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        if self.capacity > 0 {
+            lines.push_back(line);
+        }
+    }
+
+    /// Buffered lines, oldest first, each one terminated by a newline
+    pub fn contents(&self) -> Vec<u8> {
+        self.lines
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|l| l.bytes().chain(iter::once(b'\n')))
+            .collect()
+    }
+}
+
+/// Forwards the records to `env_logger`, while copying them in a `LogRingBuffer`
+pub struct RingBufferLogger {
+    inner: env_logger::Logger,
+    buffer: Arc<LogRingBuffer>
+}
+
+impl Log for RingBufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= BUFFER_LEVEL || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= BUFFER_LEVEL {
+            self.buffer.push(format!("{} {}: {}", record.level(), record.target(), record.args()));
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Installs the global logger, configured through `RUST_LOG` like `env_logger::init()`, keeping
+/// the last `capacity` lines in the returned buffer
+pub fn init(capacity: usize) -> Result<Arc<LogRingBuffer>, SetLoggerError> {
+    let inner = env_logger::Builder::from_default_env().build();
+    let buffer = Arc::new(LogRingBuffer::new(capacity));
+    let max_level = max(inner.filter(), BUFFER_LEVEL.to_level_filter());
+
+    log::set_boxed_logger(Box::new(RingBufferLogger { inner, buffer: buffer.clone() }))?;
+    log::set_max_level(max_level);
+    Ok(buffer)
+}
+
+/// Serves the content of a `LogRingBuffer`
+#[derive(Debug)]
+pub struct LogFSFileType {
+    pub buffer: Arc<LogRingBuffer>
+}
+
+impl LogFSFileType {
+    pub fn new(buffer: Arc<LogRingBuffer>) -> LogFSFileType {
+        LogFSFileType { buffer }
+    }
+}
+
+impl FSFileTypeOps for LogFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.buffer.contents().len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let contents = self.buffer.contents();
+        let start = min(offset as usize, contents.len());
+        let end = min(start + buffer.len(), contents.len());
+        buffer[..end - start].copy_from_slice(&contents[start..end]);
+        Ok(())
+    }
+}
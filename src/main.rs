@@ -1,48 +1,187 @@
-#[macro_use] extern crate log;
-
-mod fs;
-
-use std::fs::File;
-use std::io::{BufReader, Error};
+use std::io::{self, BufWriter, Error, Write};
 use std::env;
+use std::process;
+use std::time::Duration;
+use std::thread;
+use std::sync::mpsc::{self, Receiver};
+use nix::sys::signal::{SigSet, Signal};
 use serde_json::Value;
-use json_fuse_fs::{FSEntry, FSNode};
-use std::ffi::{OsStr, OsString};
-use fs::JsonFS;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use json_fuse_fs::fs::{JsonFS, ReaddirSort, mount_options, writable_mount_options, validate_mountpoint};
+use json_fuse_fs::export::{export_dir_with, export_tar_with};
+use json_fuse_fs::log_buffer;
+use json_fuse_fs::{BuildOptions, STDIN_DESCRIPTOR, load_descriptor};
 
-fn load_json(path: &str) -> Result<Value, Error> {
-    // Open the file in read-only mode with buffer.
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+/// Number of log lines served by the `.log` file of the mount
+const LOG_CAPACITY: usize = 1000;
 
-    let u: Value = serde_json::from_reader(reader)?;
+fn load_json(path: &str) -> Result<Value, Error> {
+    // Either a file or stdin, for `-`
+    load_descriptor(path)
+}
 
-    Ok(u)
+/// Loads the descriptor at `path` again on each SIGHUP, sending it to the receiver. SIGHUP is
+/// blocked in the calling thread and so in the threads it starts afterwards, so that only the
+/// thread waiting for it takes it: call this before mounting.
+fn reload_on_hangup(path: String) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    let mut hangup = SigSet::empty();
+    hangup.add(Signal::SIGHUP);
+    hangup.thread_block().unwrap();
+    thread::spawn(move || loop {
+        if let Err(e) = hangup.wait() {
+            log::warn!("Cannot wait for SIGHUP, the descriptor won't be reloaded: {}", e);
+            return;
+        }
+        match load_json(&path) {
+            Ok(descriptor) => {
+                log::info!("Reloading the descriptor {}", path);
+                // The file system is unmounted
+                if sender.send(descriptor).is_err() {
+                    return;
+                }
+            },
+            Err(e) => log::warn!("Cannot load the descriptor {}, keeping the current one: {}", path, e)
+        }
+    });
+    receiver
 }
 
 fn main() {
-    env_logger::init();
-
-    let args: Vec<OsString> = env::args_os().collect();
+    let mut args: Vec<OsString> = env::args_os().collect();
+    // Keeping the lines at info level costs on every request, so the `.log` file is opt-in
+    let log_file = args.iter().any(|a| a == "--log-file");
+    args.retain(|a| a != "--log-file");
+    let log_buffer = if log_file {
+        Some(log_buffer::init(LOG_CAPACITY).unwrap())
+    } else {
+        env_logger::init();
+        None
+    };
+    let nonempty = args.iter().any(|a| a == "--nonempty");
+    args.retain(|a| a != "--nonempty");
+    let inodes_dir = args.iter().any(|a| a == "--inodes-dir");
+    args.retain(|a| a != "--inodes-dir");
+    let writable = args.iter().any(|a| a == "--writable");
+    args.retain(|a| a != "--writable");
+    let allow_commands = args.iter().any(|a| a == "--allow-commands");
+    args.retain(|a| a != "--allow-commands");
+    let local_prefix = match args.iter().position(|a| a == "--local-prefix") {
+        Some(i) if i + 1 < args.len() => {
+            let prefix = args.remove(i + 1);
+            args.remove(i);
+            Some(prefix.to_string_lossy().into_owned())
+        },
+        _ => None
+    };
+    let ttl = match args.iter().position(|a| a == "--ttl-ms") {
+        Some(i) if i + 1 < args.len() => {
+            let ttl_ms = args.remove(i + 1);
+            args.remove(i);
+            match ttl_ms.to_string_lossy().parse() {
+                Ok(ttl_ms) => Some(Duration::from_millis(ttl_ms)),
+                Err(_) => {
+                    eprintln!("Invalid TTL {}, expected milliseconds", ttl_ms.to_string_lossy());
+                    process::exit(1);
+                }
+            }
+        },
+        _ => None
+    };
+    let mut owner = [None, None];
+    for (id, flag) in owner.iter_mut().zip(&["--uid", "--gid"]) {
+        if let Some(i) = args.iter().position(|a| a == flag) {
+            if i + 1 < args.len() {
+                let value = args.remove(i + 1);
+                args.remove(i);
+                match value.to_string_lossy().parse::<u32>() {
+                    Ok(value) => *id = Some(value),
+                    Err(_) => {
+                        eprintln!("Invalid {} {}, expected a number", flag, value.to_string_lossy());
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+    let charset = match args.iter().position(|a| a == "--charset") {
+        Some(i) if i + 1 < args.len() => {
+            let charset = args.remove(i + 1);
+            args.remove(i);
+            Some(charset.to_string_lossy().into_owned())
+        },
+        _ => None
+    };
+    let readdir_sort = match args.iter().position(|a| a == "--readdir-sort") {
+        Some(i) if i + 1 < args.len() => {
+            let policy = args.remove(i + 1);
+            args.remove(i);
+            match ReaddirSort::parse(&policy.to_string_lossy()) {
+                Some(readdir_sort) => readdir_sort,
+                None => {
+                    eprintln!("Unknown readdir sort {}, expected source, name, name_desc, size or mtime", policy.to_string_lossy());
+                    process::exit(1);
+                }
+            }
+        },
+        _ => ReaddirSort::Source
+    };
+    let options = BuildOptions { local_prefix, allow_commands, ..BuildOptions::default() };
     let executable_name = args[0].to_str().unwrap();
 
-    if let (Some(filename), Some(mountpoint)) = (args.get(1).and_then(|s| s.to_str()), args.get(2)) {
+    let str_arg = |i: usize| args.get(i).and_then(|s| s.to_str());
+
+    if let (Some("--ls"), Some(path), Some(filename)) = (str_arg(1), str_arg(2), str_arg(3)) {
+        let j = load_json(filename).expect(format!("Cannot load {}", filename).as_str());
+
+        let fs = JsonFS::new_with_options(j, &options).unwrap();
+
+        match fs.ls(path) {
+            Some(entries) => println!("{}", entries),
+            None => {
+                eprintln!("{}: No such file or directory", path);
+                process::exit(1);
+            }
+        }
+    } else if let (Some("--export"), Some(filename), Some(destination)) = (str_arg(1), str_arg(2), str_arg(3)) {
         let j = load_json(filename).expect(format!("Cannot load {}", filename).as_str());
 
-        let (parsed_fs_tree, inode_map) = FSNode::new(j).unwrap();
+        let fs = JsonFS::new_with_options(j, &options).unwrap();
 
-        info!("Parsed FS Tree: {:?}", parsed_fs_tree);
+        export_dir_with(&fs, fs.fs_tree_root(), Path::new(destination)).unwrap();
+    } else if let (Some("--tar"), Some(filename)) = (str_arg(1), str_arg(2)) {
+        let j = load_json(filename).expect(format!("Cannot load {}", filename).as_str());
+
+        let fs = JsonFS::new_with_options(j, &options).unwrap();
+
+        let stdout = io::stdout();
+        let mut writer = export_tar_with(&fs, fs.fs_tree_root(), BufWriter::new(stdout.lock())).unwrap();
+        writer.flush().unwrap();
+    } else if let (Some(filename), Some(mountpoint)) = (str_arg(1), args.get(2)) {
+        let j = load_json(filename).expect(format!("Cannot load {}", filename).as_str());
 
-        let fs = JsonFS::new(parsed_fs_tree, inode_map);
+        if let Err(e) = validate_mountpoint(Path::new(mountpoint)) {
+            eprintln!("Invalid mountpoint {}: {}", mountpoint.to_string_lossy(), e);
+            process::exit(1);
+        }
 
-        let options = ["-o", "ro", "-o", "fsname=jsonfs"]
-            .iter()
-            .map(|o| o.as_ref())
-            .collect::<Vec<&OsStr>>();
+        let fs = if writable { JsonFS::new_writable(j, &options) } else { JsonFS::new_with_options(j, &options) };
+        let fs = fs.unwrap().with_readdir_sort(readdir_sort);
+        let fs = if let Some(log_buffer) = log_buffer { fs.with_log(log_buffer) } else { fs };
+        let fs = if inodes_dir { fs.with_inodes_dir() } else { fs };
+        let fs = if let Some(charset) = charset { fs.with_charset(Some(charset)) } else { fs };
+        let fs = if let Some(ttl) = ttl { fs.with_ttl(ttl) } else { fs };
+        let fs = if let Some(uid) = owner[0] { fs.with_uid(uid) } else { fs };
+        let fs = if let Some(gid) = owner[1] { fs.with_gid(gid) } else { fs };
+        // A descriptor read from stdin has no file to be written back to, nor to be reloaded from
+        let fs = if writable && filename != STDIN_DESCRIPTOR { fs.with_persist_to(PathBuf::from(filename)) } else { fs };
+        let fs = if filename != STDIN_DESCRIPTOR { fs.with_reloads(reload_on_hangup(filename.to_string())) } else { fs };
 
-        fuse::mount(fs, mountpoint, &options).unwrap();
+        let mount_options = if writable { writable_mount_options(nonempty) } else { mount_options(nonempty) };
+        fuse::mount(fs, mountpoint, &mount_options).unwrap();
     } else {
-        panic!("Usage: {} [json_descriptor] [mountpoint]", executable_name)
+        panic!("Usage: {} [--nonempty] [--log-file] [--inodes-dir] [--writable] [--allow-commands] [--readdir-sort policy] [--charset charset] [--ttl-ms milliseconds] [--uid uid] [--gid gid] [--local-prefix directory] [json_descriptor | -] [mountpoint] | --ls [path] [json_descriptor] | --export [json_descriptor] [directory] | --tar [json_descriptor]", executable_name)
     }
 
 }
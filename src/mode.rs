@@ -0,0 +1,56 @@
+use super::*;
+use fuse::FileAttr;
+
+/// Reports the permission bits declared in the descriptor, e.g. `"mode": "0755"` for a script,
+/// rather than the ones of `inner`
+#[derive(Debug)]
+pub struct ModeFSFileType {
+    pub inner: Box<FSFileType>,
+    pub mode: u16
+}
+
+impl ModeFSFileType {
+    pub fn new(inner: FSFileType, mode: u16) -> ModeFSFileType {
+        ModeFSFileType {
+            inner: Box::new(inner),
+            mode
+        }
+    }
+
+    /// Parses octal permission bits like `0755` or `644`
+    pub fn parse_mode(mode: &str) -> Result<u16, DescriptorError> {
+        match u16::from_str_radix(mode, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(mode),
+            _ => Err(DescriptorError::invalid(format!("invalid mode {}", mode)))
+        }
+    }
+}
+
+impl FSFileTypeOps for ModeFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(FileAttr {
+            perm: self.mode,
+            ..self.inner.ops().get_attributes(inode)?
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.inner.ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.ops().cache_on_open()
+    }
+}
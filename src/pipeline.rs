@@ -0,0 +1,138 @@
+use super::*;
+use std::io::Read;
+use std::cmp::min;
+use fuse::FileAttr;
+use crate::export::FSFileReader;
+use crate::filter::run_filter;
+
+/// Built-in transform of a pipeline step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Decompresses gzip content
+    Gunzip,
+    /// Converts UTF-16 text to UTF-8, little endian unless a byte order mark says otherwise
+    Utf16ToUtf8,
+    /// Uppercases text
+    Upper,
+    /// Lowercases text
+    Lower
+}
+
+impl Step {
+    pub fn parse(step: &str) -> Result<Step, DescriptorError> {
+        match step {
+            "gunzip" => Ok(Step::Gunzip),
+            "utf16-to-utf8" => Ok(Step::Utf16ToUtf8),
+            "upper" => Ok(Step::Upper),
+            "lower" => Ok(Step::Lower),
+            _ => Err(DescriptorError::invalid(format!("unknown pipeline step {}", step)))
+        }
+    }
+
+    fn apply(self, input: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Step::Gunzip => run_filter("gzip -dc", input),
+            Step::Utf16ToUtf8 => Ok(utf16_to_utf8(&input).into_bytes()),
+            Step::Upper => Ok(map_text(input, str::to_uppercase, <[u8]>::to_ascii_uppercase)),
+            Step::Lower => Ok(map_text(input, str::to_lowercase, <[u8]>::to_ascii_lowercase))
+        }
+    }
+}
+
+fn utf16_to_utf8(input: &[u8]) -> String {
+    let (big_endian, input) = if input.starts_with(&[0xfe, 0xff]) {
+        (true, &input[2..])
+    } else if input.starts_with(&[0xff, 0xfe]) {
+        (false, &input[2..])
+    } else {
+        (false, input)
+    };
+    let units: Vec<u16> = input
+        .chunks(2)
+        .map(|pair| match (pair, big_endian) {
+            ([high, low], true) | ([low, high], false) => u16::from(*high) << 8 | u16::from(*low),
+            // A trailing odd byte isn't a code unit
+            _ => 0xfffd
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Maps `input` with `text` when it's UTF-8, with `bytes` otherwise
+fn map_text(input: Vec<u8>, text: fn(&str) -> String, bytes: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    match String::from_utf8(input) {
+        Ok(s) => text(&s).into_bytes(),
+        Err(e) => bytes(e.as_bytes())
+    }
+}
+
+/// Serves the content of `source` transformed by `steps` in order, e.g.
+/// `{"type": "pipeline", "source": "file:/x.gz", "steps": ["gunzip", "upper"]}`. Steps may change
+/// the length and can't be streamed, so the result is computed on first access and cached.
+#[derive(Debug)]
+pub struct PipelineFSFileType {
+    pub source: Box<FSFileType>,
+    pub steps: Vec<Step>,
+    output: RefCell<Option<Vec<u8>>>
+}
+
+impl PipelineFSFileType {
+    pub fn new(source: FSFileType, steps: Vec<Step>) -> PipelineFSFileType {
+        PipelineFSFileType {
+            source: Box::new(source),
+            steps,
+            output: RefCell::new(None)
+        }
+    }
+
+    fn run(&self) -> io::Result<Vec<u8>> {
+        let mut content = vec![];
+        FSFileReader::new(self.source.ops())?.read_to_end(&mut content)?;
+        for step in self.steps.iter() {
+            content = step.apply(content)?;
+        }
+        Ok(content)
+    }
+
+    fn with_output<T>(&self, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if self.output.borrow().is_none() {
+            *self.output.borrow_mut() = Some(self.run()?);
+        }
+        Ok(f(self.output.borrow().as_ref().unwrap()))
+    }
+}
+
+impl FSFileTypeOps for PipelineFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            size,
+            blocks: blocks(size, self.block_size()),
+            ..self.source.ops().get_attributes(inode)?
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_output(|output| output.len() as u64)
+    }
+
+    fn block_size(&self) -> u64 {
+        self.source.ops().block_size()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        true
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.with_output(|output| {
+            let start = min(offset as usize, output.len());
+            let end = min(start + buffer.len(), output.len());
+            buffer[..end - start].copy_from_slice(&output[start..end]);
+        })
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.source.ops().probe()
+    }
+}
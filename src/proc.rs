@@ -0,0 +1,124 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::fs::read_to_string;
+use fuse::{FileType, FileAttr};
+use libc::EIO;
+
+/// Value of the running process served by a `proc` file
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ProcField {
+    /// Process id
+    Pid,
+    /// Seconds since the process started
+    Uptime,
+    /// Resident set size, in bytes
+    Rss
+}
+
+impl ProcField {
+    pub fn parse(field: &str) -> Result<ProcField, DescriptorError> {
+        match field {
+            "pid" => Ok(ProcField::Pid),
+            "uptime" => Ok(ProcField::Uptime),
+            "rss" => Ok(ProcField::Rss),
+            _ => Err(DescriptorError::invalid(format!("unknown proc field {}", field)))
+        }
+    }
+}
+
+/// Serves a value of the running process, followed by a newline, e.g.
+/// `{"type": "proc", "field": "uptime"}`. The value is computed again on each access.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct ProcFSFileType {
+    pub field: ProcField
+}
+
+impl ProcFSFileType {
+    pub fn new(field: &str) -> Result<ProcFSFileType, DescriptorError> {
+        Ok(ProcFSFileType { field: ProcField::parse(field)? })
+    }
+
+    fn value(&self) -> io::Result<String> {
+        let value = match self.field {
+            ProcField::Pid => std::process::id().to_string(),
+            ProcField::Uptime => format!("{:.2}", uptime()?),
+            ProcField::Rss => rss()?.to_string()
+        };
+        Ok(format!("{}\n", value))
+    }
+}
+
+fn invalid_proc_data() -> io::Error {
+    io::Error::from_raw_os_error(EIO)
+}
+
+/// Seconds since the process started: the uptime of the system, minus the start time of the
+/// process since boot, reported in clock ticks by `/proc/self/stat`
+fn uptime() -> io::Result<f64> {
+    let system_uptime: f64 = read_to_string("/proc/uptime")?
+        .split_whitespace()
+        .next()
+        .and_then(|u| u.parse().ok())
+        .ok_or_else(invalid_proc_data)?;
+
+    // The command name can contain spaces, so the fields are counted after its closing paren
+    let stat = read_to_string("/proc/self/stat")?;
+    let start_ticks: f64 = stat[stat.rfind(')').ok_or_else(invalid_proc_data)? + 1..]
+        .split_whitespace()
+        .nth(19)
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(invalid_proc_data)?;
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+
+    Ok((system_uptime - start_ticks / ticks_per_second).max(0.0))
+}
+
+/// Resident set size, from the pages counted by `/proc/self/statm`
+fn rss() -> io::Result<u64> {
+    let resident_pages: u64 = read_to_string("/proc/self/statm")?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid_proc_data)?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+
+    Ok(resident_pages * page_size)
+}
+
+impl FSFileTypeOps for ProcFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.value()?.len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.value()?.len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let value = self.value()?;
+        let value = value.as_bytes();
+        let start = min(offset as usize, value.len());
+        let len = min(buffer.len(), value.len() - start);
+        buffer[..len].copy_from_slice(&value[start..start + len]);
+        Ok(())
+    }
+}
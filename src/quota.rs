@@ -0,0 +1,69 @@
+use super::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use fuse::FileAttr;
+use libc::EDQUOT;
+
+/// Serves at most `quota` bytes of `inner` over the lifetime of the process, the reads going
+/// past it failing with `EDQUOT`
+#[derive(Debug)]
+pub struct QuotaFSFileType {
+    pub inner: Box<FSFileType>,
+    pub quota: u64,
+    served: AtomicU64
+}
+
+impl QuotaFSFileType {
+    pub fn new(inner: FSFileType, quota: u64) -> QuotaFSFileType {
+        QuotaFSFileType {
+            inner: Box::new(inner),
+            quota,
+            served: AtomicU64::new(0)
+        }
+    }
+
+    /// Bytes served so far
+    pub fn served(&self) -> u64 {
+        self.served.load(Ordering::SeqCst)
+    }
+
+    /// Counts `len` more bytes as served, unless that goes past the quota
+    fn charge(&self, len: u64) -> io::Result<()> {
+        let mut served = self.served.load(Ordering::SeqCst);
+        loop {
+            if served + len > self.quota {
+                return Err(io::Error::from_raw_os_error(EDQUOT));
+            }
+            match self.served.compare_exchange(served, served + len, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return Ok(()),
+                Err(current) => served = current
+            }
+        }
+    }
+}
+
+impl FSFileTypeOps for QuotaFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.inner.ops().get_attributes(inode)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.charge(buffer.len() as u64)?;
+        self.inner.ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.ops().cache_on_open()
+    }
+}
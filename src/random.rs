@@ -0,0 +1,68 @@
+use super::*;
+use std::time::SystemTime;
+use fuse::{FileType, FileAttr};
+
+/// Serves `size` pseudo-random bytes derived from `seed`, e.g.
+/// `{"type": "random", "size": 1048576, "seed": 42}`. The content is never stored: each run of
+/// 8 bytes is computed from the seed and its offset, so reads at any offset are reproducible.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct RandomFSFileType {
+    pub size: u64,
+    pub seed: u64,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl RandomFSFileType {
+    pub fn new(size: u64, seed: u64) -> RandomFSFileType {
+        RandomFSFileType { size, seed, built: SystemTime::now() }
+    }
+
+    /// The 8 bytes starting at offset `8 * index`, from the SplitMix64 output for the seed and the index
+    fn word(&self, index: u64) -> [u8; 8] {
+        let mut z = self.seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)).to_le_bytes()
+    }
+}
+
+impl FSFileTypeOps for RandomFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let offset = offset as u64;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            let position = offset + i as u64;
+            if position >= self.size {
+                break;
+            }
+            *byte = self.word(position / 8)[(position % 8) as usize];
+        }
+        Ok(())
+    }
+}
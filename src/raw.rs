@@ -1,32 +1,64 @@
 use super::*;
 use std::time::SystemTime;
+use std::cell::Cell;
+use std::cmp::min;
 use fuse::{FileType, FileAttr};
 
+/// Raw data is usually a few bytes, so it's reported in the smallest blocks
+const RAW_BLOCK_SIZE: u64 = BLOCK_UNIT;
+
 #[derive(Debug)]
 #[derive(Eq, PartialEq)]
-#[derive(Hash)]
 pub struct RawFSFileType {
-    pub data: String
+    /// Content of the file, changed by the writes of a `JsonFS::new_writable` file system
+    pub data: RefCell<Vec<u8>>,
+    /// When the file was built, reported as its access and creation times
+    pub built: SystemTime,
+    /// When the content was last changed, reported as its modification and change times
+    pub modified: Cell<SystemTime>
 }
 
 impl RawFSFileType {
     pub fn new(pointer: String) -> RawFSFileType {
+        let built = SystemTime::now();
         RawFSFileType {
-            data: pointer
+            data: RefCell::new(pointer.into_bytes()),
+            built,
+            modified: Cell::new(built)
+        }
+    }
+
+    /// Writes `data` at `offset`, growing the content with zeros up to `offset` when needed. The
+    /// callers bound `offset`, see `JsonFS::write_inode`.
+    pub fn write(&self, offset: i64, data: &[u8]) -> usize {
+        let mut content = self.data.borrow_mut();
+        let start = offset as usize;
+        if content.len() < start + data.len() {
+            content.resize(start + data.len(), 0);
         }
+        content[start..start + data.len()].copy_from_slice(data);
+        self.modified.set(SystemTime::now());
+        data.len()
+    }
+
+    /// Truncates the content to `size`, or grows it with zeros up to `size`
+    pub fn set_size(&self, size: usize) {
+        self.data.borrow_mut().resize(size, 0);
+        self.modified.set(SystemTime::now());
     }
 }
 
 impl FSFileTypeOps for RawFSFileType {
-    fn get_attributes(&self, inode: u64) -> FileAttr {
-        FileAttr {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.data.borrow().len() as u64;
+        Ok(FileAttr {
             ino: inode,
-            size: self.data.bytes().len() as u64,
-            blocks: 1,
-            atime: SystemTime::now(),
-            mtime: SystemTime::now(),
-            ctime: SystemTime::now(),
-            crtime: SystemTime::now(),
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.modified.get(),
+            ctime: self.modified.get(),
+            crtime: self.built,
             kind: FileType::RegularFile,
             perm: 0o644,
             nlink: 1,
@@ -34,16 +66,23 @@ impl FSFileTypeOps for RawFSFileType {
             gid: nix::unistd::getgid().into(),
             rdev: 0,
             flags: 0
-        }
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.borrow().len() as u64)
+    }
+
+    fn block_size(&self) -> u64 {
+        RAW_BLOCK_SIZE
     }
 
     fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
-        let off = offset as usize;
-        if buffer.len() > self.data.len() - off {
-            buffer[..self.data.len() - off].copy_from_slice(&self.data.as_bytes()[offset as usize..])
-        } else {
-            buffer.copy_from_slice(&self.data.as_bytes()[offset as usize..buffer.len()])
-        }
+        // The kernel may read past the end, which serves no bytes
+        let data = self.data.borrow();
+        let start = min(offset as usize, data.len());
+        let end = min(start + buffer.len(), data.len());
+        buffer[..end - start].copy_from_slice(&data[start..end]);
         Ok(())
     }
 }
@@ -0,0 +1,57 @@
+use super::*;
+use std::time::SystemTime;
+use fuse::{FileType, FileAttr};
+
+/// Serves the content of the file at `path`, e.g. `ref:/blobs/large.bin`, so that a file can appear
+/// at several places of the tree without being declared twice. Like `attrof`, the target is
+/// resolved by `JsonFS`, which checks that all the references lead to a file when mounting.
+/// Outside of it, the file reads as empty.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct RefFSFileType {
+    pub path: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+impl RefFSFileType {
+    pub fn new(path: String) -> Result<RefFSFileType, DescriptorError> {
+        if !path.starts_with('/') {
+            return Err(DescriptorError::invalid(format!("the ref target {} is not an absolute path", path)));
+        }
+        Ok(RefFSFileType {
+            path,
+            built: SystemTime::now()
+        })
+    }
+}
+
+impl FSFileTypeOps for RefFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(FileAttr {
+            ino: inode,
+            size: 0,
+            blocks: 0,
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn read(&self, _offset: i64, _buffer: &mut [u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::{min, Reverse};
+use std::fs::{File, metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use fuse::{FileType, FileAttr};
+
+/// Serves a rotating set of files, e.g. `app.log`, `app.log.1`, `app.log.2`, as one concatenated
+/// stream. The set is globbed again on each access, so new rotations are picked up.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct RotateFSFileType {
+    pub pattern: String,
+    /// Serve the current file first, rather than the oldest rotation
+    pub newest_first: bool
+}
+
+impl RotateFSFileType {
+    pub fn new(pattern: String, newest_first: bool) -> Result<RotateFSFileType, DescriptorError> {
+        glob::Pattern::new(&pattern).map_err(|e| DescriptorError::invalid(format!("invalid pattern {}: {}", pattern, e)))?;
+        Ok(RotateFSFileType { pattern, newest_first })
+    }
+
+    /// Path of the current file: the pattern up to its first wildcard
+    fn base(&self) -> &str {
+        let end = self.pattern.find(|c| c == '*' || c == '?' || c == '[').unwrap_or_else(|| self.pattern.len());
+        &self.pattern[..end]
+    }
+
+    /// Rotation index of `path`: 0 for the current file, `n` for `<base>.n`
+    fn rotation_index(&self, path: &Path) -> Option<u32> {
+        let suffix = path.to_str()?.get(self.base().len()..)?;
+        if suffix.is_empty() {
+            Some(0)
+        } else if suffix.starts_with('.') {
+            suffix[1..].parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// The files of the rotation set, in serving order. Files are ordered by rotation index, the
+    /// ones without an index coming after, newest first.
+    fn files(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        let paths = glob::glob(&self.pattern).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        let mut files = vec![];
+        for path in paths {
+            let path = path.map_err(|e| io::Error::new(e.error().kind(), e.to_string()))?;
+            let meta = metadata(&path)?;
+            if meta.is_file() {
+                let index = self.rotation_index(&path).unwrap_or(u32::max_value());
+                files.push((index, Reverse(meta.modified()?), path, meta.len()));
+            }
+        }
+        files.sort();
+        if !self.newest_first {
+            files.reverse();
+        }
+        Ok(files.into_iter().map(|(_, _, path, len)| (path, len)).collect())
+    }
+}
+
+impl FSFileTypeOps for RotateFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.files()?.iter().map(|(_, len)| len).sum())
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let mut file_start = 0;
+        let mut written = 0;
+        for (path, len) in self.files()? {
+            if written == buffer.len() {
+                break;
+            }
+            let position = offset as u64 + written as u64;
+            if position < file_start + len {
+                let skip = position - file_start;
+                let to_read = min(len - skip, (buffer.len() - written) as u64) as usize;
+                let mut file = File::open(&path)?;
+                file.seek(SeekFrom::Start(skip))?;
+                file.read_exact(&mut buffer[written..written + to_read])?;
+                written += to_read;
+            }
+            file_start += len;
+        }
+        Ok(())
+    }
+}
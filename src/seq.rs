@@ -0,0 +1,114 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use fuse::{FileType, FileAttr};
+
+/// Serves the numbers from `from` to `to`, each followed by `sep`, e.g.
+/// `{"type": "seq", "from": 1, "to": 1000000, "sep": "\n"}`. The content is never materialized:
+/// reads find the number an offset falls in from the runs of numbers with the same digit count.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct SeqFSFileType {
+    pub from: u64,
+    pub to: u64,
+    pub sep: String,
+    /// When the file was built, reported as its times
+    pub built: SystemTime
+}
+
+/// Run of consecutive numbers with the same digit count, so taking the same bytes each
+struct Run {
+    first: u64,
+    last: u64,
+    item_len: u64
+}
+
+impl Run {
+    fn size(&self) -> u64 {
+        (self.last - self.first + 1).saturating_mul(self.item_len)
+    }
+}
+
+impl SeqFSFileType {
+    pub fn new(from: u64, to: u64, sep: String) -> Result<SeqFSFileType, DescriptorError> {
+        if from > to {
+            return Err(DescriptorError::invalid(format!("seq from {} is after to {}", from, to)));
+        }
+        Ok(SeqFSFileType { from, to, sep, built: SystemTime::now() })
+    }
+
+    fn runs(&self) -> Vec<Run> {
+        let mut runs = vec![];
+        let mut first = self.from;
+        let mut digits = first.to_string().len() as u32;
+        loop {
+            // u64::MAX has 20 digits, so its run ends with the type
+            let run_last = 10u64.checked_pow(digits).map(|limit| limit - 1).unwrap_or(std::u64::MAX);
+            let last = min(self.to, run_last);
+            runs.push(Run { first, last, item_len: u64::from(digits) + self.sep.len() as u64 });
+            if last == self.to {
+                return runs;
+            }
+            first = last + 1;
+            digits += 1;
+        }
+    }
+}
+
+impl FSFileTypeOps for SeqFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.runs().iter().fold(0u64, |size, run| size.saturating_add(run.size())))
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let mut offset = offset as u64;
+        let mut filled = 0;
+
+        for run in self.runs() {
+            if filled == buffer.len() {
+                break;
+            }
+            if offset >= run.size() {
+                offset -= run.size();
+                continue;
+            }
+
+            let mut number = run.first + offset / run.item_len;
+            let mut skip = (offset % run.item_len) as usize;
+            while filled < buffer.len() {
+                let item = format!("{}{}", number, self.sep);
+                let len = min(item.len() - skip, buffer.len() - filled);
+                buffer[filled..filled + len].copy_from_slice(&item.as_bytes()[skip..skip + len]);
+                filled += len;
+                skip = 0;
+                if number == run.last {
+                    break;
+                }
+                number += 1;
+            }
+            offset = 0;
+        }
+        Ok(())
+    }
+}
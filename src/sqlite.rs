@@ -0,0 +1,113 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use fuse::{FileType, FileAttr};
+use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use rusqlite::types::Value;
+use libc::EIO;
+use log::info;
+
+/// Serves the result of a query as CSV. The query runs on first access and its result is cached.
+#[derive(Debug)]
+pub struct SqliteFSFileType {
+    pub database: String,
+    pub query: String,
+    result: RefCell<Option<Vec<u8>>>
+}
+
+impl SqliteFSFileType {
+    /// Parses pointers like `/data/app.db#SELECT id,name FROM users`
+    pub fn new(pointer: String) -> Result<SqliteFSFileType, DescriptorError> {
+        let separator = pointer.find('#').ok_or_else(|| DescriptorError::invalid(format!("sqlite pointer {} has no #query", pointer)))?;
+        Ok(SqliteFSFileType {
+            database: pointer[..separator].to_string(),
+            query: pointer[separator + 1..].to_string(),
+            result: RefCell::new(None)
+        })
+    }
+
+    fn run_query(&self) -> rusqlite::Result<Vec<u8>> {
+        let connection = Connection::open_with_flags(&self.database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut statement = connection.prepare(&self.query)?;
+
+        let column_count = statement.column_count();
+        let header: Vec<String> = statement.column_names().iter().map(|c| csv_field(c)).collect();
+
+        let mut csv = header.join(",");
+        csv.push('\n');
+
+        let mut rows = statement.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let fields = (0..column_count)
+                .map(|i| row.get::<_, Value>(i).map(csv_value))
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+
+        Ok(csv.into_bytes())
+    }
+
+    fn with_result<T, F: FnOnce(&[u8]) -> T>(&self, f: F) -> io::Result<T> {
+        if self.result.borrow().is_none() {
+            let result = self.run_query().map_err(|e| {
+                info!("Query {} on {} failed: {}", self.query, self.database, e);
+                io::Error::from_raw_os_error(EIO)
+            })?;
+            *self.result.borrow_mut() = Some(result);
+        }
+        Ok(f(self.result.borrow().as_ref().unwrap()))
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_value(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => csv_field(&s),
+        Value::Blob(b) => csv_field(&String::from_utf8_lossy(&b))
+    }
+}
+
+impl FSFileTypeOps for SqliteFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_result(|r| r.len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.with_result(|result| {
+            let start = min(offset as usize, result.len());
+            let end = min(start + buffer.len(), result.len());
+            buffer[..end - start].copy_from_slice(&result[start..end]);
+        })
+    }
+}
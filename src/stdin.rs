@@ -0,0 +1,79 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use fuse::{FileType, FileAttr};
+use log::warn;
+
+static STDIN_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Reads the whole stdin of the process. Stdin can be consumed only once, by either the
+/// descriptor or a `stdin:` file, so later calls fail.
+pub fn take_stdin() -> io::Result<Vec<u8>> {
+    if STDIN_TAKEN.swap(true, Ordering::SeqCst) {
+        return Err(io::Error::new(io::ErrorKind::Other, "stdin was already consumed"));
+    }
+    let mut data = vec![];
+    io::stdin().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Serves the content of the stdin of the process, read to EOF when the tree is built
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct StdinFSFileType {
+    pub data: Vec<u8>,
+    /// When stdin was read, reported as the times of the file
+    pub built: SystemTime
+}
+
+impl StdinFSFileType {
+    pub fn new() -> Result<StdinFSFileType, DescriptorError> {
+        let data = take_stdin().map_err(|e| {
+            warn!("Cannot read stdin: {}", e);
+            DescriptorError::invalid(format!("cannot read stdin: {}", e))
+        })?;
+        Ok(StdinFSFileType { data, built: SystemTime::now() })
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<StdinFSFileType> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Ok(StdinFSFileType { data, built: SystemTime::now() })
+    }
+}
+
+impl FSFileTypeOps for StdinFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.data.len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let start = min(offset as usize, self.data.len());
+        let end = min(start + buffer.len(), self.data.len());
+        buffer[..end - start].copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use super::*;
+use std::time::SystemTime;
+use std::cmp::min;
+use fuse::{FileType, FileAttr};
+
+/// A symbolic link to `target`, e.g. `symlink:/etc/hostname`. The target is resolved by the
+/// kernel, relative ones from the directory of the link.
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+pub struct SymlinkFSFileType {
+    pub target: String,
+    /// When the link was built, reported as its times
+    pub built: SystemTime
+}
+
+impl SymlinkFSFileType {
+    pub fn new(target: String) -> Result<SymlinkFSFileType, DescriptorError> {
+        if target.is_empty() {
+            return Err(DescriptorError::invalid("empty symlink target"));
+        }
+        Ok(SymlinkFSFileType {
+            target,
+            built: SystemTime::now()
+        })
+    }
+}
+
+impl FSFileTypeOps for SymlinkFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.target.len() as u64;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: 0,
+            atime: self.built,
+            mtime: self.built,
+            ctime: self.built,
+            crtime: self.built,
+            kind: FileType::Symlink,
+            perm: 0o777,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.target.len() as u64)
+    }
+
+    /// Serves the target, as `readlink` does
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let target = self.target.as_bytes();
+        let start = min(offset as usize, target.len());
+        let end = min(start + buffer.len(), target.len());
+        buffer[..end - start].copy_from_slice(&target[start..end]);
+        Ok(())
+    }
+}
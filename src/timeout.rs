@@ -0,0 +1,93 @@
+use super::*;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use fuse::FileAttr;
+use libc::{EIO, ETIMEDOUT};
+use log::info;
+
+/// Runs the reads and the attribute lookups of `inner` on a helper thread, failing with
+/// `ETIMEDOUT` when they take longer than `timeout`, so a blocking source can't wedge the FUSE
+/// worker. A read stuck past its timeout keeps `inner` locked, so the following calls time out
+/// too until it returns.
+#[derive(Debug)]
+pub struct TimeoutFSFileType {
+    pub inner: Arc<Mutex<FSFileType>>,
+    pub timeout: Duration
+}
+
+impl TimeoutFSFileType {
+    pub fn new(inner: FSFileType, timeout: Duration) -> TimeoutFSFileType {
+        TimeoutFSFileType {
+            inner: Arc::new(Mutex::new(inner)),
+            timeout
+        }
+    }
+
+    /// Runs `op` on `inner` from a helper thread, waiting for it at most `timeout`. A helper that
+    /// panicked leaves `inner` poisoned, failing the following calls with `EIO`.
+    fn run<T, F>(&self, what: &str, op: F) -> io::Result<T>
+        where T: Send + 'static, F: FnOnce(&FSFileType) -> io::Result<T> + Send + 'static {
+        let (sender, receiver) = channel();
+        let inner = self.inner.clone();
+
+        thread::spawn(move || {
+            let result = match inner.lock() {
+                Ok(inner) => op(&inner),
+                Err(_) => Err(io::Error::from_raw_os_error(EIO))
+            };
+            // The receiver is gone if the call already timed out
+            let _ = sender.send(result);
+        });
+
+        match receiver.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                info!("{} timed out after {:?}", what, self.timeout);
+                Err(io::Error::from_raw_os_error(ETIMEDOUT))
+            },
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::from_raw_os_error(EIO))
+        }
+    }
+}
+
+impl FSFileTypeOps for TimeoutFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.run("Attributes lookup", move |inner| inner.ops().get_attributes(inode))
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.run("Size lookup", |inner| inner.ops().size())
+    }
+
+    fn block_size(&self) -> u64 {
+        // Not worth a helper thread: a busy or poisoned inner falls back to the default
+        match self.inner.try_lock() {
+            Ok(inner) => inner.ops().block_size(),
+            Err(_) => DEFAULT_BLOCK_SIZE
+        }
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        let len = buffer.len();
+        let data = self.run(&format!("Read at offset {}", offset), move |inner| {
+            let mut data = vec![0; len];
+            inner.ops().read(offset, &mut data).map(|()| data)
+        })?;
+        buffer.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.run("Probe", |inner| inner.ops().probe())
+    }
+
+    fn cache_on_open(&self) -> bool {
+        // Like `block_size`, a busy or poisoned inner falls back to the default
+        match self.inner.try_lock() {
+            Ok(inner) => inner.ops().cache_on_open(),
+            Err(_) => false
+        }
+    }
+}
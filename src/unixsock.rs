@@ -0,0 +1,100 @@
+use super::*;
+use std::time::{Duration, SystemTime};
+use std::cmp::min;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use fuse::{FileType, FileAttr};
+use libc::EIO;
+use log::info;
+
+/// How long to wait for data from the socket when not configured
+pub const DEFAULT_UNIXSOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the bytes received from a Unix domain socket. On first access it connects, sends the
+/// optional request line and reads until the peer closes the connection or stops sending for
+/// `timeout`. The response is cached.
+#[derive(Debug)]
+pub struct UnixSockFSFileType {
+    pub socket_path: String,
+    pub request: Option<String>,
+    pub timeout: Duration,
+    response: RefCell<Option<Vec<u8>>>
+}
+
+impl UnixSockFSFileType {
+    pub fn new(socket_path: String, request: Option<String>, timeout: Duration) -> UnixSockFSFileType {
+        UnixSockFSFileType {
+            socket_path,
+            request,
+            timeout,
+            response: RefCell::new(None)
+        }
+    }
+
+    fn receive(&self) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        if let Some(request) = &self.request {
+            stream.write_all(request.as_bytes())?;
+            stream.write_all(b"\n")?;
+        }
+        stream.shutdown(Shutdown::Write)?;
+
+        let mut response = vec![];
+        match stream.read_to_end(&mut response) {
+            Ok(_) => Ok(response),
+            // A streaming peer doesn't close the connection: serve what it sent so far
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(response),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn with_response<T, F: FnOnce(&[u8]) -> T>(&self, f: F) -> io::Result<T> {
+        if self.response.borrow().is_none() {
+            let response = self.receive().map_err(|e| {
+                info!("Cannot receive from {}: {}", self.socket_path, e);
+                match e.raw_os_error() {
+                    Some(errno) => io::Error::from_raw_os_error(errno),
+                    None => io::Error::from_raw_os_error(EIO)
+                }
+            })?;
+            *self.response.borrow_mut() = Some(response);
+        }
+        Ok(f(self.response.borrow().as_ref().unwrap()))
+    }
+}
+
+impl FSFileTypeOps for UnixSockFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        let size = self.size()?;
+        Ok(FileAttr {
+            ino: inode,
+            size,
+            blocks: blocks(size, self.block_size()),
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: nix::unistd::getuid().into(),
+            gid: nix::unistd::getgid().into(),
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_response(|r| r.len() as u64)
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.with_response(|response| {
+            let start = min(offset as usize, response.len());
+            let end = min(start + buffer.len(), response.len());
+            buffer[..end - start].copy_from_slice(&response[start..end]);
+        })
+    }
+}
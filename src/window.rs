@@ -0,0 +1,112 @@
+use super::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use fuse::FileAttr;
+use libc::ENOENT;
+
+/// Serves `inner` only between `after` and `before`, failing with `ENOENT` otherwise, as if the
+/// file didn't exist yet or anymore
+#[derive(Debug)]
+pub struct WindowFSFileType {
+    pub inner: Box<FSFileType>,
+    pub after: Option<SystemTime>,
+    pub before: Option<SystemTime>,
+    clock: fn() -> SystemTime
+}
+
+impl WindowFSFileType {
+    pub fn new(inner: FSFileType, after: Option<SystemTime>, before: Option<SystemTime>) -> WindowFSFileType {
+        WindowFSFileType {
+            inner: Box::new(inner),
+            after,
+            before,
+            clock: SystemTime::now
+        }
+    }
+
+    /// Parses an `available` object like `{"after": "2024-01-01T00:00:00Z", "before": "2025-01-01T00:00:00Z"}`,
+    /// where both bounds are optional
+    pub fn parse(inner: FSFileType, available: &serde_json::Value) -> Result<WindowFSFileType, DescriptorError> {
+        let available = available.as_object().ok_or_else(|| DescriptorError::invalid("available is not an object"))?;
+        let bound = |key: &str| match available.get(key) {
+            Some(time) => parse_utc_time(time.as_str().ok_or_else(|| DescriptorError::invalid(format!("available {} is not a string", key)))?).map(Some),
+            None => Ok(None)
+        };
+        Ok(WindowFSFileType::new(inner, bound("after")?, bound("before")?))
+    }
+
+    /// Replaces the system clock the window is checked against
+    pub fn with_clock(mut self, clock: fn() -> SystemTime) -> WindowFSFileType {
+        self.clock = clock;
+        self
+    }
+
+    fn check_available(&self) -> io::Result<()> {
+        let now = (self.clock)();
+        let started = self.after.map(|after| now >= after).unwrap_or(true);
+        let ended = self.before.map(|before| now >= before).unwrap_or(false);
+        if started && !ended {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(ENOENT))
+        }
+    }
+}
+
+/// Parses a UTC time like `2024-01-01T00:00:00Z`
+fn parse_utc_time(time: &str) -> Result<SystemTime, DescriptorError> {
+    let invalid = || DescriptorError::invalid(format!("invalid UTC time {}", time));
+    let bytes = time.as_bytes();
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':'), (19, b'Z')];
+    if bytes.len() != 20 || separators.iter().any(|&(i, c)| bytes[i] != c) {
+        return Err(invalid());
+    }
+    let field = |range: std::ops::Range<usize>| time[range].parse::<u64>().map_err(|_| invalid());
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if year < 1970 || month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+
+    let days = days_since_epoch(year, month, day);
+    Ok(UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second))
+}
+
+/// Days between 1970-01-01 and a date of the proleptic Gregorian calendar
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Years start in March, so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+impl FSFileTypeOps for WindowFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        self.check_available()?;
+        self.inner.ops().get_attributes(inode)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.check_available()?;
+        self.inner.ops().size()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.inner.ops().block_size()
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        self.check_available()?;
+        self.inner.ops().read(offset, buffer)
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.inner.ops().probe()
+    }
+
+    fn cache_on_open(&self) -> bool {
+        self.inner.ops().cache_on_open()
+    }
+}
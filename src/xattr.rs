@@ -0,0 +1,61 @@
+use super::*;
+use std::path::Path;
+
+/// Extended attribute computed for each file, from the extension of its name
+pub const MIME_TYPE_XATTR: &str = "user.mime_type";
+
+/// MIME type of the files whose extension isn't known
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("log", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "application/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("tar", "application/x-tar"),
+    ("gz", "application/gzip"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml")
+];
+
+/// MIME type of a file named `name`, guessed from its extension
+pub fn mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    extension
+        .and_then(|extension| MIME_TYPES.iter().find(|(e, _)| *e == extension))
+        .map(|(_, mime_type)| *mime_type)
+        .unwrap_or(DEFAULT_MIME_TYPE)
+}
+
+/// MIME type of a file named `name`, with `; charset=<charset>` appended for the text types
+pub fn mime_type_with_charset(name: &str, charset: Option<&str>) -> String {
+    let mime_type = mime_type(name);
+    match charset {
+        Some(charset) if mime_type.starts_with("text/") => format!("{}; charset={}", mime_type, charset),
+        _ => mime_type.to_string()
+    }
+}
+
+/// Parses the `xattrs` of an object file descriptor, a map from attribute names to string values
+pub fn parse_xattrs(descriptor: &serde_json::Value) -> Result<HashMap<String, Vec<u8>>, DescriptorError> {
+    descriptor
+        .as_object()
+        .ok_or_else(|| DescriptorError::invalid("xattrs is not an object"))?
+        .iter()
+        .map(|(name, value)| match value.as_str() {
+            Some(value) => Ok((name.clone(), value.as_bytes().to_vec())),
+            None => Err(DescriptorError::invalid(format!("xattr {} is not a string", name)))
+        })
+        .collect()
+}
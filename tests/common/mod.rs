@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request received by a `MockServer`
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The response a `MockServer` replies with. `Content-Length` is added when missing.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl MockResponse {
+    pub fn ok(body: &[u8]) -> MockResponse {
+        MockResponse { status: 200, headers: vec![], body: body.to_vec() }
+    }
+
+    pub fn with_status(mut self, status: u16) -> MockResponse {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Minimal HTTP/1.1 server running on background threads, answering each connection on its own
+/// thread with the response built by its handler and recording the requests it receives
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> MockServer where F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler = Arc::new(handler);
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let (handler, recorded) = (handler.clone(), recorded.clone());
+                    thread::spawn(move || {
+                        if let Some(request) = read_request(&stream) {
+                            let response = handler(&request);
+                            recorded.lock().unwrap().push(request.clone());
+                            write_response(stream, &request, &response);
+                        }
+                    });
+                }
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<MockRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let separator = line.find(':')?;
+        headers.push((line[..separator].trim().to_string(), line[separator + 1..].trim().to_string()));
+    }
+
+    Some(MockRequest { method, path, headers })
+}
+
+fn write_response(mut stream: TcpStream, request: &MockRequest, response: &MockResponse) {
+    let mut head = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", response.status);
+    for (name, value) in response.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !response.headers.iter().any(|(n, _)| n.eq_ignore_ascii_case("content-length")) {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    if request.method != "HEAD" {
+        let _ = stream.write_all(&response.body);
+    }
+}
@@ -0,0 +1,162 @@
+extern crate json_fuse_fs;
+
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::time::SystemTime;
+use fuse::{FileAttr, FileType};
+use json_fuse_fs::*;
+use json_fuse_fs::fs::JsonFS;
+use json_fuse_fs::export::{FSFileReader, export_file, export_dir, export_dir_with, export_tar, export_tar_with, EXPORT_BUFFER_SIZE};
+
+/// Serves `size` bytes of a repeating pattern, recording the biggest read it's asked for
+struct InstrumentedFSFileType {
+    size: u64,
+    max_read: Cell<usize>
+}
+
+impl FSFileTypeOps for InstrumentedFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        Ok(FileAttr {
+            ino: inode,
+            size: self.size,
+            blocks: 1,
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            flags: 0
+        })
+    }
+
+    fn read(&self, offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        if buffer.len() > self.max_read.get() {
+            self.max_read.set(buffer.len());
+        }
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = ((offset as usize + i) % 251) as u8;
+        }
+        Ok(())
+    }
+}
+
+/// Fills only the first byte of each read
+struct ShortReadFSFileType;
+
+impl FSFileTypeOps for ShortReadFSFileType {
+    fn get_attributes(&self, inode: u64) -> io::Result<FileAttr> {
+        InstrumentedFSFileType { size: 4, max_read: Cell::new(0) }.get_attributes(inode)
+    }
+
+    fn read(&self, _offset: i64, buffer: &mut [u8]) -> io::Result<()> {
+        buffer[0] = b'x';
+        Ok(())
+    }
+}
+
+#[test]
+fn short_reads_are_padded_with_zeros() {
+    let mut reader = FSFileReader::new(&ShortReadFSFileType).unwrap();
+    let mut buffer = [0xff; 8];
+
+    assert_eq!(reader.read(&mut buffer).unwrap(), 4);
+    assert_eq!(&buffer[..4], b"x\0\0\0");
+}
+
+#[test]
+fn export_large_file_with_bounded_buffer() {
+    let file_type = InstrumentedFSFileType { size: 16 * 1024 * 1024 + 3, max_read: Cell::new(0) };
+
+    let mut exported = Vec::new();
+    let written = export_file(&file_type, &mut exported).unwrap();
+
+    assert_eq!(written, file_type.size);
+    assert_eq!(exported.len() as u64, file_type.size);
+    assert!(exported.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+    assert!(file_type.max_read.get() <= EXPORT_BUFFER_SIZE);
+}
+
+#[test]
+fn export_tar_streams_entries() {
+    let json = r#"
+            {
+                "file.txt": "raw:abc",
+                "nested": {
+                    "nested.txt": "raw:nested content"
+                }
+            }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    let tar_bytes = export_tar(&fs_tree, Vec::new()).unwrap();
+
+    let mut archive = tar::Archive::new(&tar_bytes[..]);
+    let mut entries: Vec<(String, tar::EntryType, Vec<u8>)> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().trim_end_matches('/').to_string();
+            let entry_type = entry.header().entry_type();
+            let mut content = vec![];
+            entry.read_to_end(&mut content).unwrap();
+            (path, entry_type, content)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0], ("file.txt".to_string(), tar::EntryType::Regular, b"abc".to_vec()));
+    assert_eq!(entries[1].0, "nested");
+    assert_eq!(entries[1].1, tar::EntryType::Directory);
+    assert_eq!(entries[2], ("nested/nested.txt".to_string(), tar::EntryType::Regular, b"nested content".to_vec()));
+}
+
+#[test]
+fn export_dir_recreates_symlinks() {
+    let json = r#"{ "target.txt": "raw:abc", "link": "symlink:target.txt" }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+    let destination = tempfile::tempdir().unwrap();
+
+    export_dir(&fs_tree, &destination.path().join("out")).unwrap();
+
+    let link = destination.path().join("out").join("link");
+    assert_eq!(std::fs::read_link(&link).unwrap().to_str().unwrap(), "target.txt");
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "abc");
+}
+
+#[test]
+fn export_resolves_refs_through_json_fs() {
+    let json = r#"{ "a.txt": "raw:abc", "b.txt": "ref:/a.txt" }"#;
+    let fs = JsonFS::new(serde_json::from_str(json).unwrap()).unwrap();
+    let destination = tempfile::tempdir().unwrap();
+
+    export_dir_with(&fs, fs.fs_tree_root(), &destination.path().join("out")).unwrap();
+    assert_eq!(std::fs::read_to_string(destination.path().join("out").join("b.txt")).unwrap(), "abc");
+
+    let tar_bytes = export_tar_with(&fs, fs.fs_tree_root(), Vec::new()).unwrap();
+    let mut archive = tar::Archive::new(&tar_bytes[..]);
+    let mut entry = archive.entries().unwrap()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.path().unwrap().to_str() == Some("b.txt"))
+        .unwrap();
+    assert_eq!(entry.header().size().unwrap(), 3);
+    let mut content = vec![];
+    entry.read_to_end(&mut content).unwrap();
+    assert_eq!(content, b"abc");
+}
+
+#[test]
+fn export_of_refs_without_json_fs_fails() {
+    let json = r#"{ "a.txt": "raw:abc", "b.txt": "ref:/a.txt" }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+    let destination = tempfile::tempdir().unwrap();
+
+    let err = export_dir(&fs_tree, &destination.path().join("out")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(export_tar(&fs_tree, Vec::new()).is_err());
+}
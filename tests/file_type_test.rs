@@ -0,0 +1,1090 @@
+extern crate json_fuse_fs;
+
+mod common;
+
+use std::env;
+use std::cmp::min;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::io::{Write, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use json_fuse_fs::*;
+use common::{MockServer, MockResponse};
+
+macro_rules! with_file_type {
+    ($json:expr, $path:expr, |$file_type:ident| $body:expr) => ({
+        let (fs_tree, _) = FSNode::new(serde_json::from_str($json).unwrap()).unwrap();
+        if let FSNode { entry: FSEntry::File($file_type), .. } = &*fs_tree.walk($path.to_string()).unwrap() {
+            $body
+        } else {
+            panic!("FSNode.entry is not a FSEntry::File(_)")
+        }
+    });
+}
+
+#[test]
+fn cond_file_type_when_condition_holds() {
+    env::set_var("JSON_FUSE_FS_COND_HOLDS", "on");
+    let json = r#"
+            {
+                "flagged.txt": { "type": "cond", "when": "env:JSON_FUSE_FS_COND_HOLDS==on", "then": "raw:abc" }
+            }"#;
+
+    with_file_type!(json, "/flagged.txt", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, 3);
+
+        let mut buffer = vec![0; 3];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"abc");
+    });
+}
+
+#[test]
+fn cond_file_type_when_condition_does_not_hold() {
+    env::set_var("JSON_FUSE_FS_COND_DOES_NOT_HOLD", "off");
+    let json = r#"
+            {
+                "flagged.txt": { "type": "cond", "when": "env:JSON_FUSE_FS_COND_DOES_NOT_HOLD==on", "then": "raw:abc" }
+            }"#;
+
+    with_file_type!(json, "/flagged.txt", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, 0);
+
+        let mut buffer = vec![0; 3];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, vec![0; 3]);
+    });
+}
+
+#[test]
+fn sqlite_file_type_serves_query_as_csv() {
+    let dir = tempfile::tempdir().unwrap();
+    let database = dir.path().join("app.db");
+
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    connection.execute_batch(r#"
+        CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+        INSERT INTO users (id, name) VALUES (1, 'francesco');
+        INSERT INTO users (id, name) VALUES (2, 'slinky, developer');
+    "#).unwrap();
+
+    let json = format!(r#"{{ "users.csv": "sqlite:{}#SELECT id,name FROM users ORDER BY id" }}"#, database.display());
+    let expected = "id,name\n1,francesco\n2,\"slinky, developer\"\n";
+
+    with_file_type!(&json, "/users.csv", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, expected.len() as u64);
+
+        let mut buffer = vec![0; expected.len()];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), expected);
+    });
+}
+
+#[test]
+fn sqlite_file_type_query_error_is_eio() {
+    let json = r#"{ "users.csv": "sqlite:/does/not/exist.db#SELECT id FROM users" }"#;
+
+    with_file_type!(json, "/users.csv", |file_type| {
+        let mut buffer = vec![0; 10];
+        let err = file_type.ops().read(0, &mut buffer).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    });
+}
+
+#[test]
+fn read_timeout_on_blocking_fifo() {
+    let dir = tempfile::tempdir().unwrap();
+    let fifo = dir.path().join("fifo");
+    nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+    // Opening a fifo without any writer blocks forever
+    let json = format!(r#"{{ "fifo": {{ "type": "file", "path": "{}", "read_timeout_ms": 100 }} }}"#, fifo.display());
+
+    with_file_type!(&json, "/fifo", |file_type| {
+        let start = Instant::now();
+        let mut buffer = vec![0; 10];
+        let err = file_type.ops().read(0, &mut buffer).unwrap_err();
+
+        assert_eq!(err.raw_os_error(), Some(libc::ETIMEDOUT));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    });
+}
+
+#[test]
+fn read_timeout_bounds_the_calls_following_a_timed_out_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let fifo = dir.path().join("fifo");
+    nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+    let json = format!(r#"{{ "fifo": {{ "type": "file", "path": "{}", "read_timeout_ms": 100 }} }}"#, fifo.display());
+
+    with_file_type!(&json, "/fifo", |file_type| {
+        let mut buffer = vec![0; 10];
+        assert_eq!(file_type.ops().read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::ETIMEDOUT));
+
+        // The stuck read still holds the fifo, the next calls time out rather than block
+        let start = Instant::now();
+        assert_eq!(file_type.ops().get_attributes(0).unwrap_err().raw_os_error(), Some(libc::ETIMEDOUT));
+        assert_eq!(file_type.ops().size().unwrap_err().raw_os_error(), Some(libc::ETIMEDOUT));
+        assert_eq!(file_type.ops().read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::ETIMEDOUT));
+        assert_eq!(file_type.ops().block_size(), DEFAULT_BLOCK_SIZE);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    });
+}
+
+#[test]
+fn read_timeout_not_exceeded() {
+    let json = r#"{ "file.txt": { "type": "raw", "data": "abc", "read_timeout_ms": 1000 } }"#;
+
+    with_file_type!(json, "/file.txt", |file_type| {
+        let mut buffer = vec![0; 3];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"abc");
+    });
+}
+
+#[test]
+fn fd_file_type_reads_without_moving_offset() {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"hello fd").unwrap();
+    file.seek(SeekFrom::Start(2)).unwrap();
+
+    let json = format!(r#"{{ "x": "fd:{}" }}"#, file.as_raw_fd());
+
+    with_file_type!(&json, "/x", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 8);
+        let mut buffer = vec![0; 2];
+        file_type.ops().read(6, &mut buffer).unwrap();
+        assert_eq!(buffer, b"fd");
+    });
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 2);
+}
+
+#[test]
+fn fd_file_type_reads_up_to_the_end_of_the_file() {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"hello fd").unwrap();
+
+    let json = format!(r#"{{ "x": "fd:{}" }}"#, file.as_raw_fd());
+
+    with_file_type!(&json, "/x", |file_type| {
+        let mut buffer = vec![0; 4];
+        file_type.ops().read(6, &mut buffer).unwrap();
+        assert_eq!(buffer, b"fd\0\0");
+    });
+}
+
+#[test]
+fn fd_file_type_invalid_fd_is_ebadf() {
+    let file_type = FSFileType::Fd(json_fuse_fs::fd::FdFSFileType { fd: i32::max_value() });
+
+    let mut buffer = vec![0; 2];
+    let err = file_type.ops().read(0, &mut buffer).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+}
+
+#[test]
+fn local_file_type_windows_past_the_end_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let small = dir.path().join("small");
+    std::fs::write(&small, b"0123456789").unwrap();
+
+    let json = format!(r#"{{ "sparse": {{ "type": "file", "path": "{}", "windows": ["0..4", "8..12"] }} }}"#, small.display());
+    let error = FSNode::new(serde_json::from_str(&json).unwrap()).unwrap_err();
+    assert_eq!(error.path(), "/sparse");
+    assert_eq!(error.cause(), &DescriptorError::invalid(format!("the window 8..12 ends past the end of {} (10 bytes)", small.display())));
+
+    // A file shrinking after the check serves only what's left of the windows
+    let json = format!(r#"{{ "sparse": {{ "type": "file", "path": "{}", "windows": ["0..4", "6..10"] }} }}"#, small.display());
+    with_file_type!(&json, "/sparse", |file_type| {
+        std::fs::write(&small, b"01234567").unwrap();
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 6);
+        let mut buffer = vec![0; 6];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"012367");
+    });
+}
+
+#[test]
+fn local_file_type_windows_count_the_blocks_of_their_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let big = dir.path().join("big");
+    std::fs::write(&big, vec![1u8; 1024 * 1024]).unwrap();
+
+    let json = format!(r#"{{ "sparse": {{ "type": "file", "path": "{}", "windows": ["0..10", "100..110"] }} }}"#, big.display());
+
+    with_file_type!(&json, "/sparse", |file_type| {
+        let attr = file_type.ops().get_attributes(0).unwrap();
+        assert_eq!(attr.blocks, blocks(20, file_type.ops().block_size()));
+        assert!(attr.blocks < 1024 * 1024 / BLOCK_UNIT);
+    });
+}
+
+#[test]
+fn local_file_type_windows_are_stitched() {
+    let dir = tempfile::tempdir().unwrap();
+    let big = dir.path().join("big");
+    let content: Vec<u8> = (0..200u32).map(|i| (i % 256) as u8).collect();
+    std::fs::write(&big, &content).unwrap();
+
+    let json = format!(r#"{{ "sparse": {{ "type": "file", "path": "{}", "windows": ["0..10", "100..110"] }} }}"#, big.display());
+
+    with_file_type!(&json, "/sparse", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 20);
+
+        let mut buffer = vec![0; 6];
+        file_type.ops().read(7, &mut buffer).unwrap();
+        assert_eq!(buffer, vec![7, 8, 9, 100, 101, 102]);
+
+        let mut all = vec![0; 20];
+        file_type.ops().read(0, &mut all).unwrap();
+        assert_eq!(&all[..10], &content[0..10]);
+        assert_eq!(&all[10..], &content[100..110]);
+    });
+}
+
+#[test]
+fn local_file_type_reassembles_large_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+    let content: Vec<u8> = (0..3 * 1024 * 1024 + 123).map(|i| (i * 31 % 251) as u8).collect();
+    std::fs::write(&path, &content).unwrap();
+
+    let json = format!(r#"{{ "large.bin": "file:{}" }}"#, path.display());
+
+    with_file_type!(&json, "/large.bin", |file_type| {
+        let size = file_type.ops().size().unwrap() as usize;
+        assert_eq!(size, content.len());
+
+        let mut reassembled = Vec::with_capacity(size);
+        for offset in (0..size).step_by(4096) {
+            let mut buffer = vec![0; min(4096, size - offset)];
+            file_type.ops().read(offset as i64, &mut buffer).unwrap();
+            reassembled.extend_from_slice(&buffer);
+        }
+        assert!(reassembled == content);
+    });
+}
+
+#[test]
+fn fault_always_fails_reads() {
+    let json = r#"{ "file.txt": { "type": "raw", "data": "x", "fault": { "read": "EIO", "rate": 1.0 } } }"#;
+
+    with_file_type!(json, "/file.txt", |file_type| {
+        for _ in 0..3 {
+            let mut buffer = vec![0; 1];
+            let err = file_type.ops().read(0, &mut buffer).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EIO));
+        }
+    });
+}
+
+#[test]
+fn fault_with_zero_rate_serves_normally() {
+    let json = r#"{ "file.txt": { "type": "raw", "data": "x", "fault": { "read": "EIO", "rate": 0.0 } } }"#;
+
+    with_file_type!(json, "/file.txt", |file_type| {
+        for _ in 0..3 {
+            let mut buffer = vec![0; 1];
+            file_type.ops().read(0, &mut buffer).unwrap();
+            assert_eq!(buffer, b"x");
+        }
+    });
+}
+
+#[test]
+fn local_file_type_deleted_file_is_enoent() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    std::fs::write(&file, "abc").unwrap();
+
+    let json = format!(r#"{{ "file.txt": "file:{}" }}"#, file.display());
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 3);
+
+        std::fs::remove_file(&file).unwrap();
+
+        let mut buffer = vec![0; 3];
+        assert_eq!(file_type.ops().read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+        assert_eq!(file_type.ops().get_attributes(0).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    });
+}
+
+#[test]
+fn stdin_file_type_serves_captured_bytes() {
+    let file_type = FSFileType::Stdin(json_fuse_fs::stdin::StdinFSFileType::from_reader(&b"piped\ninput"[..]).unwrap());
+
+    assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 11);
+    let mut buffer = vec![0; 5];
+    file_type.ops().read(6, &mut buffer).unwrap();
+    assert_eq!(buffer, b"input");
+}
+
+#[test]
+fn size_matches_attributes_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let local = dir.path().join("local.txt");
+    std::fs::write(&local, "local content").unwrap();
+    let database = dir.path().join("app.db");
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    connection.execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('a'), ('b');").unwrap();
+    let fd_file = tempfile::tempfile().unwrap();
+    (&fd_file).write_all(b"fd content").unwrap();
+
+    let json = format!(r#"
+            {{
+                "raw.txt": "raw:abc",
+                "local.txt": "file:{local}",
+                "windows.txt": {{ "type": "file", "path": "{local}", "windows": ["0..2", "6..9"] }},
+                "query.csv": "sqlite:{database}#SELECT v FROM t",
+                "fd.txt": "fd:{fd}",
+                "cond.txt": {{ "type": "cond", "when": "env:JSON_FUSE_FS_SIZE_UNSET==on", "then": "raw:abc" }},
+                "fault.txt": {{ "type": "raw", "data": "abcd", "fault": {{ "read": "EIO", "rate": 1.0 }} }},
+                "timeout.txt": {{ "type": "raw", "data": "abcde", "read_timeout_ms": 1000 }},
+                "checksum.txt": {{ "type": "crc32", "of": "raw:abc" }}
+            }}"#, local = local.display(), database = database.display(), fd = fd_file.as_raw_fd());
+
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(&json).unwrap()).unwrap();
+    for node in fs_tree.flatten_strong() {
+        if let FSEntry::File(file_type) = &node.entry {
+            assert_eq!(file_type.ops().size().unwrap(), file_type.ops().get_attributes(0).unwrap().size, "{}", node.name.borrow());
+        }
+    }
+}
+
+#[test]
+fn http_identity_accept_encoding() {
+    let server = MockServer::start(|_| MockResponse::ok(b"hello identity"));
+
+    let json = format!(r#"{{ "file.txt": {{ "type": "http", "url": "{}/file.txt", "accept_encoding": "identity" }} }}"#, server.url);
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        let size = file_type.ops().get_attributes(0).unwrap().size;
+        let mut buffer = vec![0; size as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"hello identity");
+    });
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    for request in requests {
+        assert_eq!(request.header("Accept-Encoding"), Some("identity"));
+    }
+}
+
+#[test]
+fn http_prefix_with_full_url() {
+    let server = MockServer::start(|_| MockResponse::ok(b"hello http"));
+
+    let json = format!(r#"{{ "x": "http:{}/x" }}"#, server.url);
+
+    with_file_type!(&json, "/x", |file_type| {
+        let size = file_type.ops().get_attributes(0).unwrap().size;
+        let mut buffer = vec![0; size as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"hello http");
+    });
+
+    assert!(server.requests().iter().all(|r| r.path == "/x"));
+}
+
+#[test]
+fn rotate_file_type_concatenates_rotations() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("app.log"), "current\n").unwrap();
+    std::fs::write(dir.path().join("app.log.1"), "first rotation\n").unwrap();
+    std::fs::write(dir.path().join("app.log.2"), "second rotation\n").unwrap();
+    let pattern = format!("{}/app.log*", dir.path().display());
+
+    let json = format!(r#"{{ "app.log": "rotate:{}" }}"#, pattern);
+    with_file_type!(&json, "/app.log", |file_type| {
+        let mut buffer = vec![0; file_type.ops().size().unwrap() as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "second rotation\nfirst rotation\ncurrent\n");
+
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(12, &mut buffer).unwrap();
+        assert_eq!(buffer, b"ion\nfirst ");
+    });
+
+    let json = format!(r#"{{ "app.log": {{ "type": "rotate", "pattern": "{}", "order": "newest_first" }} }}"#, pattern);
+    with_file_type!(&json, "/app.log", |file_type| {
+        let mut buffer = vec![0; file_type.ops().size().unwrap() as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "current\nfirst rotation\nsecond rotation\n");
+    });
+}
+
+#[test]
+fn blobref_file_type_reads_blob_by_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    // sha256("hello blob")
+    let hash = "e997afd18e5f6be004fc193aed2c90291e68ab2c7599a62538c935b7fca6ab0f";
+    std::fs::create_dir_all(dir.path().join(&hash[..2])).unwrap();
+    std::fs::write(dir.path().join(&hash[..2]).join(&hash[2..]), "hello blob").unwrap();
+
+    let json = format!(r#"
+            {{
+                "blob.bin": {{ "type": "blobref", "store": "{store}", "sha256": "{hash}" }},
+                "missing.bin": {{ "type": "blobref", "store": "{store}", "sha256": "{missing}" }}
+            }}"#, store = dir.path().display(), hash = hash, missing = "0".repeat(64));
+
+    with_file_type!(&json, "/blob.bin", |file_type| {
+        assert_eq!(file_type.ops().size().unwrap(), 10);
+        let mut buffer = vec![0; 4];
+        file_type.ops().read(6, &mut buffer).unwrap();
+        assert_eq!(buffer, b"blob");
+    });
+    with_file_type!(&json, "/missing.bin", |file_type| {
+        let mut buffer = vec![0; 4];
+        assert_eq!(file_type.ops().read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    });
+}
+
+#[test]
+fn unixsock_file_type_serves_response() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("app.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            write!(stream, "echo: {}", request).unwrap();
+        }
+    });
+
+    let json = format!(r#"
+            {{
+                "events": "unixsock:{socket}",
+                "status": {{ "type": "unixsock", "path": "{socket}", "request": "STATUS", "timeout_ms": 1000 }},
+                "refused": "unixsock:{missing}"
+            }}"#, socket = socket.display(), missing = dir.path().join("missing.sock").display());
+
+    with_file_type!(&json, "/status", |file_type| {
+        let mut buffer = vec![0; file_type.ops().size().unwrap() as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"echo: STATUS\n");
+    });
+    with_file_type!(&json, "/events", |file_type| {
+        let mut buffer = vec![0; file_type.ops().size().unwrap() as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"echo: ");
+    });
+    with_file_type!(&json, "/refused", |file_type| {
+        let mut buffer = vec![0; 1];
+        assert!(file_type.ops().read(0, &mut buffer).is_err());
+    });
+}
+
+#[test]
+fn blocks_follow_file_type_block_size() {
+    let json = r#"
+            {
+                "small.txt": "raw:abc",
+                "generated.bin": { "type": "http", "url": "http://127.0.0.1:1/generated.bin", "size": 10000 }
+            }"#;
+
+    with_file_type!(json, "/small.txt", |file_type| {
+        assert_eq!(file_type.ops().block_size(), BLOCK_UNIT);
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().blocks, 1);
+    });
+    with_file_type!(json, "/generated.bin", |file_type| {
+        assert_eq!(file_type.ops().block_size(), DEFAULT_BLOCK_SIZE);
+        // 10000 bytes take 3 blocks of 4096 bytes, that is 24 units of 512 bytes
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().blocks, 24);
+    });
+
+    assert_eq!(blocks(0, 4096), 0);
+    assert_eq!(blocks(4096, 4096), 8);
+    assert_eq!(blocks(1, 100), 1);
+}
+
+#[test]
+fn http_short_partial_responses_fill_the_window() {
+    const BODY: &[u8] = b"hello partial content";
+    const CHUNK: usize = 4;
+
+    let server = MockServer::start(|request| {
+        let range = request.header("Range").unwrap().trim_start_matches("bytes=").to_string();
+        let mut bounds = range.split('-').map(|b| b.parse::<usize>().unwrap());
+        let (start, end) = (bounds.next().unwrap(), bounds.next().unwrap());
+        if start >= BODY.len() {
+            return MockResponse::ok(b"").with_status(416);
+        }
+        // Serve at most CHUNK bytes, whatever the requested range is
+        let last = *[end, start + CHUNK - 1, BODY.len() - 1].iter().min().unwrap();
+        MockResponse::ok(&BODY[start..=last])
+            .with_status(206)
+            .with_header("Content-Range", &format!("bytes {}-{}/{}", start, last, BODY.len()))
+    });
+
+    let json = format!(r#"{{ "file.txt": "{}/file.txt" }}"#, server.url);
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(3, &mut buffer).unwrap();
+        assert_eq!(buffer, &BODY[3..13]);
+        assert_eq!(server.requests().len(), 3);
+
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(18, &mut buffer).unwrap();
+        assert_eq!(&buffer[..3], &BODY[18..]);
+        assert_eq!(&buffer[3..], &[0; 7]);
+    });
+}
+
+#[test]
+fn http_ignored_range_serves_the_window() {
+    const BODY: &[u8] = b"the whole body, whatever the range";
+
+    // The server ignores the Range header and always replies 200 with the whole content
+    let server = MockServer::start(|_| MockResponse::ok(BODY));
+
+    let json = format!(r#"{{ "file.txt": "{}/file.txt" }}"#, server.url);
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        let mut reassembled = vec![];
+        for offset in (0..BODY.len()).step_by(5) {
+            let mut buffer = vec![0; 5];
+            file_type.ops().read(offset as i64, &mut buffer).unwrap();
+            reassembled.extend_from_slice(&buffer[..min(5, BODY.len() - offset)]);
+        }
+        assert_eq!(reassembled, BODY);
+
+        let mut buffer = vec![0; 5];
+        file_type.ops().read(BODY.len() as i64 + 10, &mut buffer).unwrap();
+        assert_eq!(buffer, [0; 5]);
+    });
+}
+
+#[test]
+fn http_mtime_is_last_modified() {
+    let server = MockServer::start(|_| MockResponse::ok(b"dated").with_header("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT"));
+    let undated = MockServer::start(|_| MockResponse::ok(b"undated"));
+
+    let json = format!(r#"{{ "dated.txt": "{}/dated.txt", "undated.txt": "{}/undated.txt" }}"#, server.url, undated.url);
+
+    with_file_type!(&json, "/dated.txt", |file_type| {
+        let mtime = file_type.ops().get_attributes(0).unwrap().mtime;
+        assert_eq!(mtime, UNIX_EPOCH + Duration::from_secs(1_445_412_480));
+    });
+    with_file_type!(&json, "/undated.txt", |file_type| {
+        let first = file_type.ops().get_attributes(0).unwrap().mtime;
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().mtime, first);
+    });
+}
+
+#[test]
+fn proc_file_type_serves_pid() {
+    let json = r#"{ "pid": { "type": "proc", "field": "pid" }, "uptime": { "type": "proc", "field": "uptime" } }"#;
+
+    with_file_type!(json, "/pid", |file_type| {
+        let size = file_type.ops().size().unwrap();
+        let mut buffer = vec![0; size as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), format!("{}\n", std::process::id()));
+    });
+    with_file_type!(json, "/uptime", |file_type| {
+        let mut buffer = vec![0; file_type.ops().size().unwrap() as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert!(String::from_utf8(buffer).unwrap().trim_end().parse::<f64>().unwrap() >= 0.0);
+    });
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "x": { "type": "proc", "field": "unknown" } }"#).unwrap()).is_err());
+}
+
+#[test]
+fn cmd_file_type_serves_command_stdout() {
+    let json = r#"{ "hello.txt": "cmd:echo hello", "failing.txt": "cmd:echo partial; exit 3" }"#;
+    let options = BuildOptions { allow_commands: true, ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).unwrap();
+
+    if let FSEntry::File(file_type) = &fs_tree.walk("/hello.txt".to_string()).unwrap().entry {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, 6);
+        assert_eq!(file_type.ops().size().unwrap(), 6);
+
+        let mut buffer = vec![0; 4];
+        file_type.ops().read(2, &mut buffer).unwrap();
+        assert_eq!(buffer, b"llo\n");
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+
+    if let FSEntry::File(file_type) = &fs_tree.walk("/failing.txt".to_string()).unwrap().entry {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap_err().raw_os_error(), Some(libc::EIO));
+        assert_eq!(file_type.ops().read(0, &mut [0; 8]).unwrap_err().raw_os_error(), Some(libc::EIO));
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+
+    // Commands don't run unless allowed
+    assert!(FSNode::new(serde_json::from_str(json).unwrap()).is_err());
+}
+
+#[test]
+fn env_file_type_serves_variable_value() {
+    env::set_var("JSON_FUSE_FS_ENV_VALUE", "some value");
+    env::remove_var("JSON_FUSE_FS_ENV_UNSET");
+    let json = r#"{ "x": "env:JSON_FUSE_FS_ENV_VALUE", "unset": "env:JSON_FUSE_FS_ENV_UNSET" }"#;
+
+    with_file_type!(json, "/x", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, 10);
+
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"some value");
+
+        // The value is looked up on each access
+        env::set_var("JSON_FUSE_FS_ENV_VALUE", "other");
+        assert_eq!(file_type.ops().size().unwrap(), 5);
+    });
+    with_file_type!(json, "/unset", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(2).unwrap().size, 0);
+    });
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "x": "env:" }"#).unwrap()).is_err());
+}
+
+#[test]
+fn gzip_file_type_decompresses_on_read() {
+    use flate2::{Compression, write::GzEncoder};
+
+    let original = "some text compressing well ".repeat(100);
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(original.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+    assert!(compressed.len() < original.len());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("text.gz");
+    std::fs::write(&path, &compressed).unwrap();
+    let json = format!(r#"
+            {{
+                "inline.txt": "gzip:{}",
+                "local.txt": {{ "type": "gzip", "path": "{}" }},
+                "corrupt.txt": "gzip:{}"
+            }}"#, json_fuse_fs::base64::encode(&compressed), path.display(), json_fuse_fs::base64::encode(b"not gzip"));
+
+    for file in &["/inline.txt", "/local.txt"] {
+        with_file_type!(&json, file, |file_type| {
+            assert_eq!(file_type.ops().get_attributes(2).unwrap().size, original.len() as u64);
+
+            let mut buffer = vec![0; original.len()];
+            file_type.ops().read(0, &mut buffer).unwrap();
+            assert_eq!(buffer, original.as_bytes());
+
+            let mut buffer = vec![0; 9];
+            file_type.ops().read(5, &mut buffer).unwrap();
+            assert_eq!(buffer, b"text comp");
+        });
+    }
+    with_file_type!(&json, "/corrupt.txt", |file_type| {
+        assert_eq!(file_type.ops().size().unwrap_err().raw_os_error(), Some(libc::EIO));
+    });
+}
+
+#[test]
+fn http_limiter_bounds_concurrent_requests() {
+    use std::sync::{Arc, Mutex};
+    use json_fuse_fs::http::{HttpFSFileType, HttpLimiter};
+
+    const MAX_REQUESTS: usize = 2;
+
+    // Requests running, and the most seen at once
+    let running = Arc::new(Mutex::new((0, 0)));
+    let server_running = running.clone();
+    let server = MockServer::start(move |_| {
+        {
+            let mut running = server_running.lock().unwrap();
+            running.0 += 1;
+            running.1 = std::cmp::max(running.0, running.1);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        server_running.lock().unwrap().0 -= 1;
+        MockResponse::ok(b"limited")
+    });
+
+    let limiter = Arc::new(HttpLimiter::new(MAX_REQUESTS));
+    let fetches: Vec<_> = (0..6)
+        .map(|i| {
+            let file_type = HttpFSFileType::new(format!("{}/{}.txt", server.url, i)).with_limiter(Some(limiter.clone()));
+            std::thread::spawn(move || file_type.size().unwrap())
+        })
+        .collect();
+    for fetch in fetches {
+        assert_eq!(fetch.join().unwrap(), 7);
+    }
+
+    assert_eq!(server.requests().len(), 6);
+    assert!(running.lock().unwrap().1 <= MAX_REQUESTS);
+}
+
+#[test]
+fn seq_file_type_maps_offsets_to_numbers() {
+    let json = r#"{ "seq.txt": { "type": "seq", "from": 8, "to": 102, "sep": ", " } }"#;
+    let expected: String = (8..=102).map(|n| format!("{}, ", n)).collect();
+
+    with_file_type!(json, "/seq.txt", |file_type| {
+        assert_eq!(file_type.ops().size().unwrap(), expected.len() as u64);
+
+        for &(offset, len) in &[(0, 5), (3, 4), (7, 20), (255, 30), (expected.len() - 3, 3), (0, expected.len())] {
+            let mut buffer = vec![0; len];
+            file_type.ops().read(offset as i64, &mut buffer).unwrap();
+            assert_eq!(buffer, &expected.as_bytes()[offset..offset + len], "offset {}", offset);
+        }
+    });
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "x": { "type": "seq", "from": 2, "to": 1 } }"#).unwrap()).is_err());
+}
+
+#[test]
+fn read_delay_postpones_reads() {
+    let json = r#"{ "slow.txt": { "type": "raw", "data": "abc", "read_delay_ms": 200 } }"#;
+
+    with_file_type!(json, "/slow.txt", |file_type| {
+        let start = Instant::now();
+        let mut buffer = vec![0; 3];
+        file_type.ops().read(0, &mut buffer).unwrap();
+
+        assert_eq!(buffer, b"abc");
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    });
+}
+
+#[test]
+fn read_delay_is_cut_short_by_shutdown() {
+    let options = BuildOptions::default();
+    let json = r#"{ "slow.txt": { "type": "raw", "data": "abc", "read_delay_ms": 60000 } }"#;
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).unwrap();
+    options.shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+
+    if let FSEntry::File(file_type) = &fs_tree.walk("/slow.txt".to_string()).unwrap().entry {
+        let start = Instant::now();
+        let mut buffer = vec![0; 3];
+        file_type.ops().read(0, &mut buffer).unwrap();
+
+        assert_eq!(buffer, b"abc");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+}
+
+#[test]
+fn filter_pipes_content_through_command() {
+    let json = r#"{ "upper.txt": { "type": "raw", "data": "hello, filter!", "filter": "tr a-z A-Z" } }"#;
+
+    with_file_type!(json, "/upper.txt", |file_type| {
+        let size = file_type.ops().get_attributes(0).unwrap().size;
+        let mut buffer = vec![0; size as usize];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"HELLO, FILTER!");
+
+        let mut buffer = vec![0; 6];
+        file_type.ops().read(7, &mut buffer).unwrap();
+        assert_eq!(buffer, b"FILTER");
+    });
+
+    let json = r#"{ "failing.txt": { "type": "raw", "data": "abc", "filter": "exit 3" } }"#;
+
+    with_file_type!(json, "/failing.txt", |file_type| {
+        let mut buffer = vec![0; 3];
+        assert_eq!(file_type.ops().read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::EIO));
+    });
+}
+
+#[test]
+fn read_through_trait_object() {
+    let dir = tempfile::tempdir().unwrap();
+    let local = dir.path().join("local.txt");
+    std::fs::write(&local, "local content").unwrap();
+    let server = MockServer::start(|_| MockResponse::ok(b"http content"));
+
+    let json = format!(
+        r#"{{ "raw.txt": "raw:raw content", "local.txt": "file:{}", "http.txt": "{}/http.txt" }}"#,
+        local.display(),
+        server.url
+    );
+
+    for &(path, expected) in &[("/raw.txt", "raw content"), ("/local.txt", "local content"), ("/http.txt", "http content")] {
+        with_file_type!(&json, path, |file_type| {
+            let ops: &dyn FSFileTypeOps = file_type.ops();
+            let size = ops.get_attributes(0).unwrap().size;
+            let mut buffer = vec![0; size as usize];
+            ops.read(0, &mut buffer).unwrap();
+            assert_eq!(buffer, expected.as_bytes(), "{}", path);
+        });
+    }
+}
+
+#[test]
+fn raw_read_past_end_serves_no_bytes() {
+    let raw = json_fuse_fs::raw::RawFSFileType::new("abcdef".to_string());
+
+    let mut buffer = vec![0; 4];
+    raw.read(100, &mut buffer).unwrap();
+    assert_eq!(buffer, [0; 4]);
+
+    raw.read(6, &mut buffer).unwrap();
+    assert_eq!(buffer, [0; 4]);
+
+    raw.read(2, &mut buffer).unwrap();
+    assert_eq!(buffer, b"cdef");
+
+    let mut buffer = vec![0; 4];
+    raw.read(4, &mut buffer).unwrap();
+    assert_eq!(buffer, b"ef\0\0");
+}
+
+#[test]
+fn window_serves_only_while_available() {
+    // 2024-01-01T00:00:00Z
+    const NEW_YEAR: u64 = 1_704_067_200;
+    let available: serde_json::Value = serde_json::from_str(r#"{ "after": "2024-01-01T00:00:00Z", "before": "2025-01-01T00:00:00Z" }"#).unwrap();
+    let window = |clock: fn() -> SystemTime| json_fuse_fs::window::WindowFSFileType::parse(
+        FSFileType::Raw(json_fuse_fs::raw::RawFSFileType::new("scheduled".to_string())),
+        &available
+    ).unwrap().with_clock(clock);
+
+    let inside = window(|| UNIX_EPOCH + Duration::from_secs(NEW_YEAR));
+    assert_eq!(inside.get_attributes(1).unwrap().size, 9);
+    let mut buffer = vec![0; 9];
+    inside.read(0, &mut buffer).unwrap();
+    assert_eq!(buffer, b"scheduled");
+
+    for outside in &[window(|| UNIX_EPOCH + Duration::from_secs(NEW_YEAR - 1)), window(|| UNIX_EPOCH + Duration::from_secs(NEW_YEAR + 366 * 86400))] {
+        assert_eq!(outside.get_attributes(1).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+        assert_eq!(outside.read(0, &mut buffer).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    }
+}
+
+#[test]
+fn window_from_descriptor() {
+    let json = r#"
+            {
+                "past.txt": { "type": "raw", "data": "x", "available": { "after": "2000-01-01T00:00:00Z", "before": "2001-01-01T00:00:00Z" } },
+                "open.txt": { "type": "raw", "data": "x", "available": { "after": "2000-01-01T00:00:00Z" } }
+            }"#;
+
+    with_file_type!(json, "/past.txt", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(1).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    });
+    with_file_type!(json, "/open.txt", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(1).unwrap().size, 1);
+    });
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "x": { "type": "raw", "data": "x", "available": { "after": "2024-01-01" } } }"#).unwrap()).is_err());
+}
+
+#[test]
+fn base64_file_type_serves_decoded_bytes() {
+    // PNG signature followed by the start of an IHDR chunk
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, b'I', b'H', b'D', b'R', 0xff];
+    let json = r#"{ "image.png": "base64:iVBORw0KGgoAAAANSUhEUv8=" }"#;
+
+    with_file_type!(json, "/image.png", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, PNG.len() as u64);
+
+        let mut buffer = vec![0; PNG.len()];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, PNG);
+
+        let mut buffer = vec![0; 4];
+        file_type.ops().read(14, &mut buffer).unwrap();
+        assert_eq!(buffer, [b'D', b'R', 0xff, 0]);
+    });
+
+    for invalid in &["base64:iVBORw0KGgo", "base64:iVBO*w==", "base64:i==="] {
+        let json = format!(r#"{{ "image.png": "{}" }}"#, invalid);
+        assert!(FSNode::new(serde_json::from_str(&json).unwrap()).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn random_file_type_is_reproducible() {
+    let json = r#"{ "random.bin": { "type": "random", "size": 1048576, "seed": 42 }, "other.bin": { "type": "random", "size": 1048576, "seed": 43 } }"#;
+
+    let read = |path: &str, offset: usize, len: usize| {
+        let mut buffer = vec![0; len];
+        with_file_type!(json, path, |file_type| file_type.ops().read(offset as i64, &mut buffer).unwrap());
+        buffer
+    };
+
+    let whole = read("/random.bin", 1000, 100);
+    assert_eq!(&read("/random.bin", 1013, 50)[..], &whole[13..63]);
+    assert_eq!(&read("/random.bin", 1050, 100)[..50], &whole[50..]);
+    assert_eq!(read("/random.bin", 1000, 100), whole);
+    assert_ne!(read("/other.bin", 1000, 100), whole);
+    assert!(whole.iter().any(|b| *b != whole[0]));
+
+    with_file_type!(json, "/random.bin", |file_type| {
+        assert_eq!(file_type.ops().get_attributes(0).unwrap().size, 1048576);
+
+        let mut buffer = vec![0; 8];
+        file_type.ops().read(1048576 - 4, &mut buffer).unwrap();
+        assert_eq!(&buffer[4..], &[0; 4]);
+    });
+}
+
+#[test]
+fn pipeline_applies_steps_in_order() {
+    let json = r#"
+            {
+                "utf16.txt": { "type": "pipeline", "source": "base64://5HAHIA/ADfAGUALAAgAHAAaQBwAGUAbABpAG4AZQA=", "steps": ["utf16-to-utf8", "upper"] },
+                "gzip.txt": { "type": "pipeline", "source": "base64:H4sIAAAAAAACA8tIzcnJV0ivyixQKMgsSM3JzEvlAgDnTD6MFAAAAA==", "steps": ["gunzip", "upper"] }
+            }"#;
+
+    for &(path, expected) in &[("/utf16.txt", "GRÜSSE, PIPELINE"), ("/gzip.txt", "HELLO GZIP PIPELINE\n")] {
+        with_file_type!(json, path, |file_type| {
+            let size = file_type.ops().get_attributes(0).unwrap().size;
+            assert_eq!(size, expected.len() as u64, "{}", path);
+            let mut buffer = vec![0; size as usize];
+            file_type.ops().read(0, &mut buffer).unwrap();
+            assert_eq!(buffer, expected.as_bytes(), "{}", path);
+        });
+    }
+
+    let unknown = r#"{ "x": { "type": "pipeline", "source": "raw:abc", "steps": ["upper", "rot13"] } }"#;
+    assert!(FSNode::new(serde_json::from_str(unknown).unwrap()).is_err());
+}
+
+#[test]
+fn mode_overrides_permission_bits() {
+    let json = r#"
+            {
+                "script.sh": { "type": "raw", "data": "echo hi", "mode": "0755" },
+                "sized.sh": { "type": "raw", "data": "echo hi", "size": 7, "mode": "700" },
+                "default.txt": { "type": "raw", "data": "abc" }
+            }"#;
+
+    for &(path, perm) in &[("/script.sh", 0o755), ("/sized.sh", 0o700), ("/default.txt", 0o644)] {
+        with_file_type!(json, path, |file_type| {
+            assert_eq!(file_type.ops().get_attributes(0).unwrap().perm, perm, "{}", path);
+        });
+    }
+
+    for invalid in &[r#""0789""#, r#""rwxr-xr-x""#, "755"] {
+        let json = format!(r#"{{ "x": {{ "type": "raw", "data": "abc", "mode": {} }} }}"#, invalid);
+        assert!(FSNode::new(serde_json::from_str(&json).unwrap()).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn http_cache_serves_a_cat_with_a_single_get() {
+    let body: Vec<u8> = (0..20000).map(|i| (i % 251) as u8).collect();
+    let server_body = body.clone();
+    let server = MockServer::start(move |_| MockResponse::ok(&server_body));
+
+    let json = format!(r#"{{ "file.bin": {{ "type": "http", "url": "{}/file.bin", "cache_ttl_ms": 60000 }} }}"#, server.url);
+
+    with_file_type!(&json, "/file.bin", |file_type| {
+        // What a `cat` does: a getattr, then reads of 4096 bytes up to the end of the content
+        let size = file_type.ops().get_attributes(2).unwrap().size as usize;
+        assert_eq!(size, body.len());
+        let mut content = vec![];
+        for offset in (0..size).step_by(4096) {
+            let mut buffer = vec![0; 4096];
+            file_type.ops().read(offset as i64, &mut buffer).unwrap();
+            assert_eq!(file_type.ops().get_attributes(2).unwrap().size as usize, size);
+            content.extend_from_slice(&buffer[..min(4096, size - offset)]);
+        }
+        assert_eq!(content, body);
+    });
+
+    let gets = server.requests().iter().filter(|r| r.method == "GET").count();
+    assert_eq!(gets, 1);
+}
+
+#[test]
+fn http_cache_expires_after_the_ttl() {
+    let server = MockServer::start(|_| MockResponse::ok(b"expiring"));
+
+    let json = format!(r#"{{ "file.txt": {{ "type": "http", "url": "{}/file.txt", "cache_ttl_ms": 20 }} }}"#, server.url);
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        let mut buffer = vec![0; 8];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        file_type.ops().read(0, &mut buffer).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"expiring");
+    });
+
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn quota_limits_the_bytes_served() {
+    let json = r#"{ "metered.txt": { "type": "raw", "data": "0123456789", "quota": 25 } }"#;
+
+    with_file_type!(json, "/metered.txt", |file_type| {
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(0, &mut buffer).unwrap();
+        file_type.ops().read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, b"0123456789");
+
+        // 20 bytes served, another 10 would go past the quota
+        let err = file_type.ops().read(0, &mut buffer).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EDQUOT));
+
+        let mut buffer = vec![0; 5];
+        file_type.ops().read(5, &mut buffer).unwrap();
+        assert_eq!(buffer, b"56789");
+        assert_eq!(file_type.ops().read(0, &mut [0; 1]).unwrap_err().raw_os_error(), Some(libc::EDQUOT));
+    });
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "x": { "type": "raw", "data": "a", "quota": "10" } }"#).unwrap()).is_err());
+}
+
+#[test]
+fn http_cache_reads_ranges_when_accepted() {
+    let body: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    let server_body = body.clone();
+    let server = MockServer::start(move |request| {
+        let range = match request.header("Range") {
+            Some(range) => range.trim_start_matches("bytes=").to_string(),
+            None => return MockResponse::ok(&server_body).with_header("Accept-Ranges", "bytes")
+        };
+        let mut bounds = range.split('-').map(|b| b.parse::<usize>().unwrap());
+        let (start, end) = (bounds.next().unwrap(), bounds.next().unwrap());
+        MockResponse::ok(&server_body[start..=end])
+            .with_status(206)
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("Content-Range", &format!("bytes {}-{}/{}", start, end, server_body.len()))
+    });
+
+    let json = format!(r#"{{ "file.bin": {{ "type": "http", "url": "{}/file.bin", "cache_ttl_ms": 60000 }} }}"#, server.url);
+
+    with_file_type!(&json, "/file.bin", |file_type| {
+        assert_eq!(file_type.ops().size().unwrap(), body.len() as u64);
+        let mut buffer = vec![0; 10];
+        file_type.ops().read(50_000, &mut buffer).unwrap();
+        assert_eq!(buffer, &body[50_000..50_010]);
+    });
+
+    let gets: Vec<_> = server.requests().into_iter().filter(|r| r.method == "GET").collect();
+    assert_eq!(gets.len(), 1);
+    assert_eq!(gets[0].header("Range"), Some("bytes=50000-50009"));
+}
+
+#[test]
+fn http_cache_downloads_the_whole_body_without_accept_ranges() {
+    let server = MockServer::start(|_| MockResponse::ok(b"no ranges here"));
+
+    let json = format!(r#"{{ "file.txt": {{ "type": "http", "url": "{}/file.txt", "cache_ttl_ms": 60000 }} }}"#, server.url);
+
+    with_file_type!(&json, "/file.txt", |file_type| {
+        let mut buffer = vec![0; 6];
+        file_type.ops().read(3, &mut buffer).unwrap();
+        assert_eq!(buffer, b"ranges");
+    });
+
+    let gets: Vec<_> = server.requests().into_iter().filter(|r| r.method == "GET").collect();
+    assert_eq!(gets.len(), 1);
+    assert_eq!(gets[0].header("Range"), None);
+}
@@ -4,13 +4,13 @@ use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 use json_fuse_fs::*;
 use json_fuse_fs::raw::RawFSFileType;
-use std::borrow::Borrow;
 
 macro_rules! assert_file_name {
     ($entry:expr, $name:expr) => ({
         let e = $entry;
+        let e: &FSNode = &*e;
         if let FSNode { name, entry: FSEntry::File(_), .. } = e {
-            assert_eq!(name, ($name))
+            assert_eq!(*name.borrow(), ($name))
         } else {
             panic!("FSNode.entry is not a FSEntry::Dir")
         }
@@ -20,6 +20,7 @@ macro_rules! assert_file_name {
 macro_rules! assert_file_local_file_path {
     ($entry:expr, $file_name:expr) => ({
         let (e, f) = ($entry, $file_name);
+        let e: &FSNode = &*e;
         if let FSNode { entry: FSEntry::File(FSFileType::Local(loc)),  .. } = e {
             assert_eq!(loc.file_path, f);
         } else {
@@ -31,8 +32,9 @@ macro_rules! assert_file_local_file_path {
 macro_rules! assert_file_raw_data {
     ($entry:expr, $data:expr) => ({
         let (e, f) = ($entry, $data);
+        let e: &FSNode = &*e;
         if let FSNode { entry: FSEntry::File(FSFileType::Raw(raw)), .. } = e {
-            assert_eq!(raw.data, f);
+            assert_eq!(*raw.data.borrow(), f.as_bytes());
         } else {
             panic!("FSNode.entry is not a FSEntry::File(FSFileType::Raw(_))")
         }
@@ -42,8 +44,9 @@ macro_rules! assert_file_raw_data {
 macro_rules! assert_dir_name {
     ($entry:expr, $name:expr) => ({
         let e = $entry;
+        let e: &FSNode = &*e;
         if let FSNode { name, entry: FSEntry::Dir(_), .. } = e {
-            assert_eq!(name, ($name))
+            assert_eq!(*name.borrow(), ($name))
         } else {
             panic!("FSNode.entry is not a FSEntry::Dir(_)")
         }
@@ -53,27 +56,27 @@ macro_rules! assert_dir_name {
 fn nested_structure() -> Rc<FSNode> {
     Rc::new(FSNode {
         inode: 1,
-        name: String::new(),
+        name: RefCell::new(String::new()),
         parent: RefCell::new(Weak::new()),
-        entry: FSEntry::Dir(
+        entry: FSEntry::Dir(RefCell::new(
             vec![
                 Rc::new(FSNode {
                     inode: 2,
-                    name: String::from("bla"),
+                    name: RefCell::new(String::from("bla")),
                     parent: RefCell::new(Weak::new()),
-                    entry: FSEntry::Dir(
+                    entry: FSEntry::Dir(RefCell::new(
                         vec![
                             Rc::new(FSNode {
                                 inode: 3,
-                                name: "file.txt".to_string(),
+                                name: RefCell::new("file.txt".to_string()),
                                 parent: RefCell::new(Weak::new()),
                                 entry: FSEntry::File(FSFileType::Raw(RawFSFileType::new("abc".to_string())))
                             })
                         ]
-                    )
+                    ))
                 })
             ]
-        )
+        ))
     })
 }
 
@@ -89,18 +92,18 @@ fn walk_to_root() {
 fn walk_to_file() {
     let structure = Rc::new(FSNode {
         inode: 1,
-        name: String::new(),
+        name: RefCell::new(String::new()),
         parent: RefCell::new(Weak::new()),
-        entry: FSEntry::Dir(
+        entry: FSEntry::Dir(RefCell::new(
             vec![
                 Rc::new(FSNode {
                     inode: 2,
-                    name: "file.txt".to_string(),
+                    name: RefCell::new("file.txt".to_string()),
                     parent: RefCell::new(Weak::new()),
                     entry: FSEntry::File(FSFileType::Raw(RawFSFileType::new("abc".to_string())))
                 })
             ]
-        )
+        ))
     });
 
     let found = structure.walk("/file.txt".to_string()).unwrap();
@@ -112,24 +115,24 @@ fn walk_to_file() {
 fn walk_to_dir() {
     let structure = Rc::new(FSNode {
         inode: 1,
-        name: String::new(),
+        name: RefCell::new(String::new()),
         parent: RefCell::new(Weak::new()),
-        entry: FSEntry::Dir(
+        entry: FSEntry::Dir(RefCell::new(
             vec![
                 Rc::new(FSNode {
                     inode: 2,
-                    name: "file.txt".to_string(),
+                    name: RefCell::new("file.txt".to_string()),
                     parent: RefCell::new(Weak::new()),
                     entry: FSEntry::File(FSFileType::Raw(RawFSFileType::new("abc".to_string())))
                 }),
                 Rc::new(FSNode {
                     inode: 3,
-                    name: String::from("bla"),
+                    name: RefCell::new(String::from("bla")),
                     parent: RefCell::new(Weak::new()),
-                    entry: FSEntry::Dir(vec![])
+                    entry: FSEntry::Dir(RefCell::new(vec![]))
                 })
             ]
-        )
+        ))
     });
 
     let found = structure.walk("/bla".to_string()).unwrap();
@@ -155,14 +158,14 @@ fn flatten() {
     assert_eq!(found.len(), 3);
 
     let root = found[0].upgrade().unwrap();
-    assert_dir_name!(root.borrow(), "");
+    assert_dir_name!(root, "");
 
     let bla = found[1].upgrade().unwrap();
-    assert_dir_name!(bla.borrow(), "bla");
+    assert_dir_name!(bla, "bla");
 
     let file = found[2].upgrade().unwrap();
 
-    assert_file_name!(file.borrow(), "file.txt");
+    assert_file_name!(file, "file.txt");
 }
 
 #[test]
@@ -182,10 +185,10 @@ fn load_raw_file_type() {
     assert_file_raw_data!(fs_tree.walk("/file.txt".to_string()).unwrap(), "abc");
 
     let root_from_inode = inode_map.get(&1).unwrap().upgrade().unwrap();
-    assert_dir_name!(root_from_inode.borrow(), "");
+    assert_dir_name!(root_from_inode, "");
 
     let file_from_inode = inode_map.get(&2).unwrap().upgrade().unwrap();
-    assert_file_name!(file_from_inode.borrow(), "file.txt");
+    assert_file_name!(file_from_inode, "file.txt");
 }
 
 #[test]
@@ -223,6 +226,483 @@ fn load_nested() {
     assert_file_name!(fs_tree.walk("/nested/nested.txt".to_string()).unwrap(), "nested.txt");
 
     let nested = fs_tree.walk("/nested".to_string()).unwrap();
-    assert_dir_name!(nested, "nested");
+    assert_dir_name!(&nested, "nested");
     assert_eq!(1, nested.parent.borrow().upgrade().unwrap().inode);
 }
+
+#[test]
+fn flatten_strong() {
+    let fs_tree = nested_structure();
+
+    let weak: Vec<Weak<FSNode>> = fs_tree.flatten();
+    let strong: Vec<Rc<FSNode>> = fs_tree.flatten_strong();
+
+    assert_eq!(strong.len(), weak.len());
+    for (s, w) in strong.iter().zip(weak.iter()) {
+        assert!(Rc::ptr_eq(s, &w.upgrade().unwrap()));
+    }
+
+    assert_dir_name!(&strong[0], "");
+    assert_dir_name!(&strong[1], "bla");
+    assert_file_name!(&strong[2], "file.txt");
+}
+
+#[test]
+fn count_matches_flatten() {
+    let dir = mirror_fixture();
+    let mirror = format!(r#"{{ "mirror": "mirror:{}", "file.txt": "raw:abc" }}"#, dir.path().display());
+    let fixtures = vec![
+        nested_structure(),
+        FSNode::new(serde_json::from_str(r#"{}"#).unwrap()).unwrap().0,
+        FSNode::new(serde_json::from_str(r#"{ "a": { "b": { "c": "raw:c" }, "d": {} }, "e": "raw:e" }"#).unwrap()).unwrap().0,
+        FSNode::new_with_options(serde_json::from_str(&mirror).unwrap(), &BuildOptions { skip_unreadable: true, ..BuildOptions::default() }).unwrap().0
+    ];
+
+    for fs_tree in fixtures {
+        assert_eq!(fs_tree.count(), fs_tree.flatten().len());
+    }
+}
+
+#[test]
+fn empty_names_are_rejected() {
+    assert!(FSNode::new(serde_json::from_str(r#"{ "": "raw:abc" }"#).unwrap()).is_err());
+    assert!(FSNode::new(serde_json::from_str(r#"{ "nested": { "": {} } }"#).unwrap()).is_err());
+}
+
+#[test]
+fn full_path() {
+    let json = r#"
+            {
+                "nested": {
+                    "nested.txt": "raw:cba"
+                }
+            }"#;
+
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    assert_eq!(fs_tree.full_path(), "/");
+    assert_eq!(fs_tree.walk("/nested".to_string()).unwrap().full_path(), "/nested");
+    assert_eq!(fs_tree.walk("/nested/nested.txt".to_string()).unwrap().full_path(), "/nested/nested.txt");
+}
+
+fn mirror_fixture() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "abc").unwrap();
+    std::fs::create_dir(dir.path().join("nested")).unwrap();
+    std::fs::write(dir.path().join("nested").join("b.txt"), "cba").unwrap();
+    // A dangling symlink can't be opened, not even by root
+    std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("unreadable.txt")).unwrap();
+    dir
+}
+
+#[test]
+fn load_mirror_skipping_unreadable() {
+    let dir = mirror_fixture();
+    let json = format!(r#"{{ "mirror": "mirror:{}" }}"#, dir.path().display());
+
+    let options = BuildOptions { skip_unreadable: true, ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(&json).unwrap(), &options).unwrap();
+
+    let a_path = dir.path().join("a.txt");
+    let b_path = dir.path().join("nested").join("b.txt");
+
+    assert_dir_name!(fs_tree.walk("/mirror".to_string()).unwrap(), "mirror");
+    assert_file_local_file_path!(fs_tree.walk("/mirror/a.txt".to_string()).unwrap(), a_path.to_str().unwrap());
+    assert_file_local_file_path!(fs_tree.walk("/mirror/nested/b.txt".to_string()).unwrap(), b_path.to_str().unwrap());
+    assert!(fs_tree.walk("/mirror/unreadable.txt".to_string()).is_none());
+}
+
+#[test]
+fn load_mirror_failing_on_unreadable() {
+    let dir = mirror_fixture();
+    let json = format!(r#"{{ "mirror": "mirror:{}" }}"#, dir.path().display());
+
+    assert!(FSNode::new(serde_json::from_str(&json).unwrap()).is_err());
+}
+
+#[test]
+fn load_glob() {
+    let dir = mirror_fixture();
+    let json = format!(r#"{{ "texts": "glob:{}/*.txt" }}"#, dir.path().display());
+
+    let options = BuildOptions { skip_unreadable: true, ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(&json).unwrap(), &options).unwrap();
+
+    let a_path = dir.path().join("a.txt");
+
+    assert_file_local_file_path!(fs_tree.walk("/texts/a.txt".to_string()).unwrap(), a_path.to_str().unwrap());
+    assert!(fs_tree.walk("/texts/unreadable.txt".to_string()).is_none());
+}
+
+#[test]
+fn default_type_applies_to_bare_strings() {
+    let json = r#"
+            {
+                "$default_type": "raw",
+                "bare.txt": "hello world",
+                "colon.txt": "key: value",
+                "explicit.txt": "file:/my_file.txt",
+                "nested": {
+                    "nested.txt": "cba"
+                }
+            }"#;
+
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    assert!(fs_tree.walk("/$default_type".to_string()).is_none());
+    assert_file_raw_data!(fs_tree.walk("/bare.txt".to_string()).unwrap(), "hello world");
+    assert_file_raw_data!(fs_tree.walk("/colon.txt".to_string()).unwrap(), "key: value");
+    assert_file_local_file_path!(fs_tree.walk("/explicit.txt".to_string()).unwrap(), "/my_file.txt");
+    assert_file_raw_data!(fs_tree.walk("/nested/nested.txt".to_string()).unwrap(), "cba");
+}
+
+#[test]
+fn objects_typed_with_other_than_a_file_type_are_directories() {
+    let json = r#"{ "$default_type": "raw", "notes": { "type": "draft", "body": "x" } }"#;
+
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    assert_dir_name!(fs_tree.walk("/notes".to_string()).unwrap(), "notes");
+    assert_file_raw_data!(fs_tree.walk("/notes/type".to_string()).unwrap(), "draft");
+    assert_file_raw_data!(fs_tree.walk("/notes/body".to_string()).unwrap(), "x");
+}
+
+#[test]
+fn lint_warns_on_invalid_json_raw_file() {
+    let json = r#"
+            {
+                "valid.json": "raw:{\"a\": 1}",
+                "invalid.json": "raw:not json",
+                "notes.txt": "raw:not json",
+                "nested": {
+                    "data.JSON": { "type": "raw", "data": "[1, 2" }
+                }
+            }"#;
+
+    let warnings = json_fuse_fs::lint::lint_descriptor(&serde_json::from_str(json).unwrap());
+
+    let paths: Vec<&str> = warnings.iter().map(|w| w.path.as_str()).collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths.contains(&"/invalid.json"));
+    assert!(paths.contains(&"/nested/data.JSON"));
+}
+
+#[test]
+fn from_manifest_uses_declared_sizes() {
+    let manifest = serde_json::json!([
+        { "path": "docs/readme.txt", "size": 42, "url": "http://127.0.0.1:1/readme.txt" },
+        { "path": "docs/nested/data.bin", "size": 1024, "url": "http://127.0.0.1:1/data.bin" },
+        { "path": "local.txt", "file": "/my_file.txt" }
+    ]);
+
+    let (fs_tree, _) = FSNode::from_manifest(&manifest).unwrap();
+
+    assert_dir_name!(fs_tree.walk("/docs/nested".to_string()).unwrap(), "nested");
+    assert_file_local_file_path!(fs_tree.walk("/local.txt".to_string()).unwrap(), "/my_file.txt");
+    // Nothing listens on port 1, so these would fail if the size was fetched
+    for (path, size) in vec![("/docs/readme.txt", 42), ("/docs/nested/data.bin", 1024)] {
+        if let FSNode { entry: FSEntry::File(file_type), .. } = &*fs_tree.walk(path.to_string()).unwrap() {
+            assert_eq!(file_type.ops().get_attributes(0).unwrap().size, size);
+        } else {
+            panic!("FSNode.entry is not a FSEntry::File(_)")
+        }
+    }
+}
+
+#[test]
+fn from_manifest_rejects_conflicting_paths() {
+    let manifest = serde_json::json!([
+        { "path": "a", "size": 1, "url": "http://127.0.0.1:1/a" },
+        { "path": "a/b", "size": 1, "url": "http://127.0.0.1:1/b" }
+    ]);
+
+    assert!(FSNode::from_manifest(&manifest).is_err());
+}
+
+#[test]
+fn normalize_names_lowercases_entries() {
+    let json = r#"
+            {
+                "README.md": "raw:abc",
+                "Nested": {
+                    "Cafe\u0301.TXT": "raw:cba"
+                }
+            }"#;
+    let options = BuildOptions { normalize_names: true, ..BuildOptions::default() };
+
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).unwrap();
+
+    assert_file_raw_data!(fs_tree.walk("/readme.md".to_string()).unwrap(), "abc");
+    assert_file_raw_data!(fs_tree.walk("/nested/caf\u{e9}.txt".to_string()).unwrap(), "cba");
+    assert!(fs_tree.walk("/README.md".to_string()).is_none());
+}
+
+#[test]
+fn normalize_names_rejects_collisions() {
+    let json = r#"{ "File.txt": "raw:abc", "file.TXT": "raw:cba" }"#;
+    let options = BuildOptions { normalize_names: true, ..BuildOptions::default() };
+
+    assert!(FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).is_err());
+}
+
+#[test]
+fn names_differing_in_case_collide() {
+    let error = FSNode::new(serde_json::from_str(r#"{ "dir": { "File": "raw:a", "file": "raw:b" } }"#).unwrap()).unwrap_err();
+    assert_eq!(error.path(), "/dir");
+    assert_eq!(error.cause(), &DescriptorError::invalid("the name file collides with File ignoring case"));
+
+    assert!(FSNode::new(serde_json::from_str(r#"{ "File": "raw:a", "dir": { "file": "raw:b" } }"#).unwrap()).is_ok());
+}
+
+#[test]
+fn empty_expansions_under_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = format!(r#"
+            {{
+                "texts": "glob:{dir}/*.txt",
+                "mirror": "mirror:{dir}/missing"
+            }}"#, dir = dir.path().display());
+
+    assert!(FSNode::new(serde_json::from_str(&json).unwrap()).is_err());
+
+    let options = BuildOptions { allow_empty_expansion: true, ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(&json).unwrap(), &options).unwrap();
+
+    for path in &["/texts", "/mirror"] {
+        let node = fs_tree.walk(path.to_string()).unwrap();
+        if let FSEntry::Dir(entries) = &node.entry {
+            assert!(entries.borrow().is_empty());
+        } else {
+            panic!("FSNode.entry is not a FSEntry::Dir(_)")
+        }
+    }
+}
+
+#[test]
+fn local_prefix_rewrites_absolute_paths() {
+    let json = r#"
+            {
+                "x": "file:/data/x",
+                "object.txt": { "type": "file", "path": "/data/object.txt" },
+                "relative.txt": "file:data/relative.txt",
+                "raw.txt": "raw:/data/raw"
+            }"#;
+
+    let options = BuildOptions { local_prefix: Some("/mnt".to_string()), ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).unwrap();
+
+    assert_file_local_file_path!(fs_tree.walk("/x".to_string()).unwrap(), "/mnt/data/x");
+    assert_file_local_file_path!(fs_tree.walk("/object.txt".to_string()).unwrap(), "/mnt/data/object.txt");
+    assert_file_local_file_path!(fs_tree.walk("/relative.txt".to_string()).unwrap(), "data/relative.txt");
+    assert_file_raw_data!(fs_tree.walk("/raw.txt".to_string()).unwrap(), "/data/raw");
+}
+
+#[test]
+fn local_prefix_applies_once_to_mirrors() {
+    let dir = mirror_fixture();
+    let json = r#"{ "mirror": "mirror:/nested" }"#;
+
+    let options = BuildOptions { local_prefix: Some(dir.path().to_str().unwrap().to_string()), ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(json).unwrap(), &options).unwrap();
+
+    let b_path = dir.path().join("nested").join("b.txt");
+    assert_file_local_file_path!(fs_tree.walk("/mirror/b.txt".to_string()).unwrap(), b_path.to_str().unwrap());
+}
+
+#[test]
+fn jsontree_expands_nested_document() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = dir.path().join("data.json");
+    std::fs::write(&document, r#"
+            {
+                "type": "inventory",
+                "shelves": [
+                    { "items": [ { "name": "hammer", "count": 3 } ] },
+                    { "items": [] }
+                ],
+                "open": true,
+                "owner": null
+            }"#).unwrap();
+
+    let json = format!(r#"{{ "tree": {{ "type": "jsontree", "source": "{}" }} }}"#, document.display());
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(&json).unwrap()).unwrap();
+
+    assert_file_raw_data!(fs_tree.walk("/tree/type".to_string()).unwrap(), "inventory");
+    assert_file_raw_data!(fs_tree.walk("/tree/shelves/0/items/0/name".to_string()).unwrap(), "hammer");
+    assert_file_raw_data!(fs_tree.walk("/tree/shelves/0/items/0/count".to_string()).unwrap(), "3");
+    assert_file_raw_data!(fs_tree.walk("/tree/open".to_string()).unwrap(), "true");
+    assert_file_raw_data!(fs_tree.walk("/tree/owner".to_string()).unwrap(), "null");
+    assert_dir_name!(fs_tree.walk("/tree/shelves/1/items".to_string()).unwrap(), "items");
+}
+
+#[test]
+fn dedup_mirrors_shares_identical_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("nested")).unwrap();
+    std::fs::write(dir.path().join("a.txt"), "same content").unwrap();
+    std::fs::write(dir.path().join("nested").join("b.txt"), "same content").unwrap();
+    std::fs::write(dir.path().join("c.txt"), "other content").unwrap();
+
+    let json = format!(r#"{{ "mirror": "mirror:{}" }}"#, dir.path().display());
+    let options = BuildOptions { dedup_mirrors: true, ..BuildOptions::default() };
+    let (fs_tree, _) = FSNode::new_with_options(serde_json::from_str(&json).unwrap(), &options).unwrap();
+
+    let shared = |path: &str| match &fs_tree.walk(path.to_string()).unwrap().entry {
+        FSEntry::File(FSFileType::Shared(shared)) => shared.inner.clone(),
+        _ => panic!("{} is not a FSEntry::File(FSFileType::Shared(_))", path)
+    };
+    assert!(std::sync::Arc::ptr_eq(&shared("/mirror/a.txt"), &shared("/mirror/nested/b.txt")));
+
+    let b = fs_tree.walk("/mirror/nested/b.txt".to_string()).unwrap();
+    assert_eq!(b.full_path(), "/mirror/nested/b.txt");
+    let c_path = dir.path().join("c.txt");
+    assert_file_local_file_path!(fs_tree.walk("/mirror/c.txt".to_string()).unwrap(), c_path.to_str().unwrap());
+}
+
+#[test]
+fn string_and_object_forms_are_equivalent() {
+    let json = r#"
+            {
+                "string.txt": "raw:abc",
+                "object.txt": { "type": "raw", "data": "abc" },
+                "string.log": "file:/var/log/app.log",
+                "object.log": { "type": "file", "path": "/var/log/app.log" }
+            }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+    let walk = |path: &str| fs_tree.walk(path.to_string()).unwrap();
+
+    match (&walk("/string.txt").entry, &walk("/object.txt").entry) {
+        (FSEntry::File(FSFileType::Raw(string)), FSEntry::File(FSFileType::Raw(object))) => assert_eq!(string.data, object.data),
+        _ => panic!("The raw files are not both FSEntry::File(FSFileType::Raw(_))")
+    }
+    match (&walk("/string.log").entry, &walk("/object.log").entry) {
+        (FSEntry::File(FSFileType::Local(string)), FSEntry::File(FSFileType::Local(object))) => assert_eq!(string, object),
+        _ => panic!("The local files are not both FSEntry::File(FSFileType::Local(_))")
+    }
+    assert_file_local_file_path!(walk("/object.log"), "/var/log/app.log");
+}
+
+#[test]
+fn foreach_expands_template_per_item() {
+    let json = r#"
+            {
+                "files": { "type": "foreach", "items": ["a", "b", "c"], "template": { "{item}.txt": "raw:{item}" } },
+                "nested": {
+                    "type": "foreach",
+                    "items": ["x", 1],
+                    "template": {
+                        "{item}": { "type": "foreach", "as": "sub", "items": ["p", "q"], "template": { "{sub}.txt": "raw:{item}-{sub}" } },
+                        "{item}-shadowed": { "type": "foreach", "items": ["inner"], "template": { "{item}": "raw:{item}" } }
+                    }
+                }
+            }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    for item in &["a", "b", "c"] {
+        assert_file_raw_data!(fs_tree.walk(format!("/files/{}.txt", item)).unwrap(), *item);
+    }
+    assert_eq!(fs_tree.walk("/files".to_string()).unwrap().count(), 4);
+    assert_file_raw_data!(fs_tree.walk("/nested/x/p.txt".to_string()).unwrap(), "x-p");
+    assert_file_raw_data!(fs_tree.walk("/nested/1/q.txt".to_string()).unwrap(), "1-q");
+    assert_file_raw_data!(fs_tree.walk("/nested/x-shadowed/inner".to_string()).unwrap(), "inner");
+
+    let colliding = r#"{ "x": { "type": "foreach", "items": ["a", "a"], "template": { "{item}": "raw:1" } } }"#;
+    assert!(FSNode::new(serde_json::from_str(colliding).unwrap()).is_err());
+    let invalid = r#"{ "x": { "type": "foreach", "items": [{}], "template": { "{item}": "raw:1" } } }"#;
+    assert!(FSNode::new(serde_json::from_str(invalid).unwrap()).is_err());
+}
+
+#[test]
+fn descriptor_errors_report_path_and_cause() {
+    let error = |json: &str| FSNode::new(serde_json::from_str(json).unwrap()).unwrap_err();
+
+    let unknown = error(r#"{ "nested": { "file.txt": "nope:abc" } }"#);
+    assert_eq!(unknown.path(), "/nested/file.txt");
+    assert_eq!(unknown.cause(), &DescriptorError::UnknownFileType("nope".to_string()));
+    assert_eq!(unknown.to_string(), "/nested/file.txt: unknown file type nope");
+
+    // An object whose type isn't a file type is a directory with an entry named `type`
+    let unknown_object = error(r#"{ "x": { "type": "nope" } }"#);
+    assert_eq!(unknown_object.path(), "/x/type");
+    assert_eq!(unknown_object.cause(), &DescriptorError::MissingColon("nope".to_string()));
+
+    let missing_colon = error(r#"{ "a": { "b": { "c": "no colon" } } }"#);
+    assert_eq!(missing_colon.path(), "/a/b/c");
+    assert_eq!(missing_colon.cause(), &DescriptorError::MissingColon("no colon".to_string()));
+
+    let invalid_node = error(r#"{ "nested": { "number": 3 } }"#);
+    assert_eq!(invalid_node, DescriptorError::InvalidNodeType { path: "/nested/number".to_string() });
+    assert_eq!(invalid_node.to_string(), "/nested/number: neither a file nor a directory descriptor");
+
+    let invalid_field = error(r#"{ "x": { "type": "raw", "data": "a", "mode": 755 } }"#);
+    assert_eq!(invalid_field.path(), "/x");
+    assert_eq!(invalid_field.cause(), &DescriptorError::InvalidField("mode".to_string()));
+
+    let invalid = error(r#"{ "x": { "type": "raw", "data": "a", "mode": "0789" } }"#);
+    assert_eq!(invalid.cause(), &DescriptorError::Invalid("invalid mode 0789".to_string()));
+
+    let root = FSNode::new(serde_json::from_str("3").unwrap()).unwrap_err();
+    assert_eq!(root.path(), "/");
+}
+
+#[test]
+fn descriptor_is_read_from_a_reader_or_a_file() {
+    let json = r#"{ "file.txt": "raw:abc", "nested": {} }"#;
+    let descriptor = read_descriptor(std::io::Cursor::new(json)).unwrap();
+    let expected: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(descriptor, expected);
+    assert!(FSNode::new(descriptor.clone()).unwrap().0.walk("/file.txt".to_string()).is_some());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("descriptor.json");
+    std::fs::write(&path, json).unwrap();
+    assert_eq!(load_descriptor(path.to_str().unwrap()).unwrap(), descriptor);
+
+    assert!(read_descriptor(std::io::Cursor::new("{ not json")).is_err());
+}
+
+#[test]
+fn arrays_are_directories_named_by_index() {
+    let json = r#"{ "items": ["raw:a", "raw:b", { "nested.txt": "raw:c" }], "empty": [] }"#;
+    let (fs_tree, _) = FSNode::new(serde_json::from_str(json).unwrap()).unwrap();
+
+    for &(path, content) in &[("/items/0", "a"), ("/items/1", "b"), ("/items/2/nested.txt", "c")] {
+        match &fs_tree.walk(path.to_string()).unwrap().entry {
+            FSEntry::File(FSFileType::Raw(raw)) => assert_eq!(*raw.data.borrow(), content.as_bytes()),
+            _ => panic!("{} is not a raw file", path)
+        }
+    }
+    assert!(fs_tree.walk("/items/3".to_string()).is_none());
+
+    match &fs_tree.walk("/empty".to_string()).unwrap().entry {
+        FSEntry::Dir(entries) => assert!(entries.borrow().is_empty()),
+        _ => panic!("/empty is not a directory")
+    }
+}
+
+#[test]
+fn single_pass_inode_map_matches_flattened_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in &["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(dir.path().join(name), "same content").unwrap();
+    }
+
+    let mut root = serde_json::Map::new();
+    for d in 0..200 {
+        let files: serde_json::Map<String, serde_json::Value> = (0..50)
+            .map(|f| (format!("file{}.txt", f), serde_json::Value::String(format!("raw:{}-{}", d, f))))
+            .collect();
+        root.insert(format!("dir{}", d), serde_json::json!({ "files": files, "list": ["raw:x", "raw:y"] }));
+    }
+    root.insert("mirror".to_string(), serde_json::Value::String(format!("mirror:{}", dir.path().display())));
+    let options = BuildOptions { dedup_mirrors: true, ..BuildOptions::default() };
+
+    let (fs_tree, inodes) = FSNode::new_with_options(serde_json::Value::Object(root), &options).unwrap();
+    let flattened = fs_tree.flatten_strong();
+    assert_eq!(inodes.len(), flattened.len());
+    assert_eq!(inodes.len(), 1 + 200 * (1 + 1 + 50 + 1 + 2) + 1 + 3);
+    for node in flattened.iter() {
+        let indexed = inodes.get(&node.inode).and_then(|n| n.upgrade()).unwrap();
+        assert!(Rc::ptr_eq(&indexed, node), "{}", node.full_path());
+    }
+}
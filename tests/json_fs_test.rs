@@ -0,0 +1,1221 @@
+extern crate json_fuse_fs;
+
+mod common;
+
+use std::ffi::OsStr;
+use std::time::Duration;
+use json_fuse_fs::*;
+use json_fuse_fs::fs::{JsonFS, OversizedRead, DEFAULT_TTL, DESCRIPTOR_FILE_NAME, VERSION_FILE_NAME, LOG_FILE_NAME, INODES_DIR_NAME, DEFAULT_NAMELEN};
+use common::{MockServer, MockResponse};
+
+fn read_all(file_type: &FSFileType) -> Vec<u8> {
+    let mut buffer = vec![0; file_type.ops().get_attributes(0).unwrap().size as usize];
+    file_type.ops().read(0, &mut buffer).unwrap();
+    buffer
+}
+
+#[test]
+fn descriptor_file_exposes_source_json() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "file.txt": "raw:abc",
+                "nested": {
+                    "nested.txt": "raw:cba"
+                }
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap();
+
+    let node = fs.fs_tree_root().walk(format!("/{}", DESCRIPTOR_FILE_NAME)).unwrap();
+    if let FSNode { entry: FSEntry::File(file_type), .. } = &*node {
+        let read_back: serde_json::Value = serde_json::from_slice(&read_all(file_type)).unwrap();
+        assert_eq!(read_back, descriptor);
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+    assert_eq!(fs.descriptor(), &descriptor);
+}
+
+#[test]
+fn permission_policy_applies_to_matching_paths() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "public.txt": "raw:abc",
+                "secrets": {
+                    "key.pem": "raw:cba",
+                    "nested": {
+                        "token": "raw:xyz"
+                    }
+                }
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap()
+        .with_permission_policy(vec![
+            (glob::Pattern::new("/secrets/*").unwrap(), 0o640),
+            (glob::Pattern::new("/secrets/*").unwrap(), 0o600)
+        ]);
+
+    let root = fs.fs_tree_root();
+    assert_eq!(fs.get_node_attr(&root.walk("/secrets/key.pem".to_string()).unwrap()).unwrap().perm, 0o600);
+    assert_eq!(fs.get_node_attr(&root.walk("/secrets/nested/token".to_string()).unwrap()).unwrap().perm, 0o600);
+    assert_eq!(fs.get_node_attr(&root.walk("/public.txt".to_string()).unwrap()).unwrap().perm, 0o644);
+}
+
+#[test]
+fn ls_directory() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "nested": {
+                    "a.txt": "raw:abc",
+                    "b": {}
+                }
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap();
+
+    let listing = fs.ls("/nested").unwrap();
+    let entries = listing.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0]["name"], "a.txt");
+    assert_eq!(entries[0]["type"], "file");
+    assert_eq!(entries[0]["size"], 3);
+    assert_eq!(entries[0]["mode"], "0644");
+
+    assert_eq!(entries[1]["name"], "b");
+    assert_eq!(entries[1]["type"], "dir");
+    assert_eq!(entries[1]["mode"], "0755");
+}
+
+#[test]
+fn ls_file() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "nested": {
+                    "a.txt": "raw:abc"
+                }
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap();
+
+    let listing = fs.ls("/nested/a.txt").unwrap();
+    let entries = listing.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["name"], "a.txt");
+
+    assert!(fs.ls("/missing").is_none());
+}
+
+#[test]
+fn reload_path_replaces_only_the_subtree() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "file.txt": "raw:abc",
+                "nested": {
+                    "nested.txt": "raw:cba"
+                }
+            }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let file_inode = fs.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    let nested_inode = fs.fs_tree_root().walk("/nested".to_string()).unwrap().inode;
+
+    fs.reload_path("/nested", serde_json::from_str(r#"{ "other.txt": "raw:xyz" }"#).unwrap()).unwrap();
+
+    let root = fs.fs_tree_root();
+    assert_eq!(root.walk("/file.txt".to_string()).unwrap().inode, file_inode);
+    assert!(root.walk("/nested/nested.txt".to_string()).is_none());
+    let nested = root.walk("/nested".to_string()).unwrap();
+    assert_ne!(nested.inode, nested_inode);
+    assert_eq!(nested.parent.borrow().upgrade().unwrap().inode, root.inode);
+    let other = root.walk("/nested/other.txt".to_string()).unwrap();
+    assert_eq!(other.full_path(), "/nested/other.txt");
+    if let FSNode { entry: FSEntry::File(file_type), .. } = &*other {
+        assert_eq!(read_all(file_type), b"xyz");
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+    assert_eq!(fs.descriptor().pointer("/nested/other.txt").unwrap(), "raw:xyz");
+}
+
+#[test]
+fn version_file_changes_with_the_descriptor() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc", "nested": { "a.txt": "raw:xyz" } }"#).unwrap();
+    let version = |fs: &mut JsonFS| {
+        let inode = fs.fs_tree_root().walk(format!("/{}", VERSION_FILE_NAME)).unwrap().inode;
+        String::from_utf8(fs.read_inode(inode, 0, 100).unwrap()).unwrap()
+    };
+
+    let mut fs = JsonFS::new(descriptor.clone()).unwrap();
+    let built = version(&mut fs);
+    assert_eq!(built, format!("{}\n", JsonFS::descriptor_version(&descriptor)));
+    assert_eq!(built.len(), 65);
+    assert_eq!(version(&mut JsonFS::new(descriptor).unwrap()), built);
+
+    fs.reload_path("/nested", serde_json::from_str(r#"{ "a.txt": "raw:xyz" }"#).unwrap()).unwrap();
+    assert_eq!(version(&mut fs), built);
+
+    fs.reload_path("/nested", serde_json::from_str(r#"{ "b.txt": "raw:xyz" }"#).unwrap()).unwrap();
+    let reloaded = version(&mut fs);
+    assert_ne!(reloaded, built);
+    assert_eq!(reloaded, format!("{}\n", JsonFS::descriptor_version(fs.descriptor())));
+}
+
+#[test]
+fn reload_path_rejects_unknown_paths() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc" }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+
+    assert!(fs.reload_path("/", serde_json::from_str(r#"{}"#).unwrap()).is_err());
+    assert!(fs.reload_path("/missing", serde_json::from_str(r#"{}"#).unwrap()).is_err());
+}
+
+#[test]
+fn statfs_reports_totals_of_the_tree() {
+    let content = "x".repeat(1000);
+    let descriptor: serde_json::Value = serde_json::from_str(&format!(r#"{{ "a.txt": "raw:{}", "nested": {{ "b.txt": "raw:abc" }} }}"#, content)).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap();
+    let statfs = fs.statfs_info();
+
+    let nodes = fs.fs_tree_root().flatten_strong();
+    assert_eq!(statfs.files, nodes.len() as u64);
+    let size: u64 = nodes.iter().filter_map(|n| match &n.entry {
+        FSEntry::File(file_type) => Some(file_type.ops().size().unwrap()),
+        FSEntry::Dir(_) => None
+    }).sum();
+    assert_eq!(statfs.blocks, (size + 511) / 512);
+    assert!(statfs.blocks >= 3);
+    assert_eq!(statfs.bsize, 512);
+    assert_eq!((statfs.bfree, statfs.bavail, statfs.ffree), (0, 0, 0));
+}
+
+#[test]
+fn statfs_reports_configured_namelen() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc" }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap();
+    assert_eq!(fs.statfs_info().namelen, DEFAULT_NAMELEN);
+
+    let fs = JsonFS::new(descriptor).unwrap()
+        .with_namelen(128);
+    assert_eq!(fs.statfs_info().namelen, 128);
+}
+
+#[test]
+fn log_file_serves_recent_log_lines() {
+    let buffer = json_fuse_fs::log_buffer::init(100).unwrap();
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc" }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap().with_log(buffer);
+    fs.reload_path("/file.txt", serde_json::Value::String("raw:cba".to_string())).unwrap();
+
+    let node = fs.fs_tree_root().walk(format!("/{}", LOG_FILE_NAME)).unwrap();
+    if let FSNode { entry: FSEntry::File(file_type), .. } = &*node {
+        let log = String::from_utf8(read_all(file_type)).unwrap();
+        assert!(log.contains("Reloaded subtree /file.txt"));
+    } else {
+        panic!("FSNode.entry is not a FSEntry::File(_)")
+    }
+}
+
+#[test]
+fn log_ring_buffer_keeps_last_lines() {
+    let buffer = json_fuse_fs::log_buffer::LogRingBuffer::new(2);
+    buffer.push("first".to_string());
+    buffer.push("second".to_string());
+    buffer.push("third".to_string());
+
+    assert_eq!(buffer.contents(), b"second\nthird\n");
+}
+
+#[test]
+fn inodes_dir_describes_nodes_by_inode() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "nested": { "file.txt": "raw:abc" }, ".inodes": "raw:shadowed" }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap().with_inodes_dir();
+    let root = fs.fs_tree_root().inode;
+
+    let inodes_dir = fs.lookup_entry(root, OsStr::new(INODES_DIR_NAME)).unwrap();
+    assert_eq!(inodes_dir.kind, fuse::FileType::Directory);
+    let nested = fs.fs_tree_root().walk("/nested".to_string()).unwrap().inode.to_string();
+
+    let info = fs.lookup_entry(inodes_dir.ino, OsStr::new(&nested)).unwrap();
+    let content = String::from_utf8(fs.read_inode(info.ino, 0, info.size as u32).unwrap()).unwrap();
+    assert!(content.starts_with(&format!("inode: {}\npath: /nested\n", nested)), "{}", content);
+
+    // Lookups of the same inode serve the same file
+    assert_eq!(fs.lookup_entry(inodes_dir.ino, OsStr::new(&nested)).unwrap().ino, info.ino);
+
+    for missing in &["424242", "nested", "-1"] {
+        assert_eq!(fs.lookup_entry(inodes_dir.ino, OsStr::new(missing)).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    }
+}
+
+#[test]
+fn oversized_reads_are_rejected_or_clamped() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:0123456789" }"#).unwrap();
+
+    let mut rejecting = JsonFS::new(descriptor.clone()).unwrap().with_max_read_bytes(4, OversizedRead::Reject);
+    let inode = rejecting.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    assert_eq!(rejecting.read_inode(inode, 0, 5).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+    assert_eq!(rejecting.read_inode(inode, 2, 4).unwrap(), b"2345");
+
+    let mut clamping = JsonFS::new(descriptor).unwrap().with_max_read_bytes(4, OversizedRead::Clamp);
+    let inode = clamping.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    assert_eq!(clamping.read_inode(inode, 3, 10).unwrap(), b"3456");
+}
+
+#[test]
+fn symlink_resolves_to_its_target() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "link": "symlink:/etc/hostname", "file.txt": "raw:abc" }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let root = fs.fs_tree_root().inode;
+
+    let link = fs.lookup_entry(root, OsStr::new("link")).unwrap();
+    assert_eq!(link.kind, fuse::FileType::Symlink);
+    assert_eq!(link.size, "/etc/hostname".len() as u64);
+    assert_eq!(fs.read_link(link.ino).unwrap(), "/etc/hostname");
+
+    let file = fs.lookup_entry(root, OsStr::new("file.txt")).unwrap();
+    assert_eq!(fs.read_link(file.ino).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+
+    let entries = fs.ls("/").unwrap();
+    let listed = entries.as_array().unwrap().iter().find(|e| e["name"] == "link").unwrap();
+    assert_eq!(listed["type"], "symlink");
+}
+
+#[test]
+fn attrof_serves_target_attributes_as_json() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "data": { "x": { "type": "raw", "data": "abcdef", "mode": "0600" } },
+                "x.attr": { "type": "attrof", "path": "/data/x" },
+                "missing.attr": { "type": "attrof", "path": "/data/missing" }
+            }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    let (target, attr_file) = (inode(&fs, "/data/x"), inode(&fs, "/x.attr"));
+    let size = fs.inode_attr(attr_file).unwrap().size;
+    let content = fs.read_inode(attr_file, 0, size as u32).unwrap();
+    assert_eq!(content.len() as u64, size);
+
+    let attributes: serde_json::Value = serde_json::from_str(&String::from_utf8(content).unwrap()).unwrap();
+    assert_eq!(attributes["ino"], target);
+    assert_eq!(attributes["size"], 6);
+    assert_eq!(attributes["kind"], "file");
+    assert_eq!(attributes["perm"], "0600");
+    assert!(attributes["mtime"].is_u64());
+
+    let missing = inode(&fs, "/missing.attr");
+    assert_eq!(fs.inode_attr(missing).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.read_inode(missing, 0, 10).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn catdir_concatenates_files_across_boundaries() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "logs": { "a.log": "raw:abc", "b.log": "raw:defgh", "c.log": "raw:ij" },
+                "all.log": { "type": "catdir", "path": "/logs" },
+                "missing.log": { "type": "catdir", "path": "/missing" }
+            }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    let all = inode(&fs, "/all.log");
+    assert_eq!(fs.inode_attr(all).unwrap().size, 10);
+    assert_eq!(fs.read_inode(all, 0, 10).unwrap(), b"abcdefghij".to_vec());
+    assert_eq!(fs.read_inode(all, 2, 5).unwrap(), b"cdefg".to_vec());
+    assert_eq!(fs.read_inode(all, 7, 100).unwrap(), b"hij".to_vec());
+    assert_eq!(fs.read_inode(all, 3, 5).unwrap(), b"defgh".to_vec());
+    assert!(fs.read_inode(all, 10, 4).unwrap().is_empty());
+
+    let missing = inode(&fs, "/missing.log");
+    assert_eq!(fs.read_inode(missing, 0, 10).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn access_allows_reads_and_refuses_writes_when_read_only() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "file.txt": "raw:abc",
+                "script.sh": { "type": "raw", "data": "echo", "mode": "0755" },
+                "nested": {}
+            }"#).unwrap();
+    let fs = JsonFS::new(descriptor.clone()).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+    let (file, script, nested) = (inode(&fs, "/file.txt"), inode(&fs, "/script.sh"), inode(&fs, "/nested"));
+
+    assert!(fs.check_access(file, libc::F_OK).is_ok());
+    assert!(fs.check_access(file, libc::R_OK).is_ok());
+    assert!(fs.check_access(nested, libc::R_OK | libc::X_OK).is_ok());
+    assert!(fs.check_access(script, libc::R_OK | libc::X_OK).is_ok());
+    assert_eq!(fs.check_access(file, libc::X_OK).unwrap_err().raw_os_error(), Some(libc::EACCES));
+    assert_eq!(fs.check_access(file, libc::R_OK | libc::W_OK).unwrap_err().raw_os_error(), Some(libc::EACCES));
+    assert_eq!(fs.check_access(424_242, libc::R_OK).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+
+    let fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap();
+    assert!(fs.check_access(inode(&fs, "/file.txt"), libc::W_OK).is_ok());
+    // Entries can be created in the directories
+    assert!(fs.check_access(inode(&fs, "/nested"), libc::W_OK).is_ok());
+}
+
+#[test]
+fn open_handles_serve_the_content_read_when_opened() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abcdefghij", "nested": {} }"#).unwrap();
+    let mut fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap();
+    let file = fs.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+
+    let fh = fs.open_inode(file).unwrap();
+    assert_eq!(fs.open_handles(), 1);
+    assert_eq!(fs.read_handle(file, fh, 0, 4).unwrap(), b"abcd".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 4, 4).unwrap(), b"efgh".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 8, 4).unwrap(), b"ij".to_vec());
+    assert!(fs.read_handle(file, fh, 12, 4).unwrap().is_empty());
+
+    // A write is seen by the handles of the file
+    fs.write_inode(file, 0, b"ABCD").unwrap();
+    assert_eq!(fs.read_handle(file, fh, 2, 4).unwrap(), b"CDef".to_vec());
+
+    assert!(fs.release_handle(fh));
+    assert!(!fs.release_handle(fh));
+    assert_eq!(fs.open_handles(), 0);
+    // Without a handle, the file is read again
+    assert_eq!(fs.read_handle(file, fh, 0, 4).unwrap(), b"ABCD".to_vec());
+
+    let nested = fs.fs_tree_root().walk("/nested".to_string()).unwrap().inode;
+    assert_eq!(fs.open_inode(nested).unwrap_err().raw_os_error(), Some(libc::EISDIR));
+    assert_eq!(fs.open_inode(424_242).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn open_handles_of_wrapped_files_read_through_the_wrappers() {
+    let descriptor = serde_json::json!({ "metered.txt": { "type": "raw", "data": "0123456789", "quota": 25 } });
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let file = fs.fs_tree_root().walk("/metered.txt".to_string()).unwrap().inode;
+
+    // Opening doesn't charge the quota, each read through the handle does
+    let fh = fs.open_inode(file).unwrap();
+    assert_eq!(fs.read_handle(file, fh, 0, 4).unwrap(), b"0123".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 4, 6).unwrap(), b"456789".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 0, 10).unwrap(), b"0123456789".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 0, 10).unwrap_err().raw_os_error(), Some(libc::EDQUOT));
+    let fh = fs.open_inode(file).unwrap();
+    assert_eq!(fs.read_handle(file, fh, 0, 5).unwrap(), b"01234".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 5, 1).unwrap_err().raw_os_error(), Some(libc::EDQUOT));
+}
+
+#[test]
+fn open_handles_read_up_to_the_size_when_opened() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("growing.txt");
+    std::fs::write(&path, "abc").unwrap();
+    let descriptor = serde_json::json!({ "growing.txt": { "type": "local", "path": path.to_str().unwrap() } });
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let file = fs.fs_tree_root().walk("/growing.txt".to_string()).unwrap().inode;
+
+    // The handle doesn't look the size up again on each read
+    let fh = fs.open_inode(file).unwrap();
+    std::fs::write(&path, "abcdef").unwrap();
+    assert_eq!(fs.read_handle(file, fh, 0, 10).unwrap(), b"abc".to_vec());
+    assert_eq!(fs.read_inode(file, 0, 10).unwrap(), b"abcdef".to_vec());
+    let fh = fs.open_inode(file).unwrap();
+    assert_eq!(fs.read_handle(file, fh, 0, 10).unwrap(), b"abcdef".to_vec());
+}
+
+#[test]
+fn open_handles_of_wrapped_http_files_serve_the_content_read_when_opened() {
+    let server = MockServer::start(|_| MockResponse::ok(b"remote content"));
+    let descriptor = serde_json::json!({
+        "remote.txt": { "type": "http", "url": format!("{}/remote.txt", server.url), "mode": "0600", "read_delay_ms": 1, "read_timeout_ms": 5000 }
+    });
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let file = fs.fs_tree_root().walk("/remote.txt".to_string()).unwrap().inode;
+
+    let fh = fs.open_inode(file).unwrap();
+    let requests = server.requests().len();
+    assert_eq!(fs.read_handle(file, fh, 0, 6).unwrap(), b"remote".to_vec());
+    assert_eq!(fs.read_handle(file, fh, 7, 7).unwrap(), b"content".to_vec());
+    assert_eq!(server.requests().len(), requests);
+}
+
+#[test]
+fn refs_serve_the_content_of_their_target() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "blobs": { "large.txt": { "type": "raw", "data": "shared content", "mode": "0600" } },
+                "a.txt": "ref:/blobs/large.txt",
+                "nested": { "b.txt": "ref:/blobs/large.txt", "c.txt": "ref:/a.txt" }
+            }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    for path in &["/a.txt", "/nested/b.txt", "/nested/c.txt"] {
+        let file = inode(&fs, path);
+        let attr = fs.inode_attr(file).unwrap();
+        assert_eq!((attr.ino, attr.size, attr.perm), (file, 14, 0o600), "{}", path);
+        assert_eq!(fs.read_inode(file, 0, 100).unwrap(), b"shared content".to_vec(), "{}", path);
+        assert_eq!(fs.read_inode(file, 7, 4).unwrap(), b"cont".to_vec(), "{}", path);
+    }
+
+    let error = |json: &str| JsonFS::new(serde_json::from_str(json).unwrap()).err().unwrap();
+    let missing = error(r#"{ "x": "ref:/missing" }"#);
+    assert_eq!(missing.path(), "/x");
+    assert_eq!(missing.cause(), &DescriptorError::invalid("the ref target /missing doesn't exist"));
+    let cycle = error(r#"{ "x": "ref:/y", "y": "ref:/z", "z": "ref:/x" }"#);
+    assert!(cycle.to_string().contains("cycle"), "{}", cycle);
+    let dir = error(r#"{ "x": "ref:/nested", "nested": {} }"#);
+    assert_eq!(dir.cause(), &DescriptorError::invalid("the ref target /nested is a directory"));
+    assert!(JsonFS::new(serde_json::from_str(r#"{ "x": "ref:relative" }"#).unwrap()).is_err());
+}
+
+#[test]
+fn unknown_inodes_are_enoent() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc" }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let unknown = 424_242;
+
+    assert_eq!(fs.inode_attr(unknown).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.read_inode(unknown, 0, 3).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.lookup_entry(unknown, OsStr::new("file.txt")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.xattrs(unknown).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+
+    // The file system is still usable afterwards
+    let root = fs.fs_tree_root().inode;
+    let attr = fs.lookup_entry(root, OsStr::new("file.txt")).unwrap();
+    assert_eq!(fs.read_inode(attr.ino, 0, 3).unwrap(), b"abc");
+}
+
+#[test]
+fn deleted_local_file_is_pruned_after_repeated_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    std::fs::write(&file, "abc").unwrap();
+
+    let descriptor: serde_json::Value = serde_json::from_str(&format!(r#"{{ "file.txt": "file:{}" }}"#, file.display())).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap().with_prune_after(2);
+    let inode = fs.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    assert_eq!(fs.read_inode(inode, 0, 3).unwrap(), b"abc");
+
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(fs.read_inode(inode, 0, 3).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert!(fs.fs_tree_root().walk("/file.txt".to_string()).is_some());
+    assert_eq!(fs.inode_attr(inode).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert!(fs.fs_tree_root().walk("/file.txt".to_string()).is_none());
+    assert_eq!(fs.ls("/").unwrap().as_array().unwrap().iter().filter(|e| e["name"] == "file.txt").count(), 0);
+}
+
+#[test]
+fn directories_report_configured_mode() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "file.txt": "raw:abc",
+                "private": {
+                    "nested": {}
+                },
+                "public": {}
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap()
+        .with_default_dir_mode(0o700)
+        .with_dir_permission_policy(vec![(glob::Pattern::new("/public").unwrap(), 0o755)]);
+
+    let root = fs.fs_tree_root();
+    assert_eq!(fs.get_node_attr(root).unwrap().perm, 0o700);
+    assert_eq!(fs.get_node_attr(&root.walk("/private/nested".to_string()).unwrap()).unwrap().perm, 0o700);
+    assert_eq!(fs.get_node_attr(&root.walk("/public".to_string()).unwrap()).unwrap().perm, 0o755);
+    assert_eq!(fs.get_node_attr(&root.walk("/file.txt".to_string()).unwrap()).unwrap().perm, 0o644);
+}
+
+#[test]
+fn mount_options_nonempty() {
+    use std::ffi::OsStr;
+    use json_fuse_fs::fs::mount_options;
+
+    assert!(!mount_options(false).contains(&OsStr::new("nonempty")));
+    assert!(mount_options(true).contains(&OsStr::new("nonempty")));
+    assert!(mount_options(true).contains(&OsStr::new("ro")));
+}
+
+#[test]
+fn validate_mountpoint_requires_directory() {
+    use json_fuse_fs::fs::validate_mountpoint;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    std::fs::write(&file, "abc").unwrap();
+
+    assert!(validate_mountpoint(dir.path()).is_ok());
+    assert_eq!(validate_mountpoint(&file).unwrap_err().raw_os_error(), Some(libc::ENOTDIR));
+    assert!(validate_mountpoint(&dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn recursive_dir_size_sums_nested_files() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "file.txt": "raw:abc",
+                "nested": {
+                    "a.txt": "raw:12345",
+                    "deeper": {
+                        "b.txt": "raw:1234567"
+                    }
+                }
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap().with_recursive_dir_size(true);
+
+    let root = fs.fs_tree_root();
+    assert_eq!(fs.get_node_attr(&root.walk("/nested".to_string()).unwrap()).unwrap().size, 12);
+    assert_eq!(fs.get_node_attr(&root.walk("/nested/deeper".to_string()).unwrap()).unwrap().size, 7);
+
+    let fs = JsonFS::new(serde_json::from_str(r#"{ "nested": { "a.txt": "raw:12345" } }"#).unwrap()).unwrap();
+    assert_eq!(fs.get_node_attr(&fs.fs_tree_root().walk("/nested".to_string()).unwrap()).unwrap().size, 0);
+}
+
+#[test]
+fn read_zero_fills_up_to_declared_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file.bin");
+    std::fs::write(&file, "abc").unwrap();
+
+    let descriptor: serde_json::Value = serde_json::from_str(&format!(r#"
+            {{
+                "padded.bin": {{ "type": "file", "path": "{}", "size": 8 }}
+            }}"#, file.display())).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let inode = fs.fs_tree_root().walk("/padded.bin".to_string()).unwrap().inode;
+
+    assert_eq!(fs.read_inode(inode, 0, 4096).unwrap(), b"abc\0\0\0\0\0");
+    assert_eq!(fs.read_inode(inode, 5, 4096).unwrap(), b"\0\0\0");
+    assert_eq!(fs.read_inode(inode, 8, 4096).unwrap(), b"");
+}
+
+#[test]
+fn on_read_callback_receives_events() {
+    use std::sync::{Arc, Mutex};
+    use json_fuse_fs::fs::ReadEvent;
+
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abcdef" }"#).unwrap();
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let inode = fs.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+
+    let events: Arc<Mutex<Vec<ReadEvent>>> = Arc::new(Mutex::new(vec![]));
+    let recorded = events.clone();
+    fs.on_read(Box::new(move |event| recorded.lock().unwrap().push(event)));
+
+    assert_eq!(fs.read_inode(inode, 2, 3).unwrap(), b"cde");
+    assert!(fs.read_inode(fs.fs_tree_root().inode, 0, 3).is_err());
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!((events[0].inode, events[0].offset, events[0].size, events[0].bytes, events[0].error), (inode, 2, 3, 3, None));
+    assert_eq!(events[1].error, Some(libc::EISDIR));
+}
+
+#[test]
+fn lazyfs_directory_is_fetched_on_first_lookup() {
+    let server = MockServer::start(|_| MockResponse::ok(br#"{ "inner.txt": "raw:lazy", "deeper": { "leaf.txt": "raw:leaf" } }"#));
+    let descriptor: serde_json::Value = serde_json::from_str(&format!(r#"
+            {{
+                "sub": {{ "type": "lazyfs", "source": "{}/sub.json" }},
+                "missing": {{ "type": "lazyfs", "source": "file:/nonexistent/sub.json" }}
+            }}"#, server.url)).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    assert!(server.requests().is_empty());
+
+    let sub = fs.fs_tree_root().walk("/sub".to_string()).unwrap().inode;
+    let inner = fs.lookup_entry(sub, OsStr::new("inner.txt")).unwrap();
+    assert_eq!(fs.read_inode(inner.ino, 0, 4).unwrap(), b"lazy");
+    let requests = server.requests().len();
+    assert!(requests > 0);
+
+    let deeper = fs.lookup_entry(sub, OsStr::new("deeper")).unwrap();
+    assert_eq!(deeper.kind, fuse::FileType::Directory);
+    assert!(fs.lookup_entry(deeper.ino, OsStr::new("leaf.txt")).is_ok());
+    assert_eq!(fs.lookup_entry(sub, OsStr::new("other.txt")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(server.requests().len(), requests);
+
+    let missing = fs.fs_tree_root().walk("/missing".to_string()).unwrap().inode;
+    assert_eq!(fs.lookup_entry(missing, OsStr::new("inner.txt")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn xattrs_merge_declared_and_computed() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "labeled.json": { "type": "raw", "data": "{}", "xattrs": { "security.selinux": "system_u:object_r:etc_t:s0" } },
+                "overridden.txt": { "type": "raw", "data": "abc", "xattrs": { "user.mime_type": "text/x-custom" } },
+                "nested": {}
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor).unwrap();
+    let inode = |path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    let labeled = fs.xattrs(inode("/labeled.json")).unwrap();
+    assert_eq!(labeled.get("security.selinux").unwrap(), b"system_u:object_r:etc_t:s0");
+    assert_eq!(labeled.get("user.mime_type").unwrap(), b"application/json");
+
+    let overridden = fs.xattrs(inode("/overridden.txt")).unwrap();
+    assert_eq!(overridden.get("user.mime_type").unwrap(), b"text/x-custom");
+
+    assert!(fs.xattrs(inode("/nested")).unwrap().is_empty());
+}
+
+#[test]
+fn descriptor_metadata_applies_to_normalized_names() {
+    let descriptor = serde_json::json!({
+        "README.md": { "type": "raw", "data": "abc", "xattrs": { "user.origin": "upstream" }, "ttl_ms": 10, "also": ["Notes.md"] },
+        "Docs": { "Guide.md": { "type": "raw", "data": "guide", "xattrs": { "user.origin": "docs" } } }
+    });
+    let options = BuildOptions { normalize_names: true, ..BuildOptions::default() };
+    let mut fs = JsonFS::new_with_options(descriptor, &options).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    let (readme, guide) = (inode(&fs, "/readme.md"), inode(&fs, "/docs/guide.md"));
+    assert_eq!(fs.xattrs(readme).unwrap().get("user.origin").unwrap(), b"upstream");
+    assert_eq!(fs.xattrs(guide).unwrap().get("user.origin").unwrap(), b"docs");
+    assert_eq!(fs.lookup_entry(inode(&fs, "/"), OsStr::new("Notes.md")).unwrap().ino, readme);
+    assert_eq!(fs.ttl(readme), Duration::from_millis(10));
+
+    fs.reload_path("/docs/guide.md", serde_json::json!("raw:reloaded")).unwrap();
+    assert_eq!(fs.read_inode(inode(&fs, "/docs/guide.md"), 0, 20).unwrap(), b"reloaded".to_vec());
+}
+
+#[test]
+fn mime_type_reports_charset_of_text_files() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "notes.txt": "raw:abc",
+                "data.json": "raw:{}"
+            }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+    assert_eq!(fs.xattrs(inode(&fs, "/notes.txt")).unwrap().get("user.mime_type").unwrap(), b"text/plain; charset=utf-8");
+    assert_eq!(fs.xattrs(inode(&fs, "/data.json")).unwrap().get("user.mime_type").unwrap(), b"application/json");
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap().with_charset(Some("iso-8859-1".to_string()));
+    assert_eq!(fs.xattrs(inode(&fs, "/notes.txt")).unwrap().get("user.mime_type").unwrap(), b"text/plain; charset=iso-8859-1");
+
+    let fs = JsonFS::new(descriptor).unwrap().with_charset(None);
+    assert_eq!(fs.xattrs(inode(&fs, "/notes.txt")).unwrap().get("user.mime_type").unwrap(), b"text/plain");
+}
+
+#[test]
+fn xattrs_must_be_strings() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "labeled.txt": { "type": "raw", "data": "abc", "xattrs": { "user.count": 1 } }
+            }"#).unwrap();
+
+    assert!(JsonFS::new(descriptor).is_err());
+}
+
+#[test]
+fn ttl_ms_overrides_attribute_ttl() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "static.txt": { "type": "raw", "data": "abc", "ttl_ms": 60000 },
+                "live.txt": { "type": "raw", "data": "abc", "ttl_ms": 0 },
+                "default.txt": "raw:abc"
+            }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let root = fs.fs_tree_root().inode;
+
+    let static_attr = fs.lookup_entry(root, OsStr::new("static.txt")).unwrap();
+    assert_eq!(fs.ttl(static_attr.ino), Duration::from_secs(60));
+    let live_attr = fs.lookup_entry(root, OsStr::new("live.txt")).unwrap();
+    assert_eq!(fs.ttl(live_attr.ino), Duration::from_secs(0));
+    let default_attr = fs.lookup_entry(root, OsStr::new("default.txt")).unwrap();
+    assert_eq!(fs.ttl(default_attr.ino), Duration::from_secs(1));
+
+    let invalid: serde_json::Value = serde_json::from_str(r#"{ "x": { "type": "raw", "data": "abc", "ttl_ms": "long" } }"#).unwrap();
+    assert!(JsonFS::new(invalid).is_err());
+}
+
+#[test]
+fn configured_ttl_applies_without_ttl_ms() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "live.txt": { "type": "raw", "data": "abc", "ttl_ms": 0 },
+                "static.txt": "raw:abc",
+                "nested": {}
+            }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor.clone()).unwrap();
+    let root = fs.fs_tree_root().inode;
+    assert_eq!(fs.ttl(root), DEFAULT_TTL);
+
+    let mut fs = JsonFS::new(descriptor).unwrap().with_ttl(Duration::from_secs(3600));
+    let static_attr = fs.lookup_entry(root, OsStr::new("static.txt")).unwrap();
+    assert_eq!(fs.ttl(static_attr.ino), Duration::from_secs(3600));
+    let nested_attr = fs.lookup_entry(root, OsStr::new("nested")).unwrap();
+    assert_eq!(fs.ttl(nested_attr.ino), Duration::from_secs(3600));
+    assert_eq!(fs.ttl(root), Duration::from_secs(3600));
+
+    // `ttl_ms` still overrides it
+    let live_attr = fs.lookup_entry(root, OsStr::new("live.txt")).unwrap();
+    assert_eq!(fs.ttl(live_attr.ino), Duration::from_secs(0));
+}
+
+#[test]
+fn configured_owner_overrides_process_owner() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:abc", "nested": {} }"#).unwrap();
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap();
+    let root = fs.fs_tree_root();
+    let file_attr = fs.get_node_attr(&root.walk("/file.txt".to_string()).unwrap()).unwrap();
+    assert_eq!((file_attr.uid, file_attr.gid), (u32::from(nix::unistd::getuid()), u32::from(nix::unistd::getgid())));
+
+    let fs = JsonFS::new(descriptor.clone()).unwrap().with_uid(4242);
+    let root = fs.fs_tree_root();
+    let file_attr = fs.get_node_attr(&root.walk("/file.txt".to_string()).unwrap()).unwrap();
+    assert_eq!((file_attr.uid, file_attr.gid), (4242, u32::from(nix::unistd::getgid())));
+
+    let fs = JsonFS::new(descriptor).unwrap().with_uid(4242).with_gid(4343);
+    let root = fs.fs_tree_root();
+    for path in &["/file.txt", "/nested", "/"] {
+        let attr = fs.get_node_attr(&root.walk(path.to_string()).unwrap()).unwrap();
+        assert_eq!((attr.uid, attr.gid), (4242, 4343), "{}", path);
+    }
+}
+
+#[test]
+fn aliases_share_inode_and_content() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "x": { "type": "raw", "data": "shared", "also": ["y", "/nested/z", "taken"] },
+                "taken": "raw:other",
+                "nested": {}
+            }"#).unwrap();
+
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let root = fs.fs_tree_root().inode;
+    let nested = fs.fs_tree_root().walk("/nested".to_string()).unwrap().inode;
+
+    let x = fs.lookup_entry(root, OsStr::new("x")).unwrap().ino;
+    assert_eq!(fs.lookup_entry(root, OsStr::new("y")).unwrap().ino, x);
+    assert_eq!(fs.lookup_entry(nested, OsStr::new("z")).unwrap().ino, x);
+    assert_ne!(fs.lookup_entry(root, OsStr::new("taken")).unwrap().ino, x);
+    assert_eq!(fs.read_inode(x, 0, 6).unwrap(), b"shared");
+}
+
+#[test]
+fn aliases_must_be_strings() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "x": { "type": "raw", "data": "shared", "also": "y" } }"#).unwrap();
+
+    assert!(JsonFS::new(descriptor).is_err());
+}
+
+#[test]
+fn writable_raw_files() {
+    use json_fuse_fs::fs::writable_mount_options;
+
+    let json = r#"{ "file.txt": "raw:hello world", "local.txt": "file:/etc/hostname", "dir": {} }"#;
+
+    let mut read_only = JsonFS::new(serde_json::from_str(json).unwrap()).unwrap();
+    let inode = read_only.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    assert_eq!(read_only.write_inode(inode, 0, b"j").unwrap_err().raw_os_error(), Some(libc::EROFS));
+
+    let mut fs = JsonFS::new_writable(serde_json::from_str(json).unwrap(), &BuildOptions::default()).unwrap();
+    assert_eq!(fs.write_inode(inode, 0, b"J").unwrap(), 1);
+    assert_eq!(fs.write_inode(inode, 6, b"there, world").unwrap(), 12);
+    assert_eq!(fs.read_inode(inode, 0, 100).unwrap(), b"Jello there, world");
+    assert_eq!(fs.inode_attr(inode).unwrap().size, 18);
+
+    // Writing past the end fills the gap with zeros
+    assert_eq!(fs.write_inode(inode, 20, b"!").unwrap(), 1);
+    assert_eq!(fs.read_inode(inode, 16, 100).unwrap(), b"ld\0\0!");
+    // But not arbitrarily far
+    assert_eq!(fs.write_inode(inode, 1 << 40, b"!").unwrap_err().raw_os_error(), Some(libc::EFBIG));
+    assert_eq!(fs.write_inode(inode, i64::max_value(), b"!").unwrap_err().raw_os_error(), Some(libc::EFBIG));
+    assert_eq!(fs.set_inode_size(inode, 1 << 40).unwrap_err().raw_os_error(), Some(libc::EFBIG));
+
+    // Truncating, as on an `O_TRUNC` open, shrinks the file and updates its modification time
+    let written = fs.inode_attr(inode).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(fs.set_inode_size(inode, 5).unwrap().size, 5);
+    assert_eq!(fs.read_inode(inode, 0, 100).unwrap(), b"Jello");
+    let truncated = fs.inode_attr(inode).unwrap();
+    assert!(truncated.mtime > written.mtime);
+    assert_eq!(truncated.crtime, written.crtime);
+    assert_eq!(fs.set_inode_size(inode, 0).unwrap().size, 0);
+    assert_eq!(fs.write_inode(inode, 0, b"x").unwrap(), 1);
+    assert_eq!(fs.read_inode(inode, 0, 100).unwrap(), b"x");
+    assert_eq!(read_only.set_inode_size(inode, 0).unwrap_err().raw_os_error(), Some(libc::EROFS));
+
+    let local = fs.fs_tree_root().walk("/local.txt".to_string()).unwrap().inode;
+    assert_eq!(fs.write_inode(local, 0, b"x").unwrap_err().raw_os_error(), Some(libc::EACCES));
+    let dir = fs.fs_tree_root().walk("/dir".to_string()).unwrap().inode;
+    assert_eq!(fs.write_inode(dir, 0, b"x").unwrap_err().raw_os_error(), Some(libc::EISDIR));
+
+    assert!(!writable_mount_options(false).contains(&OsStr::new("ro")));
+}
+
+#[test]
+fn list_dir_resumes_from_the_returned_offset() {
+    let files: serde_json::Map<String, serde_json::Value> = (0..100)
+        .map(|i| (format!("file{:03}.txt", i), serde_json::Value::String(format!("raw:{}", i))))
+        .collect();
+    let descriptor = serde_json::json!({ "big": files });
+    let mut fs = JsonFS::new(descriptor).unwrap();
+    let dir = fs.fs_tree_root().walk("/big".to_string()).unwrap().inode;
+
+    // A reply buffer with room for `capacity` entries, remembering where to resume
+    let read = |fs: &mut JsonFS, offset: i64, capacity: usize| {
+        let mut names = vec![];
+        let mut next = offset;
+        fs.list_dir(dir, offset, |_, next_offset, _, name| {
+            if names.len() == capacity {
+                return true;
+            }
+            names.push(name.to_string_lossy().into_owned());
+            next = next_offset;
+            false
+        }).unwrap();
+        (names, next)
+    };
+
+    let (first, next) = read(&mut fs, 0, 40);
+    assert_eq!(first.len(), 40);
+    assert_eq!(next, 40);
+    let (second, last) = read(&mut fs, next, 1000);
+    assert_eq!(second.len(), 62);
+    assert_eq!(last, 102);
+
+    let mut all = first;
+    all.extend(second);
+    let mut expected = vec![".".to_string(), "..".to_string()];
+    expected.extend((0..100).map(|i| format!("file{:03}.txt", i)));
+    assert_eq!(all, expected);
+
+    assert!(read(&mut fs, last, 1000).0.is_empty());
+    assert_eq!(fs.list_dir(424_242, 0, |_, _, _, _| false).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn readdir_sort_policies() {
+    use json_fuse_fs::fs::ReaddirSort;
+
+    let server = MockServer::start(|request| {
+        let (body, date): (&[u8], _) = match request.path.as_str() {
+            "/a.txt" => (&[b'a'; 30], "Wed, 21 Oct 2015 07:28:00 GMT"),
+            "/b.txt" => (&[b'b'; 10], "Tue, 21 Jan 2020 07:28:00 GMT"),
+            _ => (&[b'c'; 20], "Thu, 21 Oct 2010 07:28:00 GMT")
+        };
+        MockResponse::ok(body).with_header("Last-Modified", date)
+    });
+    let json = format!(r#"{{ "dir": {{ "a.txt": "{0}/a.txt", "b.txt": "{0}/b.txt", "c.txt": "{0}/c.txt" }} }}"#, server.url);
+
+    for &(policy, expected) in &[
+        ("source", ["a.txt", "b.txt", "c.txt"]),
+        ("name", ["a.txt", "b.txt", "c.txt"]),
+        ("name_desc", ["c.txt", "b.txt", "a.txt"]),
+        ("size", ["b.txt", "c.txt", "a.txt"]),
+        ("mtime", ["c.txt", "a.txt", "b.txt"])
+    ] {
+        let fs = JsonFS::new(serde_json::from_str(&json).unwrap()).unwrap()
+            .with_readdir_sort(ReaddirSort::parse(policy).unwrap());
+        let dir = fs.fs_tree_root().walk("/dir".to_string()).unwrap().inode;
+        let names: Vec<_> = fs.dir_entries(dir).unwrap().iter().map(|(_, _, name)| name.clone()).collect();
+        let mut all = vec![".", ".."];
+        all.extend(expected.iter());
+        assert_eq!(names, all, "{}", policy);
+    }
+
+    assert_eq!(ReaddirSort::parse("random"), None);
+}
+
+#[test]
+fn serialize_to_json_keeps_written_content() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "$default_type": "raw",
+                "bare.txt": "bare",
+                "nested": {
+                    "deeper": { "file.txt": "raw:abc" },
+                    "object.txt": { "type": "raw", "data": "old", "ttl_ms": 10 },
+                    "local.txt": "file:/etc/hostname"
+                },
+                "binary.bin": "raw:x"
+            }"#).unwrap();
+    let mut fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap();
+
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+    let (deeper, object, binary) = (inode(&fs, "/nested/deeper/file.txt"), inode(&fs, "/nested/object.txt"), inode(&fs, "/binary.bin"));
+    fs.write_inode(deeper, 3, b"def").unwrap();
+    fs.write_inode(object, 0, b"new").unwrap();
+    fs.write_inode(binary, 0, &[0xff, 0x00, 0xfe]).unwrap();
+
+    let expected: serde_json::Value = serde_json::from_str(r#"
+            {
+                "$default_type": "raw",
+                "bare.txt": "raw:bare",
+                "nested": {
+                    "deeper": { "file.txt": "raw:abcdef" },
+                    "object.txt": { "type": "raw", "data": "new", "ttl_ms": 10 },
+                    "local.txt": "file:/etc/hostname"
+                },
+                "binary.bin": "base64:/wD+"
+            }"#).unwrap();
+    assert_eq!(fs.serialize_to_json(), expected);
+
+    // The serialized descriptor builds the same content again
+    let mut rebuilt = JsonFS::new(fs.serialize_to_json()).unwrap();
+    let binary = inode(&rebuilt, "/binary.bin");
+    assert_eq!(rebuilt.read_inode(binary, 0, 10).unwrap(), vec![0xff, 0x00, 0xfe]);
+}
+
+#[test]
+fn serialize_to_json_keeps_the_writes_to_normalized_names() {
+    let descriptor = serde_json::json!({ "Notes.txt": "raw:old", "Docs": { "Draft.txt": "raw:draft", "Kept.txt": "raw:kept" } });
+    let options = BuildOptions { normalize_names: true, ..BuildOptions::default() };
+    let mut fs = JsonFS::new_writable(descriptor, &options).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    fs.write_inode(inode(&fs, "/notes.txt"), 0, b"new").unwrap();
+    let docs = inode(&fs, "/docs");
+    fs.unlink_entry(docs, OsStr::new("draft.txt")).unwrap();
+
+    let expected = serde_json::json!({ "Notes.txt": "raw:new", "Docs": { "Kept.txt": "raw:kept" } });
+    assert_eq!(fs.serialize_to_json(), expected);
+}
+
+#[test]
+fn checksum_siblings_serve_the_crc32_of_their_file() {
+    let descriptor = serde_json::json!({ "file.txt": "raw:123456789", "nested": { "other.txt": { "type": "raw", "data": "" } } });
+    let options = BuildOptions { checksum_siblings: true, ..BuildOptions::default() };
+    let mut fs = JsonFS::new_writable(descriptor, &options).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    let checksum = inode(&fs, "/file.txt.crc32");
+    assert_eq!(fs.read_inode(checksum, 0, 20).unwrap(), b"cbf43926\n".to_vec());
+    assert_eq!(fs.read_inode(inode(&fs, "/nested/other.txt.crc32"), 0, 20).unwrap(), b"00000000\n".to_vec());
+    assert!(fs.fs_tree_root().walk("/file.txt.crc32.crc32".to_string()).is_none());
+    assert!(fs.fs_tree_root().walk("/nested.crc32".to_string()).is_none());
+
+    // The checksum follows the writes to the file
+    fs.write_inode(inode(&fs, "/file.txt"), 0, b"abc").unwrap();
+    fs.set_inode_size(inode(&fs, "/file.txt"), 3).unwrap();
+    assert_eq!(fs.read_inode(checksum, 0, 20).unwrap(), b"352441c2\n".to_vec());
+}
+
+#[test]
+fn checksums_of_bare_strings_use_the_default_type() {
+    let descriptor = serde_json::json!({
+        "$default_type": "raw",
+        "a.txt": "123456789",
+        "b": { "type": "crc32", "of": "123456789" }
+    });
+    let options = BuildOptions { checksum_siblings: true, ..BuildOptions::default() };
+    let mut fs = JsonFS::new_with_options(descriptor, &options).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+
+    assert_eq!(fs.read_inode(inode(&fs, "/a.txt.crc32"), 0, 20).unwrap(), b"cbf43926\n".to_vec());
+    assert_eq!(fs.read_inode(inode(&fs, "/b"), 0, 20).unwrap(), b"cbf43926\n".to_vec());
+}
+
+#[test]
+fn persist_writes_the_descriptor_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("descriptor.json");
+    let descriptor: serde_json::Value = serde_json::from_str(r#"{ "file.txt": "raw:before" }"#).unwrap();
+
+    let mut fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap().with_persist_to(path.clone());
+    let inode = fs.fs_tree_root().walk("/file.txt".to_string()).unwrap().inode;
+    fs.write_inode(inode, 0, b"after!").unwrap();
+    fs.persist().unwrap();
+
+    let persisted: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(persisted, serde_json::json!({ "file.txt": "raw:after!" }));
+}
+
+#[test]
+fn mkdir_and_create_add_nodes_seen_by_lookup_and_readdir() {
+    use fuse::FileType;
+
+    let descriptor = serde_json::json!({ "file.txt": "raw:abc" });
+    let mut fs = JsonFS::new_writable(descriptor.clone(), &BuildOptions::default()).unwrap();
+    let root = fs.fs_tree_root().inode;
+
+    let dir = fs.make_dir(root, OsStr::new("new")).unwrap();
+    assert_eq!(dir.kind, FileType::Directory);
+    let (file, fh) = fs.create_file(dir.ino, OsStr::new("file")).unwrap();
+    assert_eq!(file.kind, FileType::RegularFile);
+    assert_eq!(file.size, 0);
+
+    assert_eq!(fs.lookup_entry(root, OsStr::new("new")).unwrap().ino, dir.ino);
+    assert_eq!(fs.lookup_entry(dir.ino, OsStr::new("file")).unwrap().ino, file.ino);
+    let names = |fs: &mut JsonFS, inode: u64| {
+        let mut names = vec![];
+        fs.list_dir(inode, 0, |_, _, _, name| {
+            names.push(name.to_string_lossy().into_owned());
+            false
+        }).unwrap();
+        names
+    };
+    assert!(names(&mut fs, root).contains(&"new".to_string()));
+    assert_eq!(names(&mut fs, dir.ino), vec![".", "..", "file"]);
+
+    // The parent links lead back to the root and the handle reads what's written
+    let node = fs.fs_tree_root().walk("/new/file".to_string()).unwrap();
+    assert_eq!(node.inode, file.ino);
+    assert_eq!(node.full_path(), "/new/file");
+    fs.write_inode(file.ino, 0, b"hello").unwrap();
+    assert_eq!(fs.read_handle(file.ino, fh, 0, 10).unwrap(), b"hello".to_vec());
+    assert!(fs.release_handle(fh));
+
+    // Taken names and read-only file systems are refused
+    assert_eq!(fs.make_dir(root, OsStr::new("file.txt")).unwrap_err().raw_os_error(), Some(libc::EEXIST));
+    assert_eq!(fs.create_file(dir.ino, OsStr::new("FILE")).unwrap_err().raw_os_error(), Some(libc::EEXIST));
+    assert_eq!(fs.create_file(file.ino, OsStr::new("x")).unwrap_err().raw_os_error(), Some(libc::ENOTDIR));
+    let mut read_only = JsonFS::new(descriptor).unwrap();
+    let root = read_only.fs_tree_root().inode;
+    assert_eq!(read_only.make_dir(root, OsStr::new("new")).unwrap_err().raw_os_error(), Some(libc::EROFS));
+
+    // The new nodes are written back
+    let serialized = fs.serialize_to_json();
+    assert_eq!(serialized["new"], serde_json::json!({ "file": "raw:hello" }));
+}
+
+#[test]
+fn unlink_and_rmdir_remove_nodes() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "target.txt": "raw:abc",
+                "link.txt": "ref:/target.txt",
+                "full": { "inner.txt": "raw:x" }
+            }"#).unwrap();
+    let mut fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap();
+    let root = fs.fs_tree_root().inode;
+
+    let dir = fs.make_dir(root, OsStr::new("new")).unwrap();
+    let (file, fh) = fs.create_file(dir.ino, OsStr::new("file")).unwrap();
+    fs.release_handle(fh);
+    assert_eq!(fs.remove_dir(root, OsStr::new("new")).unwrap_err().raw_os_error(), Some(libc::ENOTEMPTY));
+    assert_eq!(fs.unlink_entry(root, OsStr::new("new")).unwrap_err().raw_os_error(), Some(libc::EISDIR));
+    assert_eq!(fs.remove_dir(dir.ino, OsStr::new("file")).unwrap_err().raw_os_error(), Some(libc::ENOTDIR));
+
+    fs.unlink_entry(dir.ino, OsStr::new("file")).unwrap();
+    assert_eq!(fs.lookup_entry(dir.ino, OsStr::new("file")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.inode_attr(file.ino).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.dir_entries(dir.ino).unwrap().len(), 2);
+    fs.remove_dir(root, OsStr::new("new")).unwrap();
+    assert_eq!(fs.lookup_entry(root, OsStr::new("new")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert!(fs.dir_entries(dir.ino).is_none());
+    assert_eq!(fs.unlink_entry(root, OsStr::new("new")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+
+    // A ref to a removed file fails until the file is created again
+    let link = fs.fs_tree_root().walk("/link.txt".to_string()).unwrap().inode;
+    fs.unlink_entry(root, OsStr::new("target.txt")).unwrap();
+    assert_eq!(fs.read_inode(link, 0, 10).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    let (target, fh) = fs.create_file(root, OsStr::new("target.txt")).unwrap();
+    fs.write_inode(target.ino, 0, b"new").unwrap();
+    fs.release_handle(fh);
+    assert_eq!(fs.read_inode(link, 0, 10).unwrap(), b"new".to_vec());
+
+    // The removed entries aren't written back
+    let serialized = fs.serialize_to_json();
+    assert!(serialized.get("new").is_none());
+    assert_eq!(serialized["target.txt"], serde_json::json!("raw:new"));
+
+    let mut read_only = JsonFS::new(serde_json::json!({ "file.txt": "raw:abc" })).unwrap();
+    let root = read_only.fs_tree_root().inode;
+    assert_eq!(read_only.unlink_entry(root, OsStr::new("file.txt")).unwrap_err().raw_os_error(), Some(libc::EROFS));
+}
+
+#[test]
+fn rename_moves_nodes_keeping_their_inodes() {
+    let descriptor: serde_json::Value = serde_json::from_str(r#"
+            {
+                "a.txt": "raw:abc",
+                "src": { "moved": { "deep.txt": "raw:deep" } },
+                "dst": { "full": { "x.txt": "raw:x" }, "empty": {} }
+            }"#).unwrap();
+    let mut fs = JsonFS::new_writable(descriptor, &BuildOptions::default()).unwrap();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+    let (root, src, dst) = (inode(&fs, "/"), inode(&fs, "/src"), inode(&fs, "/dst"));
+
+    // In the same directory
+    let file = inode(&fs, "/a.txt");
+    fs.rename_entry(root, OsStr::new("a.txt"), root, OsStr::new("b.txt")).unwrap();
+    assert_eq!(fs.lookup_entry(root, OsStr::new("a.txt")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.lookup_entry(root, OsStr::new("b.txt")).unwrap().ino, file);
+    assert_eq!(fs.read_inode(file, 0, 10).unwrap(), b"abc".to_vec());
+    assert!(fs.dir_entries(root).unwrap().iter().all(|(_, _, name)| name != "a.txt"));
+
+    // Across directories, with the subtree
+    let (moved, deep) = (inode(&fs, "/src/moved"), inode(&fs, "/src/moved/deep.txt"));
+    assert_eq!(fs.rename_entry(src, OsStr::new("moved"), dst, OsStr::new("full")).unwrap_err().raw_os_error(), Some(libc::ENOTEMPTY));
+    assert_eq!(fs.rename_entry(src, OsStr::new("moved"), moved, OsStr::new("inner")).unwrap_err().raw_os_error(), Some(libc::EINVAL));
+    fs.rename_entry(src, OsStr::new("moved"), dst, OsStr::new("empty")).unwrap();
+    assert_eq!(inode(&fs, "/dst/empty"), moved);
+    assert_eq!(inode(&fs, "/dst/empty/deep.txt"), deep);
+    assert_eq!(fs.fs_tree_root().walk("/dst/empty/deep.txt".to_string()).unwrap().full_path(), "/dst/empty/deep.txt");
+    assert_eq!(fs.lookup_entry(dst, OsStr::new("empty")).unwrap().ino, moved);
+    assert_eq!(fs.dir_entries(src).unwrap().len(), 2);
+    assert!(fs.dir_entries(moved).unwrap().contains(&(dst, fuse::FileType::Directory, "..".into())));
+    assert_eq!(fs.read_inode(deep, 0, 10).unwrap(), b"deep".to_vec());
+
+    // The descriptor written back follows the moves
+    let serialized = fs.serialize_to_json();
+    assert_eq!(serialized["b.txt"], serde_json::json!("raw:abc"));
+    assert_eq!(serialized["src"], serde_json::json!({}));
+    assert_eq!(serialized["dst"]["empty"], serde_json::json!({ "deep.txt": "raw:deep" }));
+}
+
+#[test]
+fn reload_swaps_the_tree_keeping_the_inodes_of_unchanged_paths() {
+    let descriptor = serde_json::json!({
+        "kept.txt": "raw:before",
+        "dir": { "inner.txt": "raw:inner" },
+        "gone.txt": "raw:gone",
+        "kind": "raw:file"
+    });
+    let mut fs = JsonFS::new(descriptor).unwrap().with_inodes_dir();
+    let inode = |fs: &JsonFS, path: &str| fs.fs_tree_root().walk(path.to_string()).unwrap().inode;
+    let (root, kept, dir, inner, gone, kind, inodes_dir) = (inode(&fs, "/"), inode(&fs, "/kept.txt"), inode(&fs, "/dir"), inode(&fs, "/dir/inner.txt"), inode(&fs, "/gone.txt"), inode(&fs, "/kind"), inode(&fs, "/.inodes"));
+    let fh = fs.open_inode(kept).unwrap();
+
+    let reloaded = serde_json::json!({
+        "kept.txt": "raw:after",
+        "dir": { "inner.txt": "raw:inner", "added.txt": "raw:added" },
+        "kind": {},
+        "link.txt": "ref:/dir/added.txt"
+    });
+    fs.reload(reloaded.clone()).unwrap();
+    assert_eq!(fs.descriptor(), &reloaded);
+
+    // The paths still there keep their inode, and the open handles read the new content
+    assert_eq!((inode(&fs, "/"), inode(&fs, "/kept.txt"), inode(&fs, "/dir"), inode(&fs, "/dir/inner.txt")), (root, kept, dir, inner));
+    assert_eq!(fs.read_inode(kept, 0, 10).unwrap(), b"after".to_vec());
+    assert_eq!(fs.read_handle(kept, fh, 0, 10).unwrap(), b"after".to_vec());
+    assert_eq!(fs.lookup_entry(root, OsStr::new(".inodes")).unwrap().ino, inodes_dir);
+
+    // The new paths resolve, the removed ones don't
+    let added = fs.lookup_entry(dir, OsStr::new("added.txt")).unwrap();
+    assert!(added.ino > gone && added.ino != kind);
+    assert_eq!(fs.read_inode(added.ino, 0, 10).unwrap(), b"added".to_vec());
+    assert!(fs.dir_entries(dir).unwrap().iter().any(|(ino, _, name)| *ino == added.ino && name == "added.txt"));
+    let link = fs.lookup_entry(root, OsStr::new("link.txt")).unwrap();
+    assert_eq!(fs.read_inode(link.ino, 0, 10).unwrap(), b"added".to_vec());
+    assert_eq!(fs.lookup_entry(root, OsStr::new("gone.txt")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(fs.inode_attr(gone).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+
+    // A path changing kind gets a new inode
+    let kind_attr = fs.lookup_entry(root, OsStr::new("kind")).unwrap();
+    assert_eq!(kind_attr.kind, fuse::FileType::Directory);
+    assert_ne!(kind_attr.ino, kind);
+
+    // A descriptor that doesn't build leaves the tree as it was
+    assert!(fs.reload(serde_json::json!({ "link.txt": "ref:/missing.txt" })).is_err());
+    assert_eq!(fs.descriptor(), &reloaded);
+    assert_eq!(fs.lookup_entry(dir, OsStr::new("added.txt")).unwrap().ino, added.ino);
+}